@@ -0,0 +1,547 @@
+use std::collections::VecDeque;
+
+use crate::cpu::CLOCK_SPEED;
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// APU（音声処理ユニット）
+// 今のところ矩形波のチャンネル1（NR10〜NR14）だけを扱う
+
+// フレームシーケンサは8192Tサイクル（512Hz）ごとに1ステップ進む
+const FRAME_SEQUENCER_CYCLES: u16 = 8192;
+
+// 出力するサンプリング周波数
+pub const SAMPLE_RATE: u32 = 44_100;
+// 溜めておくサンプル数の上限（左右を合わせて約1秒分）
+// 取り出されないまま上限を超えたら古いものから捨てる
+const MAX_BUFFERED_SAMPLES: usize = SAMPLE_RATE as usize * 2;
+
+// デューティ比ごとの8ステップの波形（12.5%、25%、50%、75%）
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+#[derive(Default)]
+pub struct Apu {
+    channel1: PulseChannel,
+    frame_sequencer_cycles: u16,
+    // フレームシーケンサの現在のステップ（0〜7）
+    frame_sequencer_step: u8,
+    // NR50（0xFF24）: 左右のマスター音量
+    pub nr50: u8,
+    // NR51（0xFF25）: 各チャンネルを左右どちらに出力するか
+    // ビット4〜7が左のチャンネル1〜4、ビット0〜3が右のチャンネル1〜4
+    pub nr51: u8,
+    // 次のサンプルを出力するまでの端数（SAMPLE_RATEをTサイクルごとに足していく）
+    sample_counter: u32,
+    // 左、右の順に交互に並んだ出力サンプル
+    samples: VecDeque<f32>,
+}
+
+impl Apu {
+    // 命令の実行で消費したTサイクル数だけ進める
+    pub fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.channel1.tick_frequency_timer();
+            self.frame_sequencer_cycles += 1;
+            if self.frame_sequencer_cycles == FRAME_SEQUENCER_CYCLES {
+                self.frame_sequencer_cycles = 0;
+                self.step_frame_sequencer();
+            }
+            self.sample_counter += SAMPLE_RATE;
+            if self.sample_counter >= CLOCK_SPEED {
+                self.sample_counter -= CLOCK_SPEED;
+                self.push_sample();
+            }
+        }
+    }
+
+    fn push_sample(&mut self) {
+        let (left, right) = self.mix();
+        self.samples.push_back(left);
+        self.samples.push_back(right);
+        while self.samples.len() > MAX_BUFFERED_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    // 溜まっているサンプルをすべて取り出す
+    pub fn drain_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+
+    // 各チャンネルの現在の出力（0.0〜1.0）
+    // チャンネル2〜4はまだ無いので無音として扱う
+    fn channel_outputs(&self) -> [f32; 4] {
+        [self.channel1.output() as f32 / 15.0, 0.0, 0.0, 0.0]
+    }
+
+    // 4つのチャンネルをNR51で左右に振り分け、NR50の音量をかけて混ぜる
+    pub fn mix(&self) -> (f32, f32) {
+        let outputs = self.channel_outputs();
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (channel, output) in outputs.iter().enumerate() {
+            if self.nr51 & (0x10 << channel) != 0 {
+                left += output;
+            }
+            if self.nr51 & (0x01 << channel) != 0 {
+                right += output;
+            }
+        }
+        // マスター音量は0〜7で、1/8〜8/8として扱う
+        let left_volume = (((self.nr50 >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((self.nr50 & 0x07) + 1) as f32 / 8.0;
+        (left / 4.0 * left_volume, right / 4.0 * right_volume)
+    }
+
+    // ステップ0、2、4、6で長さカウンタ、2と6でスイープ、7でエンベロープを進める
+    fn step_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer_step;
+        if step.is_multiple_of(2) {
+            self.channel1.clock_length();
+        }
+        if step == 2 || step == 6 {
+            self.channel1.clock_sweep();
+        }
+        if step == 7 {
+            self.channel1.clock_envelope();
+        }
+        self.frame_sequencer_step = (step + 1) % 8;
+    }
+
+    // チャンネル1の現在の出力（0.0〜1.0）
+    pub fn sample(&mut self) -> f32 {
+        self.channel1.output() as f32 / 15.0
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF10 => self.channel1.nr10(),
+            0xFF11 => self.channel1.nr11(),
+            0xFF12 => self.channel1.nr12,
+            // NR13は書き込み専用
+            0xFF13 => 0xFF,
+            0xFF14 => self.channel1.nr14(),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF10 => self.channel1.write_nr10(value),
+            0xFF11 => self.channel1.write_nr11(value),
+            0xFF12 => self.channel1.write_nr12(value),
+            0xFF13 => self.channel1.frequency = (self.channel1.frequency & 0x0700) | value as u16,
+            0xFF14 => self.channel1.write_nr14(value),
+            0xFF24 => self.nr50 = value,
+            0xFF25 => self.nr51 = value,
+            _ => {}
+        }
+    }
+}
+
+// 矩形波のチャンネル
+#[derive(Default)]
+struct PulseChannel {
+    enabled: bool,
+    // NR11のビット6〜7
+    duty: u8,
+    // 波形の何ステップ目か（0〜7）
+    duty_position: u8,
+    // 11ビットの周波数の値（NR13とNR14のビット0〜2）
+    frequency: u16,
+    // 次に波形のステップが進むまでのTサイクル数
+    frequency_timer: u16,
+
+    // 0になるとチャンネルが止まる長さカウンタ
+    length_counter: u8,
+    // NR14のビット6
+    length_enabled: bool,
+
+    // NR12: 初期音量、増減の向き、周期
+    nr12: u8,
+    volume: u8,
+    envelope_timer: u8,
+
+    // NR10: スイープの周期、減算するか、シフト量
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    // スイープの計算に使う周波数の写し
+    shadow_frequency: u16,
+    // トリガー後に減算モードで計算したか
+    sweep_negated: bool,
+}
+
+impl PulseChannel {
+    fn nr10(&self) -> u8 {
+        0x80 | (self.sweep_period << 4) | ((self.sweep_negate as u8) << 3) | self.sweep_shift
+    }
+
+    fn write_nr10(&mut self, value: u8) {
+        self.sweep_period = (value >> 4) & 0x07;
+        self.sweep_negate = value & 0x08 != 0;
+        self.sweep_shift = value & 0x07;
+        // 減算モードで計算したあとに加算モードに戻すとチャンネルが止まる
+        if !self.sweep_negate && self.sweep_negated {
+            self.enabled = false;
+        }
+    }
+
+    // 長さは書き込み専用なので読めるのはデューティ比だけ
+    fn nr11(&self) -> u8 {
+        (self.duty << 6) | 0x3F
+    }
+
+    fn write_nr11(&mut self, value: u8) {
+        self.duty = value >> 6;
+        self.length_counter = 64 - (value & 0x3F);
+    }
+
+    fn write_nr12(&mut self, value: u8) {
+        self.nr12 = value;
+        // 上位5ビットがすべて0だとDACが切れてチャンネルも止まる
+        if !self.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    fn nr14(&self) -> u8 {
+        ((self.length_enabled as u8) << 6) | 0xBF
+    }
+
+    fn write_nr14(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x07) << 8);
+        self.length_enabled = value & 0x40 != 0;
+        if value & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    fn dac_enabled(&self) -> bool {
+        self.nr12 & 0xF8 != 0
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        if self.length_counter == 0 {
+            self.length_counter = 64;
+        }
+        self.frequency_timer = self.frequency_period();
+        self.volume = self.nr12 >> 4;
+        self.envelope_timer = self.nr12 & 0x07;
+
+        self.shadow_frequency = self.frequency;
+        self.sweep_timer = self.sweep_reload();
+        self.sweep_enabled = self.sweep_period != 0 || self.sweep_shift != 0;
+        self.sweep_negated = false;
+        // シフト量があれば、トリガーした時点でオーバーフローを確認する
+        if self.sweep_shift != 0 {
+            self.calculate_sweep();
+        }
+    }
+
+    // 波形の1ステップにかかるTサイクル数
+    fn frequency_period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    fn tick_frequency_timer(&mut self) {
+        if self.frequency_timer > 0 {
+            self.frequency_timer -= 1;
+        }
+        if self.frequency_timer == 0 {
+            self.frequency_timer = self.frequency_period();
+            self.duty_position = (self.duty_position + 1) % 8;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length_enabled && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        let period = self.nr12 & 0x07;
+        if period == 0 {
+            return;
+        }
+        if self.envelope_timer > 0 {
+            self.envelope_timer -= 1;
+        }
+        if self.envelope_timer == 0 {
+            self.envelope_timer = period;
+            if self.nr12 & 0x08 != 0 && self.volume < 15 {
+                self.volume += 1;
+            } else if self.nr12 & 0x08 == 0 && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    // 周期0は8として扱う
+    fn sweep_reload(&self) -> u8 {
+        if self.sweep_period == 0 {
+            8
+        } else {
+            self.sweep_period
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_timer > 0 {
+            self.sweep_timer -= 1;
+        }
+        if self.sweep_timer != 0 {
+            return;
+        }
+        self.sweep_timer = self.sweep_reload();
+        if !self.sweep_enabled || self.sweep_period == 0 {
+            return;
+        }
+        let frequency = self.calculate_sweep();
+        if frequency <= 2047 && self.sweep_shift != 0 {
+            self.shadow_frequency = frequency;
+            self.frequency = frequency;
+            // 新しい周波数でもう一度オーバーフローを確認する
+            self.calculate_sweep();
+        }
+    }
+
+    // 次の周波数を計算し、2047を超えたらチャンネルを止める
+    fn calculate_sweep(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.sweep_shift;
+        let frequency = if self.sweep_negate {
+            self.sweep_negated = true;
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        };
+        if frequency > 2047 {
+            self.enabled = false;
+        }
+        frequency
+    }
+
+    // 現在の出力（0〜15）
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        DUTY_TABLE[self.duty as usize][self.duty_position as usize] * self.volume
+    }
+}
+
+// 出力済みのサンプルは状態ではないので含めない（読み込むと捨てる）
+impl Snapshot for Apu {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        self.channel1.save_state(writer);
+        writer.write_u16(self.frame_sequencer_cycles);
+        writer.write_u8(self.frame_sequencer_step);
+        writer.write_u8(self.nr50);
+        writer.write_u8(self.nr51);
+        writer.write_u32(self.sample_counter);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.channel1.load_state(reader)?;
+        self.frame_sequencer_cycles = reader.read_u16()?;
+        self.frame_sequencer_step = reader.read_u8()?;
+        self.nr50 = reader.read_u8()?;
+        self.nr51 = reader.read_u8()?;
+        self.sample_counter = reader.read_u32()?;
+        self.samples.clear();
+        Ok(())
+    }
+}
+
+impl Snapshot for PulseChannel {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.duty_position);
+        writer.write_u16(self.frequency);
+        writer.write_u16(self.frequency_timer);
+        writer.write_u8(self.length_counter);
+        writer.write_bool(self.length_enabled);
+        writer.write_u8(self.nr12);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.envelope_timer);
+        writer.write_u8(self.sweep_period);
+        writer.write_bool(self.sweep_negate);
+        writer.write_u8(self.sweep_shift);
+        writer.write_u8(self.sweep_timer);
+        writer.write_bool(self.sweep_enabled);
+        writer.write_u16(self.shadow_frequency);
+        writer.write_bool(self.sweep_negated);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.enabled = reader.read_bool()?;
+        self.duty = reader.read_u8()?;
+        self.duty_position = reader.read_u8()?;
+        self.frequency = reader.read_u16()?;
+        self.frequency_timer = reader.read_u16()?;
+        self.length_counter = reader.read_u8()?;
+        self.length_enabled = reader.read_bool()?;
+        self.nr12 = reader.read_u8()?;
+        self.volume = reader.read_u8()?;
+        self.envelope_timer = reader.read_u8()?;
+        self.sweep_period = reader.read_u8()?;
+        self.sweep_negate = reader.read_bool()?;
+        self.sweep_shift = reader.read_u8()?;
+        self.sweep_timer = reader.read_u8()?;
+        self.sweep_enabled = reader.read_bool()?;
+        self.shadow_frequency = reader.read_u16()?;
+        self.sweep_negated = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 音量15、周波数0x700でトリガーする
+    fn trigger_channel1(apu: &mut Apu, nr10: u8) {
+        apu.write(0xFF10, nr10);
+        apu.write(0xFF11, 0x80);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF13, 0x00);
+        apu.write(0xFF14, 0x87);
+    }
+
+    // 周波数タイマーは(2048 - 周波数) * 4Tサイクルごとに波形を1ステップ進めて再読み込みされる
+    #[test]
+    fn test_frequency_timer_reload() {
+        let mut apu = Apu::default();
+        trigger_channel1(&mut apu, 0x00);
+        assert_eq!(apu.channel1.frequency_timer, 0x100 * 4);
+
+        for _ in 0..0x100 * 4 - 1 {
+            apu.tick(1);
+        }
+        assert_eq!(apu.channel1.duty_position, 0);
+        apu.tick(1);
+        assert_eq!(apu.channel1.duty_position, 1);
+        assert_eq!(apu.channel1.frequency_timer, 0x100 * 4);
+    }
+
+    // デューティ比50%の波形の出力
+    #[test]
+    fn test_sample_follows_duty() {
+        let mut apu = Apu::default();
+        trigger_channel1(&mut apu, 0x00);
+        assert_eq!(apu.sample(), 1.0);
+        for _ in 0..0x100 {
+            apu.tick(4);
+        }
+        assert_eq!(apu.channel1.duty_position, 1);
+        assert_eq!(apu.sample(), 0.0);
+    }
+
+    // スイープはフレームシーケンサのステップ2で周波数をシフトした分だけ変える
+    #[test]
+    fn test_sweep_shifts_frequency() {
+        let mut apu = Apu::default();
+        // 周期1、加算、シフト1
+        apu.write(0xFF10, 0x11);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF13, 0x00);
+        apu.write(0xFF14, 0x81);
+        tick_frame_steps(&mut apu, 3);
+        assert_eq!(apu.channel1.frequency, 0x180);
+
+        // 減算モード
+        let mut apu = Apu::default();
+        apu.write(0xFF10, 0x19);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF13, 0x00);
+        apu.write(0xFF14, 0x81);
+        tick_frame_steps(&mut apu, 3);
+        assert_eq!(apu.channel1.frequency, 0x080);
+    }
+
+    // スイープで2047を超えるとチャンネルが止まる
+    #[test]
+    fn test_sweep_overflow_disables_channel() {
+        let mut apu = Apu::default();
+        trigger_channel1(&mut apu, 0x11);
+        assert!(!apu.channel1.enabled);
+    }
+
+    // 減算モードで計算したあとに加算モードに切り替えるとチャンネルが止まる
+    #[test]
+    fn test_sweep_negate_quirk() {
+        let mut apu = Apu::default();
+        apu.write(0xFF10, 0x19);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x81);
+        assert!(apu.channel1.enabled);
+        apu.write(0xFF10, 0x11);
+        assert!(!apu.channel1.enabled);
+    }
+
+    // 長さカウンタが0になるとチャンネルが止まる
+    #[test]
+    fn test_length_counter() {
+        let mut apu = Apu::default();
+        apu.write(0xFF11, 0x3E); // 長さ2
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0xC0);
+        tick_frame_steps(&mut apu, 1);
+        assert!(apu.channel1.enabled);
+        tick_frame_steps(&mut apu, 2);
+        assert!(!apu.channel1.enabled);
+    }
+
+    // NR51でチャンネル1を左だけに出力すると、右は無音になる
+    #[test]
+    fn test_mix_routes_channel_to_left_only() {
+        let mut apu = Apu::default();
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0x10);
+        trigger_channel1(&mut apu, 0x00);
+        // 1000サンプル分より少し多く進める
+        for _ in 0..CLOCK_SPEED / SAMPLE_RATE * 1000 / 4 + 100 {
+            apu.tick(4);
+        }
+        let samples = apu.drain_samples();
+        assert!(samples.len() >= 2000);
+        let left = samples.iter().step_by(2);
+        let mut right = samples.iter().skip(1).step_by(2);
+        assert!(left.clone().any(|&sample| sample > 0.0));
+        assert!(left.clone().all(|&sample| sample <= 0.25));
+        assert!(right.all(|&sample| sample == 0.0));
+        // 取り出したあとは空になる
+        assert!(apu.drain_samples().is_empty());
+    }
+
+    // 約44100Hzでサンプルが出力される
+    #[test]
+    fn test_sample_rate() {
+        let mut apu = Apu::default();
+        for _ in 0..CLOCK_SPEED / 4 / 4 {
+            apu.tick(4);
+        }
+        // 0.25秒分で左右合わせて約22050サンプル
+        assert_eq!(apu.drain_samples().len(), 22050);
+    }
+
+    fn tick_frame_steps(apu: &mut Apu, steps: u16) {
+        for _ in 0..steps * FRAME_SEQUENCER_CYCLES / 4 {
+            apu.tick(4);
+        }
+    }
+}
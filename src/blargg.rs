@@ -0,0 +1,105 @@
+// BlarggのテストROM（cpu_instrs）で命令の実装を検証する
+// ROMは配布物に含めないので、環境変数BLARGG_ROMSで指定したディレクトリか、
+// tests/roms/cpu_instrs/individualに置いておく
+// ROMが見つからないテストは何もせずに成功扱いにする
+
+use std::path::PathBuf;
+
+use crate::cpu::CPU;
+
+// テストが終わるまで実行するフレーム数の上限（60フレームで約1秒）
+const MAX_FRAMES: usize = 60 * 60;
+
+fn rom_path(name: &str) -> PathBuf {
+    let directory = std::env::var("BLARGG_ROMS")
+        .unwrap_or_else(|_| "tests/roms/cpu_instrs/individual".to_string());
+    PathBuf::from(directory).join(name)
+}
+
+// ROMを実行し、シリアルに「Passed」か「Failed」が出力されるまで待つ
+fn run_test_rom(name: &str) {
+    let path = rom_path(name);
+    if !path.exists() {
+        eprintln!("skipping {}: {} not found", name, path.display());
+        return;
+    }
+    let mut cpu = CPU::load_cartridge(path.to_str().unwrap()).unwrap();
+    for _ in 0..MAX_FRAMES {
+        if let Err(error) = cpu.run_frame() {
+            panic!(
+                "{}: {} (output: {:?})",
+                name,
+                error,
+                cpu.bus.serial_output()
+            );
+        }
+        let output = cpu.bus.serial_output();
+        if output.contains("Passed") {
+            return;
+        }
+        if output.contains("Failed") {
+            panic!("{} failed: {:?}", name, output);
+        }
+    }
+    panic!(
+        "{} did not finish in {} frames (output: {:?})",
+        name,
+        MAX_FRAMES,
+        cpu.bus.serial_output()
+    );
+}
+
+#[test]
+fn test_cpu_instrs_01_special() {
+    run_test_rom("01-special.gb");
+}
+
+#[test]
+fn test_cpu_instrs_02_interrupts() {
+    run_test_rom("02-interrupts.gb");
+}
+
+#[test]
+fn test_cpu_instrs_03_op_sp_hl() {
+    run_test_rom("03-op sp,hl.gb");
+}
+
+#[test]
+fn test_cpu_instrs_04_op_r_imm() {
+    run_test_rom("04-op r,imm.gb");
+}
+
+#[test]
+fn test_cpu_instrs_05_op_rp() {
+    run_test_rom("05-op rp.gb");
+}
+
+#[test]
+fn test_cpu_instrs_06_ld_r_r() {
+    run_test_rom("06-ld r,r.gb");
+}
+
+#[test]
+fn test_cpu_instrs_07_jr_jp_call_ret_rst() {
+    run_test_rom("07-jr,jp,call,ret,rst.gb");
+}
+
+#[test]
+fn test_cpu_instrs_08_misc_instrs() {
+    run_test_rom("08-misc instrs.gb");
+}
+
+#[test]
+fn test_cpu_instrs_09_op_r_r() {
+    run_test_rom("09-op r,r.gb");
+}
+
+#[test]
+fn test_cpu_instrs_10_bit_ops() {
+    run_test_rom("10-bit ops.gb");
+}
+
+#[test]
+fn test_cpu_instrs_11_op_a_hl() {
+    run_test_rom("11-op a,(hl).gb");
+}
@@ -0,0 +1,210 @@
+// カートリッジヘッダ（0x0100〜0x014F）の情報
+#[derive(Debug, PartialEq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cartridge_type: CartridgeType,
+    // 0x0148: ROMサイズのコード（32KiB << n）
+    pub rom_size: u8,
+    // 0x0149: RAMサイズのコード
+    pub ram_size: u8,
+}
+
+// タイトルは0x0134〜0x0143のASCII文字列
+const TITLE_START: usize = 0x0134;
+const TITLE_END: usize = 0x0143;
+const CARTRIDGE_TYPE_ADDRESS: usize = 0x0147;
+const ROM_SIZE_ADDRESS: usize = 0x0148;
+const RAM_SIZE_ADDRESS: usize = 0x0149;
+const HEADER_CHECKSUM_ADDRESS: usize = 0x014D;
+
+impl CartridgeHeader {
+    // ヘッダ領域を含むバイト列（先頭が0x0000）からヘッダを読み取る
+    pub fn parse(bytes: &[u8]) -> CartridgeHeader {
+        let byte_at = |address: usize| bytes.get(address).copied().unwrap_or(0);
+        // 余った部分は0で埋められているので、最初の0までをタイトルとする
+        let title = (TITLE_START..=TITLE_END)
+            .map(byte_at)
+            .take_while(|&byte| byte != 0)
+            .map(|byte| byte as char)
+            .collect();
+
+        CartridgeHeader {
+            title,
+            cartridge_type: CartridgeType::from(byte_at(CARTRIDGE_TYPE_ADDRESS)),
+            rom_size: byte_at(ROM_SIZE_ADDRESS),
+            ram_size: byte_at(RAM_SIZE_ADDRESS),
+        }
+    }
+
+    // ブートROMと同じ方法でヘッダのチェックサムを検証する
+    // 0x0134〜0x014Cの各バイトについて x = x - byte - 1 を計算し、
+    // 下位8ビットが0x014Dの値と一致すれば正しい
+    pub fn verify_checksum(&self, rom: &[u8]) -> bool {
+        if rom.len() <= HEADER_CHECKSUM_ADDRESS {
+            return false;
+        }
+        let checksum = rom[TITLE_START..HEADER_CHECKSUM_ADDRESS]
+            .iter()
+            .fold(0u8, |x, &byte| x.wrapping_sub(byte).wrapping_sub(1));
+        checksum == rom[HEADER_CHECKSUM_ADDRESS]
+    }
+
+    // ROMのバイト数
+    pub fn rom_size_bytes(&self) -> usize {
+        0x8000 << self.rom_size
+    }
+
+    // 外部RAMのバイト数
+    pub fn ram_size_bytes(&self) -> usize {
+        match self.ram_size {
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            _ => 0,
+        }
+    }
+}
+
+// 0x0147: カートリッジの種類（使われているメモリバンクコントローラ）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CartridgeType {
+    RomOnly,
+    Mbc1,
+    Mbc1Ram,
+    Mbc1RamBattery,
+    Mbc2,
+    Mbc2Battery,
+    RomRam,
+    RomRamBattery,
+    Mbc3TimerBattery,
+    Mbc3TimerRamBattery,
+    Mbc3,
+    Mbc3Ram,
+    Mbc3RamBattery,
+    Mbc5,
+    Mbc5Ram,
+    Mbc5RamBattery,
+    Mbc5Rumble,
+    Mbc5RumbleRam,
+    Mbc5RumbleRamBattery,
+    Unknown(u8),
+}
+
+impl CartridgeType {
+    // 外部RAMがバッテリーでバックアップされているか
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self,
+            CartridgeType::Mbc1RamBattery
+                | CartridgeType::Mbc2Battery
+                | CartridgeType::RomRamBattery
+                | CartridgeType::Mbc3TimerBattery
+                | CartridgeType::Mbc3TimerRamBattery
+                | CartridgeType::Mbc3RamBattery
+                | CartridgeType::Mbc5RamBattery
+                | CartridgeType::Mbc5RumbleRamBattery
+        )
+    }
+}
+
+impl std::convert::From<u8> for CartridgeType {
+    fn from(byte: u8) -> CartridgeType {
+        match byte {
+            0x00 => CartridgeType::RomOnly,
+            0x01 => CartridgeType::Mbc1,
+            0x02 => CartridgeType::Mbc1Ram,
+            0x03 => CartridgeType::Mbc1RamBattery,
+            0x05 => CartridgeType::Mbc2,
+            0x06 => CartridgeType::Mbc2Battery,
+            0x08 => CartridgeType::RomRam,
+            0x09 => CartridgeType::RomRamBattery,
+            0x0F => CartridgeType::Mbc3TimerBattery,
+            0x10 => CartridgeType::Mbc3TimerRamBattery,
+            0x11 => CartridgeType::Mbc3,
+            0x12 => CartridgeType::Mbc3Ram,
+            0x13 => CartridgeType::Mbc3RamBattery,
+            0x19 => CartridgeType::Mbc5,
+            0x1A => CartridgeType::Mbc5Ram,
+            0x1B => CartridgeType::Mbc5RamBattery,
+            0x1C => CartridgeType::Mbc5Rumble,
+            0x1D => CartridgeType::Mbc5RumbleRam,
+            0x1E => CartridgeType::Mbc5RumbleRamBattery,
+            _ => CartridgeType::Unknown(byte),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_header(title: &[u8], cartridge_type: u8) -> Vec<u8> {
+        let mut bytes = vec![0; 0x150];
+        bytes[TITLE_START..TITLE_START + title.len()].copy_from_slice(title);
+        bytes[CARTRIDGE_TYPE_ADDRESS] = cartridge_type;
+        bytes[ROM_SIZE_ADDRESS] = 0x02;
+        bytes[RAM_SIZE_ADDRESS] = 0x03;
+        bytes
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = CartridgeHeader::parse(&build_header(b"TETRIS", 0x03));
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.cartridge_type, CartridgeType::Mbc1RamBattery);
+        assert_eq!(header.rom_size, 0x02);
+        assert_eq!(header.ram_size, 0x03);
+        assert_eq!(header.rom_size_bytes(), 0x20000);
+        assert_eq!(header.ram_size_bytes(), 0x8000);
+    }
+
+    // タイトルが16文字すべてを使っている場合
+    #[test]
+    fn test_parse_header_full_length_title() {
+        let header = CartridgeHeader::parse(&build_header(b"ABCDEFGHIJKLMNOP", 0x00));
+        assert_eq!(header.title, "ABCDEFGHIJKLMNOP");
+        assert_eq!(header.cartridge_type, CartridgeType::RomOnly);
+    }
+
+    fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        let mut x: u8 = 0;
+        for &byte in &bytes[0x0134..=0x014C] {
+            x = x.wrapping_sub(byte).wrapping_sub(1);
+        }
+        bytes[HEADER_CHECKSUM_ADDRESS] = x;
+        bytes
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let rom = with_checksum(build_header(b"POKEMON RED", 0x13));
+        let header = CartridgeHeader::parse(&rom);
+        assert!(header.verify_checksum(&rom));
+    }
+
+    // 1バイトでも書き換わっていれば検証に失敗する
+    #[test]
+    fn test_verify_checksum_detects_corruption() {
+        let mut rom = with_checksum(build_header(b"POKEMON RED", 0x13));
+        let header = CartridgeHeader::parse(&rom);
+        rom[0x0140] ^= 0x01;
+        assert!(!header.verify_checksum(&rom));
+    }
+
+    #[test]
+    fn test_verify_checksum_too_short() {
+        let rom = vec![0; 0x100];
+        let header = CartridgeHeader::parse(&rom);
+        assert!(!header.verify_checksum(&rom));
+    }
+
+    #[test]
+    fn test_cartridge_type_from_byte() {
+        assert_eq!(CartridgeType::from(0x01), CartridgeType::Mbc1);
+        assert_eq!(CartridgeType::from(0x13), CartridgeType::Mbc3RamBattery);
+        assert_eq!(CartridgeType::from(0x19), CartridgeType::Mbc5);
+        assert_eq!(CartridgeType::from(0xFC), CartridgeType::Unknown(0xFC));
+    }
+}
@@ -1,103 +1,715 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+
+use crate::dispatch;
+use crate::input_log::{InputLog, InputRecorder, InputReplayer};
 use crate::instruction::{
-    ArithmeticTarget, Instruction, JumpTest, LoadByteSource, LoadByteTarget, LoadType,
+    ArithmeticTarget, HighOffset, IncDecTarget, Indirect, Instruction, JumpTest, LoadByteSource,
+    LoadByteTarget, LoadType, LoadWordSource, LoadWordTarget, PrefixTarget, StackTarget,
+    WordRegister,
+};
+use crate::interrupt;
+use crate::memory::{
+    BGP_ADDRESS, DIV_ADDRESS, INTERRUPT_ENABLE_ADDRESS, INTERRUPT_FLAG_ADDRESS, LCDC_ADDRESS,
+    Memory, MemoryBus, OBP0_ADDRESS, OBP1_ADDRESS,
 };
-use crate::memory::MemoryBus;
 use crate::registers::Registers;
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
 
-#[derive(Default)]
-pub struct CPU {
+// 描き終えたフレームバッファを受け取る関数
+pub type FrameCallback = Box<dyn FnMut(&[u8])>;
+
+// 命令の実行に必要なのはメモリの読み書きだけなので、メモリの型はMで差し替えられる
+// 描画やタイマーなど周辺機器を動かすステップの実行は、実際のバス（MemoryBus）の場合だけ
+pub struct CPU<M = MemoryBus> {
     pub registers: Registers,
     pub pc: u16,
     pub sp: u16,
-    pub bus: MemoryBus,
+    pub bus: M,
+    // 割り込みマスタ有効フラグ（IME）
+    pub ime: bool,
+    // EIは次の命令を実行し終えてからIMEを有効にするので、それまでの保留フラグ
+    ime_pending: bool,
+    // HALT中は割り込みが要求されるまで命令をフェッチしない
+    pub halted: bool,
+    // STOP中はボタンが押されるまでCPUもLCDも止まる
+    pub stopped: bool,
+    // HALTバグ: 次の命令のフェッチでpcが進まず、同じバイトが2回読まれる
+    halt_bug: bool,
+    // 直前に実行した条件分岐が成立したか（サイクル数の計算に使う）
+    branch_taken: bool,
+    // これまでに消費したTサイクル数の合計
+    pub cycles: u64,
+    // プロファイリング用の、reset_countersからの命令数とTサイクル数
+    // cyclesと違ってフレームの区切りには使わないので、いつでも0に戻せる
+    instructions_executed: u64,
+    cycles_elapsed: u64,
+    // 設定されていれば、各ステップの前にlog_stateの行を書き出す
+    trace_output: Option<Box<dyn Write>>,
+    // ステップバック用に、各ステップの直前のスナップショットを古い順に溜める
+    history: VecDeque<Vec<u8>>,
+    // 溜めるスナップショットの数（0なら溜めない）
+    history_depth: usize,
+    // 設定されていれば、VBlankに入るたびに描き終えたフレームバッファを渡して呼ぶ
+    frame_callback: Option<FrameCallback>,
+    // 記録中なら、run_frameのたびにフレームの始めのボタンの状態を記録する
+    input_recorder: Option<InputRecorder>,
+    // 再生中なら、run_frameのたびに記録したボタンの状態を設定する
+    input_replayer: Option<InputReplayer>,
+    // trace_lineでアドレスの代わりに表示するラベル
+    symbols: HashMap<u16, String>,
+    // 設定されていれば、run_for_durationが実時間（この時計のミリ秒）に合わせて待つ
+    throttle_clock: Option<fn() -> u64>,
+    // スロットル中に待つときに呼ぶ（引数はミリ秒）
+    throttle_sleep: fn(u64),
+}
+
+// フロントエンドやテストから見るための、CPUの状態の写し
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpuDebugState {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub f: u8,
+    pub h: u8,
+    pub l: u8,
+    pub zero: bool,
+    pub subtract: bool,
+    pub half_carry: bool,
+    pub carry: bool,
+    pub pc: u16,
+    pub sp: u16,
+    pub ime: bool,
+    pub halted: bool,
+}
+
+// 命令の実行中に起きたエラー
+#[derive(Debug, PartialEq)]
+pub enum CpuError {
+    // 実機では使われていないオペコード（実機ではCPUが止まる。pcはオペコードのアドレス）
+    IllegalOpcode { byte: u8, pc: u16 },
+}
+
+impl std::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CpuError::IllegalOpcode { byte, pc } => {
+                write!(f, "Illegal instruction 0x{:02X} at 0x{:04X}", byte, pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+// 1フレーム（154ライン × 456Tサイクル）のTサイクル数
+pub const CYCLES_PER_FRAME: u64 = 70224;
+
+// 実機のクロック（1秒あたりのTサイクル数、約4.19MHz）
+pub const CLOCK_SPEED: u32 = 4_194_304;
+
+// 速度を実機に合わせるときに使う時計（プロセスが始まってからのミリ秒）
+fn host_clock() -> u64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_millis() as u64
+}
+
+// set_throttleで有効にしたときの待ち方（実時間でmsミリ秒眠る）
+fn host_sleep(ms: u64) {
+    std::thread::sleep(std::time::Duration::from_millis(ms));
+}
+
+// オフセットが即値なら2バイト、Cレジスタなら1バイトの命令
+fn high_offset_length(offset: &HighOffset) -> u16 {
+    match offset {
+        HighOffset::A8 => 2,
+        HighOffset::C => 1,
+    }
+}
+
+// ステップバック用のスナップショットに使うメモリの上限
+// 1つあたり約120KiBなので、外部RAMがなければ500ステップほどになる
+const HISTORY_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+// HALT中に1ステップで進めるTサイクル数
+const HALTED_CYCLES: u8 = 4;
+// 割り込みの処理（pcのpushとベクタへのジャンプ）にかかるTサイクル数
+const INTERRUPT_DISPATCH_CYCLES: u8 = 20;
+
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::with_memory(MemoryBus::default())
+    }
 }
 
 impl CPU {
+    // ファイルからカートリッジを読み込み、エントリポイント（0x0100）から実行するCPUを作る
+    pub fn load_cartridge(path: &str) -> std::io::Result<CPU> {
+        let rom = std::fs::read(path)?;
+        let mut cpu = CPU::default();
+        cpu.bus.load_rom(&rom);
+        cpu.reset();
+        Ok(cpu)
+    }
+}
+
+impl<M: Memory> CPU<M> {
+    // 指定したメモリにつながった、電源投入直後のCPUを作る
+    pub fn with_memory(bus: M) -> CPU<M> {
+        CPU {
+            registers: Registers::default(),
+            pc: 0,
+            sp: 0,
+            bus,
+            ime: false,
+            ime_pending: false,
+            halted: false,
+            stopped: false,
+            halt_bug: false,
+            branch_taken: false,
+            cycles: 0,
+            instructions_executed: 0,
+            cycles_elapsed: 0,
+            trace_output: None,
+            history: VecDeque::new(),
+            history_depth: 0,
+            frame_callback: None,
+            input_recorder: None,
+            input_replayer: None,
+            symbols: HashMap::new(),
+            throttle_clock: None,
+            throttle_sleep: host_sleep,
+        }
+    }
+
+    // ブートROMを実行し終えた直後（DMG）の状態にする
+    // ブートROMを使わずにエントリポイント（0x0100）から実行を始めるときに使う
+    pub fn reset(&mut self) {
+        self.registers.set_af(0x01B0);
+        self.registers.set_bc(0x0013);
+        self.registers.set_de(0x00D8);
+        self.registers.set_hl(0x014D);
+        self.sp = 0xFFFE;
+        self.pc = 0x0100;
+        self.ime = false;
+        self.ime_pending = false;
+        self.halted = false;
+        self.stopped = false;
+        self.halt_bug = false;
+        self.cycles = 0;
+
+        // 主なI/Oレジスタ（サウンドのトリガーやタイマーは止まった状態にしておく）
+        let io_registers = [
+            (0xFF10, 0x80),
+            (0xFF11, 0xBF),
+            (0xFF12, 0xF3),
+            (0xFF24, 0x77),
+            (0xFF25, 0xF3),
+            (LCDC_ADDRESS, 0x91),
+            (BGP_ADDRESS, 0xFC),
+            (OBP0_ADDRESS, 0xFF),
+            (OBP1_ADDRESS, 0xFF),
+            (INTERRUPT_FLAG_ADDRESS, 0xE1),
+            (INTERRUPT_ENABLE_ADDRESS, 0x00),
+        ];
+        for (address, value) in io_registers {
+            self.bus.write_byte(address, value);
+        }
+    }
+
+    // 命令を実行し、次に実行する命令のアドレスを返す
+    // 実行中のpcは常にこの命令のオペコードを指している（フェッチではpcを進めない）
+    // そのためオペランドはpc + 1から読み、分岐しない場合の次のpcはpc + 命令の長さになる
     pub fn execute(&mut self, instruction: Instruction) -> u16 {
         match instruction {
+            Instruction::NOP => self.pc.wrapping_add(1),
             Instruction::JP(test) => {
-                let jump_condition = match test {
-                    JumpTest::NotZero => !self.registers.f.zero,
-                    JumpTest::Zero => self.registers.f.zero,
-                    JumpTest::NotCarry => !self.registers.f.carry,
-                    JumpTest::Carry => self.registers.f.carry,
-                    JumpTest::Always => true,
-                };
+                let jump_condition = self.check_jump_test(test);
+                self.branch_taken = jump_condition;
                 self.jump(jump_condition)
             }
-            Instruction::ADD(target) => match target {
-                ArithmeticTarget::A => self.pc,
-                ArithmeticTarget::B => self.pc,
-                ArithmeticTarget::C => {
-                    let value = self.registers.c;
-                    let new_value = self.add(value);
-                    self.registers.a = new_value;
-                    self.pc.wrapping_add(1)
+            Instruction::JPHL => self.registers.get_hl(),
+            Instruction::JR(test) => {
+                let jump_condition = self.check_jump_test(test);
+                self.branch_taken = jump_condition;
+                self.jump_relative(jump_condition)
+            }
+            Instruction::ADD(target) => self.arithmetic(target, Self::add),
+            Instruction::ADC(target) => self.arithmetic(target, Self::adc),
+            Instruction::SUB(target) => self.arithmetic(target, Self::sub),
+            Instruction::SBC(target) => self.arithmetic(target, Self::sbc),
+            Instruction::AND(target) => self.arithmetic(target, Self::and),
+            Instruction::OR(target) => self.arithmetic(target, Self::or),
+            Instruction::XOR(target) => self.arithmetic(target, Self::xor),
+            Instruction::CP(target) => self.arithmetic(target, Self::compare),
+            Instruction::INC(target) => self.inc_dec(target, Self::inc),
+            Instruction::DEC(target) => self.inc_dec(target, Self::dec),
+            Instruction::ADDHL(register) => {
+                let value = self.read_word_register(register);
+                self.add_hl(value);
+                self.pc.wrapping_add(1)
+            }
+            Instruction::ADDSP => {
+                self.sp = self.add_sp_signed();
+                self.pc.wrapping_add(2)
+            }
+            Instruction::LDHLSP => {
+                let value = self.add_sp_signed();
+                self.registers.set_hl(value);
+                self.pc.wrapping_add(2)
+            }
+            // 16ビットのinc/decはフラグを一切変更しない
+            Instruction::INC16(register) => {
+                let value = self.read_word_register(register);
+                self.write_word_register(register, value.wrapping_add(1));
+                self.pc.wrapping_add(1)
+            }
+            Instruction::DEC16(register) => {
+                let value = self.read_word_register(register);
+                self.write_word_register(register, value.wrapping_sub(1));
+                self.pc.wrapping_add(1)
+            }
+            Instruction::RLCA => {
+                let value = self.registers.a;
+                self.registers.a = self.rotate_left(value, false);
+                // Aのローテートは結果が0でもzeroを立てない
+                self.registers.f.zero = false;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::RRCA => {
+                let value = self.registers.a;
+                self.registers.a = self.rotate_right(value, false);
+                self.registers.f.zero = false;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::RLA => {
+                let value = self.registers.a;
+                self.registers.a = self.rotate_left(value, true);
+                self.registers.f.zero = false;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::RRA => {
+                let value = self.registers.a;
+                self.registers.a = self.rotate_right(value, true);
+                self.registers.f.zero = false;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::RLC(target) => {
+                let value = self.read_prefix_target(target);
+                let new_value = self.rotate_left(value, false);
+                self.write_prefix_target(target, new_value);
+                self.pc.wrapping_add(2)
+            }
+            Instruction::RRC(target) => {
+                let value = self.read_prefix_target(target);
+                let new_value = self.rotate_right(value, false);
+                self.write_prefix_target(target, new_value);
+                self.pc.wrapping_add(2)
+            }
+            Instruction::RL(target) => {
+                let value = self.read_prefix_target(target);
+                let new_value = self.rotate_left(value, true);
+                self.write_prefix_target(target, new_value);
+                self.pc.wrapping_add(2)
+            }
+            Instruction::RR(target) => {
+                let value = self.read_prefix_target(target);
+                let new_value = self.rotate_right(value, true);
+                self.write_prefix_target(target, new_value);
+                self.pc.wrapping_add(2)
+            }
+            Instruction::SLA(target) => {
+                let value = self.read_prefix_target(target);
+                let new_value = self.shift_left_arithmetic(value);
+                self.write_prefix_target(target, new_value);
+                self.pc.wrapping_add(2)
+            }
+            Instruction::SRA(target) => {
+                let value = self.read_prefix_target(target);
+                let new_value = self.shift_right_arithmetic(value);
+                self.write_prefix_target(target, new_value);
+                self.pc.wrapping_add(2)
+            }
+            Instruction::SRL(target) => {
+                let value = self.read_prefix_target(target);
+                let new_value = self.shift_right_logical(value);
+                self.write_prefix_target(target, new_value);
+                self.pc.wrapping_add(2)
+            }
+            Instruction::BIT(bit, target) => {
+                let value = self.read_prefix_target(target);
+                self.bit(bit, value);
+                self.pc.wrapping_add(2)
+            }
+            // RES/SETはフラグを変更しない
+            Instruction::RES(bit, target) => {
+                let value = self.read_prefix_target(target);
+                self.write_prefix_target(target, value & !(1 << bit));
+                self.pc.wrapping_add(2)
+            }
+            Instruction::SET(bit, target) => {
+                let value = self.read_prefix_target(target);
+                self.write_prefix_target(target, value | (1 << bit));
+                self.pc.wrapping_add(2)
+            }
+            Instruction::SWAP(target) => {
+                let value = self.read_prefix_target(target);
+                let new_value = self.swap(value);
+                self.write_prefix_target(target, new_value);
+                // 0xCBと命令の2バイト進む
+                self.pc.wrapping_add(2)
+            }
+            Instruction::DAA => {
+                self.decimal_adjust();
+                self.pc.wrapping_add(1)
+            }
+            // CPL/SCF/CCFはzeroを変更しない
+            Instruction::CPL => {
+                self.registers.a = !self.registers.a;
+                self.registers.f.subtract = true;
+                self.registers.f.half_carry = true;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::SCF => {
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = true;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::CCF => {
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = !self.registers.f.carry;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::DI => {
+                // DIは即座に無効化し、保留中のEIも取り消す
+                self.ime = false;
+                self.ime_pending = false;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::EI => {
+                self.ime_pending = true;
+                self.pc.wrapping_add(1)
+            }
+            Instruction::HALT => {
+                // IMEが無効なのに割り込みが既に要求されている場合はHALTせず、
+                // HALTバグが発生する
+                if !self.ime && self.pending_interrupts() != 0 {
+                    self.halt_bug = true;
+                } else {
+                    self.halted = true;
                 }
-                ArithmeticTarget::D => self.pc,
-                ArithmeticTarget::E => self.pc,
-                ArithmeticTarget::H => self.pc,
-                ArithmeticTarget::L => self.pc,
-            },
+                self.pc.wrapping_add(1)
+            }
+            Instruction::STOP => {
+                // STOPに入るとDIVがリセットされる
+                self.bus.write_byte(DIV_ADDRESS, 0);
+                // KEY1で速度の切り替えが準備されていれば、切り替えるだけで止まらない
+                if !self.bus.switch_speed() {
+                    self.stopped = true;
+                }
+                self.pc.wrapping_add(2)
+            }
+            // 実機ではCPUが止まったままになるので、pcを進めない
+            // stepはここに来る前にIllegalOpcodeを返す
+            Instruction::Illegal(_) => self.pc,
             Instruction::LD(load_type) => match load_type {
-                LoadType::Byte(target, source) => {
+                LoadType::Byte(target, source) => self.load_byte(target, source),
+                LoadType::Word(target, source) => {
+                    // 即値を読む場合は3バイト命令
+                    let length = match (&target, &source) {
+                        (LoadWordTarget::A16, _) | (_, LoadWordSource::D16) => 3,
+                        _ => 1,
+                    };
                     let source_value = match source {
-                        LoadByteSource::A => self.registers.a,
-                        LoadByteSource::B => self.registers.b,
-                        LoadByteSource::C => self.registers.c,
-                        LoadByteSource::D => self.registers.d,
-                        LoadByteSource::E => self.registers.e,
-                        LoadByteSource::H => self.registers.h,
-                        LoadByteSource::L => self.registers.l,
-                        LoadByteSource::D8 => self.read_next_byte(),
-                        LoadByteSource::HLI => self.bus.read_byte(self.registers.get_hl()),
+                        LoadWordSource::D16 => self.read_next_word(),
+                        LoadWordSource::SP => self.sp,
+                        LoadWordSource::HL => self.registers.get_hl(),
                     };
                     match target {
-                        LoadByteTarget::A => self.registers.a = source_value,
-                        LoadByteTarget::B => self.registers.b = source_value,
-                        LoadByteTarget::C => self.registers.c = source_value,
-                        LoadByteTarget::D => self.registers.d = source_value,
-                        LoadByteTarget::E => self.registers.e = source_value,
-                        LoadByteTarget::H => self.registers.h = source_value,
-                        LoadByteTarget::L => self.registers.l = source_value,
-                        LoadByteTarget::HLI => {
-                            self.bus.write_byte(self.registers.get_hl(), source_value)
+                        LoadWordTarget::BC => self.registers.set_bc(source_value),
+                        LoadWordTarget::DE => self.registers.set_de(source_value),
+                        LoadWordTarget::HL => self.registers.set_hl(source_value),
+                        LoadWordTarget::SP => self.sp = source_value,
+                        LoadWordTarget::A16 => {
+                            let address = self.read_next_word();
+                            self.bus.write_word(address, source_value);
                         }
                     }
-                    match source {
-                        LoadByteSource::D8 => self.pc.wrapping_add(2),
-                        _ => self.pc.wrapping_add(1),
-                    }
+                    self.pc.wrapping_add(length)
+                }
+                LoadType::IndirectFromA(indirect) => {
+                    let address = self.indirect_address(indirect);
+                    self.bus.write_byte(address, self.registers.a);
+                    self.pc.wrapping_add(1)
+                }
+                LoadType::AFromIndirect(indirect) => {
+                    let address = self.indirect_address(indirect);
+                    self.registers.a = self.bus.read_byte(address);
+                    self.pc.wrapping_add(1)
+                }
+                LoadType::AbsoluteFromA => {
+                    let address = self.read_next_word();
+                    self.bus.write_byte(address, self.registers.a);
+                    self.pc.wrapping_add(3)
+                }
+                LoadType::AFromAbsolute => {
+                    let address = self.read_next_word();
+                    self.registers.a = self.bus.read_byte(address);
+                    self.pc.wrapping_add(3)
+                }
+                LoadType::HighFromA(offset) => {
+                    let address = self.high_address(&offset);
+                    self.bus.write_byte(address, self.registers.a);
+                    self.pc.wrapping_add(high_offset_length(&offset))
+                }
+                LoadType::AFromHigh(offset) => {
+                    let address = self.high_address(&offset);
+                    self.registers.a = self.bus.read_byte(address);
+                    self.pc.wrapping_add(high_offset_length(&offset))
                 }
             },
             Instruction::CALL(test) => {
-                let jump_condition = match test {
-                    JumpTest::NotZero => !self.registers.f.zero,
-                    _ => { panic!("TODO: support more conditions") }
-                };
+                let jump_condition = self.check_jump_test(test);
+                self.branch_taken = jump_condition;
                 self.call(jump_condition)
-            },
+            }
             Instruction::RET(test) => {
-                let jump_condition = match test {
-                    JumpTest::NotZero => !self.registers.f.zero,
-                    _ => { panic!("TODO: support more conditions") }
-                };
+                let jump_condition = self.check_jump_test(test);
+                self.branch_taken = jump_condition;
                 self.return_(jump_condition)
             }
+            Instruction::RETI => {
+                // EIと違い、IMEの有効化は遅延しない
+                self.ime = true;
+                self.return_(true)
+            }
+            Instruction::PUSH(target) => {
+                let value = match target {
+                    StackTarget::BC => self.registers.get_bc(),
+                    StackTarget::DE => self.registers.get_de(),
+                    StackTarget::HL => self.registers.get_hl(),
+                    StackTarget::AF => self.registers.get_af(),
+                };
+                self.push(value);
+                self.pc.wrapping_add(1)
+            }
+            Instruction::RST(vector) => {
+                // CALLと同じく戻り先（RSTは1バイトなのでpc + 1）をスタックに積む
+                self.push(self.pc.wrapping_add(1));
+                vector as u16
+            }
+            Instruction::POP(target) => {
+                let value = self.pop();
+                match target {
+                    StackTarget::BC => self.registers.set_bc(value),
+                    StackTarget::DE => self.registers.set_de(value),
+                    StackTarget::HL => self.registers.set_hl(value),
+                    // set_afを通すのでFの下位4ビットは0になる
+                    StackTarget::AF => self.registers.set_af(value),
+                }
+                self.pc.wrapping_add(1)
+            }
+        }
+    }
+
+    fn check_jump_test(&self, test: JumpTest) -> bool {
+        match test {
+            JumpTest::NotZero => !self.registers.f.zero,
+            JumpTest::Zero => self.registers.f.zero,
+            JumpTest::NotCarry => !self.registers.f.carry,
+            JumpTest::Carry => self.registers.f.carry,
+            JumpTest::Always => true,
+        }
+    }
+
+    // (BC)、(DE)、(HL+)、(HL-)が指すアドレス
+    // (HL+)と(HL-)はアドレスを決めたあとにHLを増減する
+    fn indirect_address(&mut self, indirect: Indirect) -> u16 {
+        match indirect {
+            Indirect::BC => self.registers.get_bc(),
+            Indirect::DE => self.registers.get_de(),
+            Indirect::HLInc => {
+                let hl = self.registers.get_hl();
+                self.registers.set_hl(hl.wrapping_add(1));
+                hl
+            }
+            Indirect::HLDec => {
+                let hl = self.registers.get_hl();
+                self.registers.set_hl(hl.wrapping_sub(1));
+                hl
+            }
+        }
+    }
+
+    // 0xFF00 + オフセットのアドレス（I/Oレジスタと高位RAM）
+    fn high_address(&self, offset: &HighOffset) -> u16 {
+        let offset = match offset {
+            HighOffset::A8 => self.read_next_byte(),
+            HighOffset::C => self.registers.c,
+        };
+        0xFF00 | offset as u16
+    }
+
+    fn read_next_byte(&self) -> u8 {
+        self.bus.read_byte(self.pc.wrapping_add(1))
+    }
+
+    fn read_next_word(&self) -> u16 {
+        self.bus.read_word(self.pc.wrapping_add(1))
+    }
+
+    // ALU命令: 対象の値とAで演算した結果をAに入れ、次の命令のアドレスを返す
+    // ディスパッチテーブルからも、enumのmatchを通らずに直接呼ばれる
+    pub(crate) fn arithmetic(
+        &mut self,
+        target: ArithmeticTarget,
+        operation: fn(&mut Self, u8) -> u8,
+    ) -> u16 {
+        let value = self.read_arithmetic_target(target);
+        self.registers.a = operation(self, value);
+        self.arithmetic_next_pc(target)
+    }
+
+    // INC/DEC r: 対象の値に演算した結果を書き戻す
+    pub(crate) fn inc_dec(
+        &mut self,
+        target: IncDecTarget,
+        operation: fn(&mut Self, u8) -> u8,
+    ) -> u16 {
+        let value = self.read_inc_dec_target(target);
+        let new_value = operation(self, value);
+        self.write_inc_dec_target(target, new_value);
+        self.pc.wrapping_add(1)
+    }
+
+    // LD r,r' / LD r,d8 / LD r,(HL) / LD (HL),r
+    pub(crate) fn load_byte(&mut self, target: LoadByteTarget, source: LoadByteSource) -> u16 {
+        let source_value = match source {
+            LoadByteSource::A => self.registers.a,
+            LoadByteSource::B => self.registers.b,
+            LoadByteSource::C => self.registers.c,
+            LoadByteSource::D => self.registers.d,
+            LoadByteSource::E => self.registers.e,
+            LoadByteSource::H => self.registers.h,
+            LoadByteSource::L => self.registers.l,
+            LoadByteSource::D8 => self.read_next_byte(),
+            LoadByteSource::HLI => self.bus.read_byte(self.registers.get_hl()),
+        };
+        match target {
+            LoadByteTarget::A => self.registers.a = source_value,
+            LoadByteTarget::B => self.registers.b = source_value,
+            LoadByteTarget::C => self.registers.c = source_value,
+            LoadByteTarget::D => self.registers.d = source_value,
+            LoadByteTarget::E => self.registers.e = source_value,
+            LoadByteTarget::H => self.registers.h = source_value,
+            LoadByteTarget::L => self.registers.l = source_value,
+            LoadByteTarget::HLI => self.bus.write_byte(self.registers.get_hl(), source_value),
+        }
+        match source {
+            LoadByteSource::D8 => self.pc.wrapping_add(2),
+            _ => self.pc.wrapping_add(1),
+        }
+    }
+
+    fn read_arithmetic_target(&self, target: ArithmeticTarget) -> u8 {
+        match target {
+            ArithmeticTarget::A => self.registers.a,
+            ArithmeticTarget::B => self.registers.b,
+            ArithmeticTarget::C => self.registers.c,
+            ArithmeticTarget::D => self.registers.d,
+            ArithmeticTarget::E => self.registers.e,
+            ArithmeticTarget::H => self.registers.h,
+            ArithmeticTarget::L => self.registers.l,
+            ArithmeticTarget::HLI => self.bus.read_byte(self.registers.get_hl()),
+            ArithmeticTarget::D8 => self.read_next_byte(),
+        }
+    }
+
+    fn arithmetic_next_pc(&self, target: ArithmeticTarget) -> u16 {
+        match target {
+            ArithmeticTarget::D8 => self.pc.wrapping_add(2),
+            _ => self.pc.wrapping_add(1),
+        }
+    }
+
+    fn read_inc_dec_target(&self, target: IncDecTarget) -> u8 {
+        match target {
+            IncDecTarget::A => self.registers.a,
+            IncDecTarget::B => self.registers.b,
+            IncDecTarget::C => self.registers.c,
+            IncDecTarget::D => self.registers.d,
+            IncDecTarget::E => self.registers.e,
+            IncDecTarget::H => self.registers.h,
+            IncDecTarget::L => self.registers.l,
+            IncDecTarget::HLI => self.bus.read_byte(self.registers.get_hl()),
+        }
+    }
+
+    fn write_inc_dec_target(&mut self, target: IncDecTarget, value: u8) {
+        match target {
+            IncDecTarget::A => self.registers.a = value,
+            IncDecTarget::B => self.registers.b = value,
+            IncDecTarget::C => self.registers.c = value,
+            IncDecTarget::D => self.registers.d = value,
+            IncDecTarget::E => self.registers.e = value,
+            IncDecTarget::H => self.registers.h = value,
+            IncDecTarget::L => self.registers.l = value,
+            IncDecTarget::HLI => self.bus.write_byte(self.registers.get_hl(), value),
+        }
+    }
+
+    fn read_prefix_target(&self, target: PrefixTarget) -> u8 {
+        match target {
+            PrefixTarget::A => self.registers.a,
+            PrefixTarget::B => self.registers.b,
+            PrefixTarget::C => self.registers.c,
+            PrefixTarget::D => self.registers.d,
+            PrefixTarget::E => self.registers.e,
+            PrefixTarget::H => self.registers.h,
+            PrefixTarget::L => self.registers.l,
+            PrefixTarget::HLI => self.bus.read_byte(self.registers.get_hl()),
         }
     }
 
-    fn read_next_byte(&mut self) -> u8 {
-        self.bus.read_byte(self.pc + 1)
+    fn write_prefix_target(&mut self, target: PrefixTarget, value: u8) {
+        match target {
+            PrefixTarget::A => self.registers.a = value,
+            PrefixTarget::B => self.registers.b = value,
+            PrefixTarget::C => self.registers.c = value,
+            PrefixTarget::D => self.registers.d = value,
+            PrefixTarget::E => self.registers.e = value,
+            PrefixTarget::H => self.registers.h = value,
+            PrefixTarget::L => self.registers.l = value,
+            PrefixTarget::HLI => self.bus.write_byte(self.registers.get_hl(), value),
+        }
+    }
+
+    fn read_word_register(&self, register: WordRegister) -> u16 {
+        match register {
+            WordRegister::BC => self.registers.get_bc(),
+            WordRegister::DE => self.registers.get_de(),
+            WordRegister::HL => self.registers.get_hl(),
+            WordRegister::SP => self.sp,
+        }
     }
 
-    fn read_next_word(&mut self) -> u16 {
-        let lsb = self.bus.read_byte(self.pc + 1) as u16;
-        let msb = self.bus.read_byte(self.pc + 2) as u16;
-        return (msb << 8) | lsb;
+    fn write_word_register(&mut self, register: WordRegister, value: u16) {
+        match register {
+            WordRegister::BC => self.registers.set_bc(value),
+            WordRegister::DE => self.registers.set_de(value),
+            WordRegister::HL => self.registers.set_hl(value),
+            WordRegister::SP => self.sp = value,
+        }
     }
 
-    fn add(&mut self, value: u8) -> u8 {
+    pub(crate) fn add(&mut self, value: u8) -> u8 {
         let (new_value, did_overflow) = self.registers.a.overflowing_add(value);
         self.registers.f.zero = new_value == 0;
         // addなのでsubtractはfalse
@@ -110,194 +722,2764 @@ impl CPU {
         new_value
     }
 
-    // should_jumpがtrueの場合はジャンプ命令の次と次に飛び先が書いてあるから、飛び先を取得する
-    // should_jumpがfalseの場合は２バイトを無視しないといけないので3バイト進める
-    // +-------------+-------------- +--------------+
-    // | Instruction | Least Signif- | Most Signif- |
-    // | Identifier  | icant Byte    | icant Byte   |
-    // +-------------+-------------- +--------------+
-    fn jump(&self, should_jump: bool) -> u16 {
-        if should_jump {
-            // 16ビットのアドレスを取得する
-            // 最下位バイトはself.pc + 1のアドレスを読み込む
-            // 最上位バイトはself.pc + 2のアドレスを読み込む
-            // それらを組み合わせて16ビットのアドレスを取得する
-            let least_significant_byte = self.bus.read_byte(self.pc + 1) as u16;
-            let most_significant_byte = self.bus.read_byte(self.pc + 2) as u16;
-            // little endianなので最下位バイトが先に来る
-            (most_significant_byte << 8) | least_significant_byte
+    pub(crate) fn adc(&mut self, value: u8) -> u8 {
+        let carry = if self.registers.f.carry { 1 } else { 0 };
+        // キャリーを足した結果もオーバーフローし得るので、u16で計算する
+        // 例: 0xFF + 0xFF + 1 = 0x1FF
+        let result = self.registers.a as u16 + value as u16 + carry as u16;
+        let new_value = result as u8;
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.carry = result > 0xFF;
+        self.registers.f.half_carry = (self.registers.a & 0xF) + (value & 0xF) + carry > 0xF;
+        new_value
+    }
+
+    pub(crate) fn sub(&mut self, value: u8) -> u8 {
+        let (new_value, did_overflow) = self.registers.a.overflowing_sub(value);
+        self.registers.f.zero = new_value == 0;
+        // subなのでsubtractはtrue
+        self.registers.f.subtract = true;
+        // ボローが発生したらcarryはtrue
+        self.registers.f.carry = did_overflow;
+        // 下位ニブルでボローが発生したらhalf_carryはtrue
+        self.registers.f.half_carry = (self.registers.a & 0xF) < (value & 0xF);
+        new_value
+    }
+
+    // subと同じフラグ計算を行うが、結果は捨ててAをそのまま返す
+    pub(crate) fn compare(&mut self, value: u8) -> u8 {
+        self.sub(value);
+        self.registers.a
+    }
+
+    pub(crate) fn sbc(&mut self, value: u8) -> u8 {
+        let carry = if self.registers.f.carry { 1 } else { 0 };
+        // value + carryが0x100になり得るので、u16で比較する
+        // 例: 0x00 - 0x00 - 1 = 0xFF（ボローが発生）
+        let subtrahend = value as u16 + carry as u16;
+        let new_value = (self.registers.a as u16).wrapping_sub(subtrahend) as u8;
+        self.registers.f.zero = new_value == 0;
+        // subなのでsubtractはtrue
+        self.registers.f.subtract = true;
+        self.registers.f.carry = (self.registers.a as u16) < subtrahend;
+        self.registers.f.half_carry = (self.registers.a & 0xF) < (value & 0xF) + carry;
+        new_value
+    }
+
+    pub(crate) fn and(&mut self, value: u8) -> u8 {
+        let new_value = self.registers.a & value;
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        // ハードウェアの仕様でANDのみhalf_carryが常にtrueになる
+        self.registers.f.half_carry = true;
+        self.registers.f.carry = false;
+        new_value
+    }
+
+    pub(crate) fn or(&mut self, value: u8) -> u8 {
+        let new_value = self.registers.a | value;
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = false;
+        new_value
+    }
+
+    pub(crate) fn xor(&mut self, value: u8) -> u8 {
+        let new_value = self.registers.a ^ value;
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = false;
+        new_value
+    }
+
+    // 16ビットの加算ではzeroは変更しない
+    fn add_hl(&mut self, value: u16) {
+        let hl = self.registers.get_hl();
+        let (new_value, did_overflow) = hl.overflowing_add(value);
+        self.registers.f.subtract = false;
+        self.registers.f.carry = did_overflow;
+        // 8ビットのときと違い、ビット11からの繰り上がりでhalf_carryを判定する
+        self.registers.f.half_carry = (hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF;
+        self.registers.set_hl(new_value);
+    }
+
+    // pc+1の符号付き即値をSPに足した値を返す（SP自体は変更しない）
+    // フラグは下位バイト同士を符号なしで足したときの繰り上がりで決まる
+    fn add_sp_signed(&mut self) -> u16 {
+        let value = self.read_next_byte();
+        let sp = self.sp;
+        self.registers.f.zero = false;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = (sp & 0x0F) + (value as u16 & 0x0F) > 0x0F;
+        self.registers.f.carry = (sp & 0xFF) + value as u16 > 0xFF;
+        sp.wrapping_add_signed(value as i8 as i16)
+    }
+
+    // incはcarryを変更しない
+    pub(crate) fn inc(&mut self, value: u8) -> u8 {
+        let new_value = value.wrapping_add(1);
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        // 下位ニブルが0xFから繰り上がったらhalf_carryはtrue
+        self.registers.f.half_carry = value & 0xF == 0xF;
+        new_value
+    }
+
+    // decもcarryを変更しない
+    pub(crate) fn dec(&mut self, value: u8) -> u8 {
+        let new_value = value.wrapping_sub(1);
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = true;
+        // 下位ニブルが0x0から繰り下がったらhalf_carryはtrue
+        self.registers.f.half_carry = value & 0xF == 0x0;
+        new_value
+    }
+
+    // 直前の加算/減算の結果をBCD（二進化十進数）に補正する
+    // subtractは変更せず、直前の演算が加算か減算かの判定に使う
+    fn decimal_adjust(&mut self) {
+        let mut a = self.registers.a;
+        let mut carry = self.registers.f.carry;
+        if !self.registers.f.subtract {
+            // 加算の後: 各ニブルが9を超えたか、繰り上がりがあれば6を足す
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
+            }
+            if self.registers.f.half_carry || (a & 0x0F) > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
         } else {
-            // ジャンプしない場合は3バイト進める
-            self.pc.wrapping_add(3)
+            // 減算の後: 繰り下がりがあったニブルから6を引く
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if self.registers.f.half_carry {
+                a = a.wrapping_sub(0x06);
+            }
         }
+        self.registers.a = a;
+        self.registers.f.zero = a == 0;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = carry;
     }
 
-    pub fn step(&mut self) {
-        let mut instruction_byte = self.bus.read_byte(self.pc);
-        let prefixed = instruction_byte == 0xCB;
-        if prefixed {
-            instruction_byte = self.bus.read_byte(self.pc + 1);
-        }
-        let next_pc = if let Some(instruction) = Instruction::from_byte(instruction_byte, prefixed)
-        {
-            self.execute(instruction)
+    // through_carryがtrueの場合はキャリーを経由して回転する（RL/RLA）
+    // falseの場合は押し出されたビット7がそのままビット0に入る（RLC/RLCA）
+    fn rotate_left(&mut self, value: u8, through_carry: bool) -> u8 {
+        let bit0 = if through_carry {
+            self.registers.f.carry as u8
         } else {
-            let description = format!(
-                "0x{}{:02X}",
-                if prefixed { "CB" } else { "" },
-                instruction_byte
-            );
-            panic!("Unkown instruction found for: {}", description)
+            value >> 7
         };
+        let new_value = (value << 1) | bit0;
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = value & 0x80 != 0;
+        new_value
+    }
 
-        self.pc = next_pc;
+    fn rotate_right(&mut self, value: u8, through_carry: bool) -> u8 {
+        let bit7 = if through_carry {
+            self.registers.f.carry as u8
+        } else {
+            value & 0x01
+        };
+        let new_value = (value >> 1) | (bit7 << 7);
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = value & 0x01 != 0;
+        new_value
     }
 
-    fn push(&mut self, value: u16) {
-        // 最上位バイトをスタックにプッシュ
-        self.sp = self.sp.wrapping_sub(1);
-        self.bus.write_byte(self.sp, ((value & 0xFF00) >> 8) as u8);
+    // 左シフトしてビット7をcarryに出す。ビット0には0が入る
+    fn shift_left_arithmetic(&mut self, value: u8) -> u8 {
+        let new_value = value << 1;
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = value & 0x80 != 0;
+        new_value
+    }
 
-        // 最下位バイトをスタックにプッシュ
-        self.sp = self.sp.wrapping_sub(1);
-        self.bus.write_byte(self.sp, (value & 0xFF) as u8);
+    // 算術右シフト: 符号ビット（ビット7）は保持される
+    fn shift_right_arithmetic(&mut self, value: u8) -> u8 {
+        let new_value = (value >> 1) | (value & 0x80);
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = value & 0x01 != 0;
+        new_value
     }
 
-    fn pop(&mut self) -> u16 {
-        let lsb = self.bus.read_byte(self.sp) as u16;
-        self.sp = self.sp.wrapping_add(1);
+    // 論理右シフト: ビット7には0が入る
+    fn shift_right_logical(&mut self, value: u8) -> u8 {
+        let new_value = value >> 1;
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = value & 0x01 != 0;
+        new_value
+    }
 
-        let msb = self.bus.read_byte(self.sp) as u16;
-        self.sp = self.sp.wrapping_add(1);
+    // 上位ニブルと下位ニブルを入れ替える
+    fn swap(&mut self, value: u8) -> u8 {
+        let new_value = value.rotate_left(4);
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = false;
+        new_value
+    }
 
-        (msb << 8) | lsb
+    // 指定したビットが0ならzeroを立てる。carryは変更しない
+    fn bit(&mut self, bit: u8, value: u8) {
+        self.registers.f.zero = value & (1 << bit) == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = true;
     }
 
-    fn call(&mut self, should_jump: bool) -> u16 {
-        let next_pc = self.pc.wrapping_add(3);
+    // should_jumpがtrueの場合はジャンプ命令の次と次に飛び先が書いてあるから、飛び先を取得する
+    // should_jumpがfalseの場合は２バイトを無視しないといけないので3バイト進める
+    // +-------------+-------------- +--------------+
+    // | Instruction | Least Signif- | Most Signif- |
+    // | Identifier  | icant Byte    | icant Byte   |
+    // +-------------+-------------- +--------------+
+    // JPのオペランドはpc + 1（下位バイト）とpc + 2（上位バイト）にある
+    fn jump(&self, should_jump: bool) -> u16 {
         if should_jump {
-            // 次のプログラムカウンタをストックにコピー
-            self.push(next_pc);
-            // 次の２バイトのメモリで指定されたアドレスに移動
             self.read_next_word()
         } else {
-            next_pc
+            // ジャンプしない場合は3バイト進める
+            self.pc.wrapping_add(3)
         }
     }
 
-    fn return_(&mut self, should_jump: bool) -> u16 {
+    // JRは命令の次の1バイトを符号付きのオフセットとして扱う
+    // 飛び先はJR命令の次の命令（pc + 2）を基準にする
+    fn jump_relative(&self, should_jump: bool) -> u16 {
+        let next_pc = self.pc.wrapping_add(2);
         if should_jump {
-            self.pop()
+            // i8として解釈することで0xFEは-2になり、後ろ向きにジャンプできる
+            let offset = self.read_next_byte() as i8;
+            next_pc.wrapping_add_signed(offset as i16)
         } else {
-            self.pc.wrapping_add(1)
+            next_pc
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+}
+
+impl CPU {
+    // 倍速モード（ゲームボーイカラー）で動いているか
+    pub fn double_speed(&self) -> bool {
+        self.bus.double_speed()
+    }
+
+    // reset_countersから実行した命令の数（割り込み処理やHALT中の待ちは含まない）
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    // reset_countersから経過したTサイクル数
+    pub fn cycles_elapsed(&self) -> u64 {
+        self.cycles_elapsed
+    }
+
+    pub fn reset_counters(&mut self) {
+        self.instructions_executed = 0;
+        self.cycles_elapsed = 0;
+    }
+
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        self.step_with_cycles()?;
+        Ok(())
+    }
+
+    // gameboy-doctorが比較に使う形式で現在の状態を返す
+    // 例: A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+    pub fn log_state(&self) -> String {
+        let pcmem: Vec<String> = (0..4)
+            .map(|offset| format!("{:02X}", self.bus.peek(self.pc.wrapping_add(offset))))
+            .collect();
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+            self.registers.a,
+            u8::from(self.registers.f),
+            self.registers.b,
+            self.registers.c,
+            self.registers.d,
+            self.registers.e,
+            self.registers.h,
+            self.registers.l,
+            self.sp,
+            self.pc,
+            pcmem.join(",")
+        )
+    }
+
+    // trace_lineでラベルとして表示するアドレスと名前を設定する
+    pub fn set_symbols(&mut self, symbols: HashMap<u16, String>) {
+        self.symbols = symbols;
+    }
+
+    // デバッガのログ用に、pcの命令を逆アセンブルしてレジスタと一緒に1行にする
+    // 例: 0x0150: LD A,B        A:42 F:Z-H- BC:0013 DE:00D8 HL:014D SP:FFFE
+    // ラベルが設定されているアドレスは、pcの後ろや命令のオペランドにラベルを表示する
+    pub fn trace_line(&self) -> String {
+        let (mut text, _) = Instruction::disassemble(&self.bus, self.pc);
+        for (address, name) in &self.symbols {
+            text = text.replace(&format!("${:04X}", address), name);
+        }
+        let location = match self.symbols.get(&self.pc) {
+            Some(name) => format!("0x{:04X} {}", self.pc, name),
+            None => format!("0x{:04X}", self.pc),
+        };
+        format!(
+            "{}: {:<14}A:{:02X} F:{} BC:{:04X} DE:{:04X} HL:{:04X} SP:{:04X}",
+            location,
+            text,
+            self.registers.a,
+            self.registers.f,
+            self.registers.get_bc(),
+            self.registers.get_de(),
+            self.registers.get_hl(),
+            self.sp
+        )
+    }
+
+    // レジスタとフラグ、割り込みとHALTの状態をまとめて返す
+    pub fn debug_state(&self) -> CpuDebugState {
+        let registers = &self.registers;
+        CpuDebugState {
+            a: registers.a,
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            f: u8::from(registers.f),
+            h: registers.h,
+            l: registers.l,
+            zero: registers.f.zero,
+            subtract: registers.f.subtract,
+            half_carry: registers.f.half_carry,
+            carry: registers.f.carry,
+            pc: self.pc,
+            sp: self.sp,
+            ime: self.ime,
+            halted: self.halted,
+        }
+    }
+
+    // 各ステップの前にlog_stateの行を書き出すようにする
+    pub fn enable_trace(&mut self, output: Box<dyn Write>) {
+        self.trace_output = Some(output);
+    }
+
+    // フレームを描き終えるたびに（VBlankの始まりで）フレームバッファを渡して呼ぶ関数を設定する
+    pub fn set_frame_callback(&mut self, callback: FrameCallback) {
+        self.frame_callback = Some(callback);
+    }
+
+    // CPUと周辺機器の状態をすべてバイト列にする（セーブステート）
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new();
+        self.save_state(&mut writer);
+        writer.finish()
+    }
+
+    // snapshotで作ったバイト列の状態に戻す
+    // 途中で失敗した場合は、呼び出す前の状態のまま変わらない
+    pub fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data)?;
+        let backup = self.snapshot();
+        self.load_state(&mut reader).inspect_err(|_| {
+            let mut reader = SnapshotReader::new(&backup).unwrap();
+            self.load_state(&mut reader).unwrap();
+        })
+    }
+
+    // 入力の記録を始める（以降のrun_frameのフレームを0から数える）
+    pub fn start_recording(&mut self) {
+        self.input_recorder = Some(InputRecorder::default());
+    }
+
+    // 入力の記録を止め、記録した内容を返す
+    pub fn stop_recording(&mut self) -> InputLog {
+        self.input_recorder
+            .take()
+            .map(InputRecorder::finish)
+            .unwrap_or_default()
+    }
+
+    // 記録した入力を、次のrun_frameから順に再生する
+    // 記録を始めたときと同じ状態から再生すれば、同じ状態にたどり着く
+    pub fn replay(&mut self, log: &InputLog) {
+        self.input_replayer = Some(InputReplayer::new(log));
+    }
+
+    // 直前のdepthステップまで、step_backで戻れるようにする
+    // 0を指定すると無効になり、溜めていたスナップショットも捨てる
+    pub fn enable_step_back(&mut self, depth: usize) {
+        self.history_depth = depth;
+        while self.history.len() > depth {
+            self.history.pop_front();
+        }
+    }
+
+    // 最後に実行したステップの直前の状態に戻す
+    // 戻れるスナップショットがなければfalseを返す
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(snapshot) => self.restore(&snapshot).is_ok(),
+            None => false,
+        }
+    }
+
+    // ステップを実行する前の状態を溜め、深さかメモリの上限を超えた分は古いものから捨てる
+    fn record_history(&mut self) {
+        let snapshot = self.snapshot();
+        let limit = self
+            .history_depth
+            .min(HISTORY_MEMORY_LIMIT / snapshot.len())
+            .max(1);
+        while self.history.len() >= limit {
+            self.history.pop_front();
+        }
+        self.history.push_back(snapshot);
+    }
+
+    // 1フレーム分の命令を実行し、描画し終えたフレームバッファを返す
+    // フレームの区切りは電源投入からのサイクル数で決まるので、
+    // 命令の途中でフレームをまたいだ分は次のフレームから差し引かれる
+    pub fn run_frame(&mut self) -> Result<&[u8], CpuError> {
+        if let Some(replayer) = self.input_replayer.as_mut() {
+            if let Some(buttons) = replayer.next_frame() {
+                self.bus.set_buttons(buttons);
+            }
+            if replayer.is_finished() {
+                self.input_replayer = None;
+            }
+        }
+        if let Some(recorder) = self.input_recorder.as_mut() {
+            recorder.record(self.bus.buttons());
+        }
+        let frame_end = (self.cycles / CYCLES_PER_FRAME + 1) * CYCLES_PER_FRAME;
+        while self.cycles < frame_end {
+            self.step()?;
+        }
+        Ok(self.bus.ppu.framebuffer())
+    }
+
+    // 実機でwall_msミリ秒かかる分のサイクルを実行する
+    // スロットル中（set_throttleで有効にした場合）は、実時間でwall_msミリ秒が経つまで待ってから戻る
+    // 無効なら待たずにすぐ戻るので、早送りやテストに使える
+    pub fn run_for_duration(&mut self, wall_ms: u64) -> Result<(), CpuError> {
+        let start = self.throttle_clock.map(|clock| clock());
+        let end = self.cycles + wall_ms * CLOCK_SPEED as u64 / 1000;
+        while self.cycles < end {
+            self.step()?;
+        }
+        if let (Some(clock), Some(start)) = (self.throttle_clock, start) {
+            loop {
+                let elapsed = clock().saturating_sub(start);
+                if elapsed >= wall_ms {
+                    break;
+                }
+                (self.throttle_sleep)(wall_ms - elapsed);
+            }
+        }
+        Ok(())
+    }
+
+    // run_for_durationの実行速度を実機に合わせるかどうか
+    pub fn set_throttle(&mut self, enabled: bool) {
+        self.throttle_clock = enabled.then_some(host_clock as fn() -> u64);
+        self.throttle_sleep = host_sleep;
+    }
+
+    // 実時間の代わりに指定した時計（ミリ秒）と待ち方で速度を合わせる
+    pub fn set_throttle_clock(&mut self, clock: fn() -> u64, sleep: fn(u64)) {
+        self.throttle_clock = Some(clock);
+        self.throttle_sleep = sleep;
+    }
+
+    // ブレークポイントのアドレスに着くまで命令を実行し続け、そのときのpcを返す
+    // ブレークポイントの命令自体は実行しない
+    // HALTやSTOPで止まった場合も、その時点のpcを返す
+    pub fn run_until_breakpoint(&mut self, breakpoints: &HashSet<u16>) -> Result<u16, CpuError> {
+        loop {
+            self.step()?;
+            if breakpoints.contains(&self.pc) || self.halted || self.stopped {
+                return Ok(self.pc);
+            }
+        }
+    }
+
+    // 1命令（または割り込み処理）を実行し、消費したTサイクル数を返す
+    pub fn step_with_cycles(&mut self) -> Result<u8, CpuError> {
+        if self.history_depth > 0 {
+            self.record_history();
+        }
+        if self.trace_output.is_some() {
+            let line = self.log_state();
+            if let Some(output) = self.trace_output.as_mut() {
+                // ログの書き込みに失敗してもエミュレーションは続ける
+                let _ = writeln!(output, "{}", line);
+            }
+        }
+        let cycles = self.run_one()?;
+        // STOP中はタイマーやPPUも止まっている
+        if !self.stopped
+            && self.bus.tick(cycles)
+            && let Some(callback) = self.frame_callback.as_mut()
+        {
+            callback(self.bus.ppu.framebuffer());
+        }
+        self.cycles += cycles as u64;
+        self.cycles_elapsed += cycles as u64;
+        Ok(cycles)
+    }
+
+    fn run_one(&mut self) -> Result<u8, CpuError> {
+        // STOP中はボタンが押されるまで何もしない
+        if self.stopped {
+            if !self.bus.any_button_pressed() {
+                return Ok(HALTED_CYCLES);
+            }
+            self.stopped = false;
+        }
+
+        // HALT中は割り込みが要求されるまで何もしない
+        // IMEが無効でも、要求があればHALTから復帰して次の命令に進む
+        if self.halted {
+            if self.pending_interrupts() == 0 {
+                return Ok(HALTED_CYCLES);
+            }
+            self.halted = false;
+        }
+
+        self.bus.set_current_pc(self.pc);
+
+        // 割り込みを処理した場合は、このステップでは命令を実行しない
+        if self.handle_interrupt() {
+            return Ok(INTERRUPT_DISPATCH_CYCLES);
+        }
+
+        // 直前の命令がEIだった場合は、この命令を実行したあとにIMEを有効にする
+        let enable_ime = self.ime_pending;
+        let mut instruction_byte = self.bus.read_byte(self.pc);
+        let prefixed = instruction_byte == 0xCB;
+        if prefixed {
+            instruction_byte = self.bus.read_byte(self.pc.wrapping_add(1));
+        }
+        let Some(handler) = dispatch::handler(instruction_byte, prefixed) else {
+            // 0xCB以外のすべてのバイトがデコードできるので、テーブルにないのは未使用のオペコードだけ
+            match Instruction::from_byte(instruction_byte, prefixed) {
+                Some(Instruction::Illegal(byte)) => {
+                    return Err(CpuError::IllegalOpcode { byte, pc: self.pc });
+                }
+                _ => unreachable!("0x{:02X} is not in the dispatch table", instruction_byte),
+            }
+        };
+        if self.halt_bug {
+            // フェッチ後にpcが進まなかったことにする
+            // オペランドはオペコードと同じバイトから読まれ、1バイト命令は2回実行される
+            self.halt_bug = false;
+            self.pc = self.pc.wrapping_sub(1);
+        }
+        let cycles = handler(self);
+        self.instructions_executed += 1;
+
+        // この命令がDIだった場合はime_pendingが取り消されている
+        if enable_ime && self.ime_pending {
+            self.ime = true;
+            self.ime_pending = false;
+        }
+
+        Ok(cycles)
+    }
+
+    // 命令を実行してpcを進め、消費したTサイクル数を返す
+    // ディスパッチテーブルから呼ばれる
+    pub(crate) fn execute_opcode(
+        &mut self,
+        instruction: Instruction,
+        byte: u8,
+        prefixed: bool,
+    ) -> u8 {
+        self.branch_taken = false;
+        self.pc = self.execute(instruction);
+        Instruction::cycles(byte, prefixed, self.branch_taken)
+    }
+
+    // IMEが有効で、IEとIFの両方で立っている割り込みがあれば処理する
+    // ビット0（VBlank）が最も優先度が高い
+    // | bit | 割り込み | ベクタ |
+    // |  0  | VBlank   | 0x40   |
+    // |  1  | LCD STAT | 0x48   |
+    // |  2  | Timer    | 0x50   |
+    // |  3  | Serial   | 0x58   |
+    // |  4  | Joypad   | 0x60   |
+    fn handle_interrupt(&mut self) -> bool {
+        if !self.ime {
+            return false;
+        }
+        let Some(interrupt) = interrupt::highest_priority(self.pending_interrupts()) else {
+            return false;
+        };
+
+        let interrupt_flag = self.bus.read_byte(INTERRUPT_FLAG_ADDRESS);
+        self.bus
+            .write_byte(INTERRUPT_FLAG_ADDRESS, interrupt_flag & !interrupt.bit());
+        self.ime = false;
+        self.push(self.pc);
+        self.pc = interrupt.vector();
+        true
+    }
+}
+
+impl<M: Memory> CPU<M> {
+    // IEとIFの両方で立っている割り込み
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    // 実機と同じく上位バイトから書き込むので、write_wordは使わない
+    fn push(&mut self, value: u16) {
+        // 最上位バイトをスタックにプッシュ
+        self.sp = self.sp.wrapping_sub(1);
+        self.bus.write_byte(self.sp, ((value & 0xFF00) >> 8) as u8);
+
+        // 最下位バイトをスタックにプッシュ
+        self.sp = self.sp.wrapping_sub(1);
+        self.bus.write_byte(self.sp, (value & 0xFF) as u8);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let value = self.bus.read_word(self.sp);
+        self.sp = self.sp.wrapping_add(2);
+        value
+    }
+
+    fn call(&mut self, should_jump: bool) -> u16 {
+        let next_pc = self.pc.wrapping_add(3);
+        if should_jump {
+            // 次のプログラムカウンタをストックにコピー
+            self.push(next_pc);
+            // 次の２バイトのメモリで指定されたアドレスに移動
+            self.read_next_word()
+        } else {
+            next_pc
+        }
+    }
+
+    fn return_(&mut self, should_jump: bool) -> u16 {
+        if should_jump {
+            self.pop()
+        } else {
+            self.pc.wrapping_add(1)
+        }
+    }
+}
+
+impl Snapshot for CPU {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        self.registers.save_state(writer);
+        writer.write_u16(self.pc);
+        writer.write_u16(self.sp);
+        writer.write_bool(self.ime);
+        writer.write_bool(self.ime_pending);
+        writer.write_bool(self.halted);
+        writer.write_bool(self.stopped);
+        writer.write_bool(self.halt_bug);
+        writer.write_bool(self.branch_taken);
+        writer.write_u64(self.cycles);
+        self.bus.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.registers.load_state(reader)?;
+        self.pc = reader.read_u16()?;
+        self.sp = reader.read_u16()?;
+        self.ime = reader.read_bool()?;
+        self.ime_pending = reader.read_bool()?;
+        self.halted = reader.read_bool()?;
+        self.stopped = reader.read_bool()?;
+        self.halt_bug = reader.read_bool()?;
+        self.branch_taken = reader.read_bool()?;
+        self.cycles = reader.read_u64()?;
+        self.bus.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{CB_CYCLES, CYCLES};
+    use crate::memory::KEY1_ADDRESS;
+    use crate::registers::FlagsRegister;
+    use crate::watch::WatchKind;
+
+    #[test]
+    fn test_load_cartridge() {
+        let path = std::env::temp_dir().join("gameboy_emulator_test_load_cartridge.gb");
+        let mut rom = vec![0x00; 0x0150];
+        rom[0x0100] = 0x00; // NOP
+        rom[0x0101] = 0xC3; // JP 0x0150
+        rom[0x0102] = 0x50;
+        rom[0x0103] = 0x01;
+        std::fs::write(&path, &rom).unwrap();
+
+        let cpu = CPU::load_cartridge(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cpu.pc, 0x0100);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.bus.read_byte(0x0101), 0xC3);
+        assert_eq!(cpu.bus.read_byte(0x0103), 0x01);
+    }
+
+    // リセット後はブートROMを実行し終えた直後のレジスタになる
+    #[test]
+    fn test_reset_post_boot_state() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x55;
+        cpu.pc = 0x1234;
+        cpu.halted = true;
+        cpu.reset();
+        assert_eq!(cpu.registers.get_af(), 0x01B0);
+        assert_eq!(cpu.registers.get_bc(), 0x0013);
+        assert_eq!(cpu.registers.get_de(), 0x00D8);
+        assert_eq!(cpu.registers.get_hl(), 0x014D);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.pc, 0x0100);
+        assert!(!cpu.ime);
+        assert!(!cpu.halted);
+        assert_eq!(cpu.bus.read_byte(LCDC_ADDRESS), 0x91);
+        assert_eq!(cpu.bus.read_byte(BGP_ADDRESS), 0xFC);
+        assert_eq!(cpu.bus.read_byte(INTERRUPT_FLAG_ADDRESS), 0xE1);
+        assert_eq!(cpu.bus.read_byte(INTERRUPT_ENABLE_ADDRESS), 0x00);
+    }
+
+    #[test]
+    fn test_load_cartridge_missing_file() {
+        assert!(CPU::load_cartridge("/nonexistent/gameboy_emulator.gb").is_err());
+    }
+
+    // addでオーバーフローが発生しない場合のテスト
+    #[test]
+    fn test_add_no_overflow() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x01;
+        cpu.registers.c = 0x02;
+        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0x03);
+    }
+
+    // addでオーバーフローが発生する場合のテスト
+    #[test]
+    fn test_add_overflow() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xFF;
+        cpu.registers.c = 0x01;
+        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0x00);
+    }
+
+    // addでhalf_carryが発生する場合のテスト
+    #[test]
+    fn test_add_half_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x0F;
+        cpu.registers.c = 0x01;
+        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0x10);
+    }
+
+    // ADD A,(HL)のテスト: HLが指すメモリの値を加算する
+    #[test]
+    fn test_add_memory() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.registers.a = 0x08;
+        cpu.registers.set_hl(0xC000);
+        cpu.bus.write_byte(0xC000, 0x08);
+        let next_pc = cpu.execute(Instruction::ADD(ArithmeticTarget::HLI));
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(cpu.registers.f.half_carry);
+        assert_eq!(next_pc, 0x0101);
+    }
+
+    #[test]
+    fn test_add_b() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x3A;
+        cpu.registers.b = 0xC6;
+        let next_pc = cpu.execute(Instruction::ADD(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // ADD A,AはAを2倍にし、フラグは2倍にした結果から決まる
+    #[test]
+    fn test_add_a() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x88;
+        let next_pc = cpu.execute(Instruction::ADD(ArithmeticTarget::A));
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // すべてのレジスタを対象にしたADDが結果をAに入れる
+    // B〜Lの値はそれぞれ0x02〜0x07で、Aの0x01に足した結果を対象ごとに決め打ちで確かめる
+    #[test]
+    fn test_add_all_registers() {
+        let cases = [
+            (ArithmeticTarget::B, 0x03),
+            (ArithmeticTarget::C, 0x04),
+            (ArithmeticTarget::D, 0x05),
+            (ArithmeticTarget::E, 0x06),
+            (ArithmeticTarget::H, 0x07),
+            (ArithmeticTarget::L, 0x08),
+        ];
+        for (target, expected_a) in cases {
+            let mut cpu = CPU::default();
+            cpu.registers.a = 0x01;
+            cpu.registers.b = 0x02;
+            cpu.registers.c = 0x03;
+            cpu.registers.d = 0x04;
+            cpu.registers.e = 0x05;
+            cpu.registers.h = 0x06;
+            cpu.registers.l = 0x07;
+            let next_pc = cpu.execute(Instruction::ADD(target));
+            assert_eq!(cpu.registers.a, expected_a, "{:?}", target);
+            assert_eq!(next_pc, 0x0001);
+        }
+    }
+
+    // 対象のレジスタだけに値を入れ、ほかは0にしてADDのフラグを確かめる
+    #[test]
+    fn test_add_all_registers_flags() {
+        let targets = [
+            ArithmeticTarget::B,
+            ArithmeticTarget::C,
+            ArithmeticTarget::D,
+            ArithmeticTarget::E,
+            ArithmeticTarget::H,
+            ArithmeticTarget::L,
+        ];
+        // (A, 対象の値, 結果, ハーフキャリー, キャリー)
+        let cases = [
+            (0xF0, 0x20, 0x10, false, true),
+            (0x0F, 0x01, 0x10, true, false),
+        ];
+        for target in targets {
+            for (a, value, expected_a, half_carry, carry) in cases {
+                let mut cpu = CPU::default();
+                cpu.registers.a = a;
+                let register = match target {
+                    ArithmeticTarget::B => &mut cpu.registers.b,
+                    ArithmeticTarget::C => &mut cpu.registers.c,
+                    ArithmeticTarget::D => &mut cpu.registers.d,
+                    ArithmeticTarget::E => &mut cpu.registers.e,
+                    ArithmeticTarget::H => &mut cpu.registers.h,
+                    ArithmeticTarget::L => &mut cpu.registers.l,
+                    _ => unreachable!(),
+                };
+                *register = value;
+                cpu.execute(Instruction::ADD(target));
+                assert_eq!(cpu.registers.a, expected_a, "{:?}", target);
+                assert!(!cpu.registers.f.zero);
+                assert!(!cpu.registers.f.subtract);
+                assert_eq!(cpu.registers.f.half_carry, half_carry, "{:?}", target);
+                assert_eq!(cpu.registers.f.carry, carry, "{:?}", target);
+            }
+        }
+    }
+
+    // ADD A,d8のテスト: pc + 1の即値を加算し、2バイト進む
+    #[test]
+    fn test_add_immediate() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.registers.a = 0xF0;
+        cpu.bus.memory[0x0201] = 0x10;
+        let next_pc = cpu.execute(Instruction::ADD(ArithmeticTarget::D8));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.carry);
+        assert_eq!(next_pc, 0x0202);
+    }
+
+    // ALU A,d8の命令をstepで実行する（0x0200にオペコード、0x0201に即値を置く）
+    fn step_immediate(cpu: &mut CPU, opcode: u8, value: u8) {
+        cpu.pc = 0x0200;
+        cpu.bus.memory[0x0200] = opcode;
+        cpu.bus.memory[0x0201] = value;
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0202);
+    }
+
+    #[test]
+    fn test_adc_immediate() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x0E;
+        cpu.registers.f.carry = true;
+        step_immediate(&mut cpu, 0xCE, 0x01);
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_sub_immediate() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x10;
+        step_immediate(&mut cpu, 0xD6, 0x01);
+        assert_eq!(cpu.registers.a, 0x0F);
+        assert!(cpu.registers.f.subtract);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_sbc_immediate() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x01;
+        cpu.registers.f.carry = true;
+        step_immediate(&mut cpu, 0xDE, 0x01);
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert!(cpu.registers.f.subtract);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_and_immediate() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xF0;
+        step_immediate(&mut cpu, 0xE6, 0x0F);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+    }
+
+    #[test]
+    fn test_xor_immediate() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xFF;
+        cpu.registers.f.carry = true;
+        step_immediate(&mut cpu, 0xEE, 0x0F);
+        assert_eq!(cpu.registers.a, 0xF0);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_or_immediate() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x00;
+        step_immediate(&mut cpu, 0xF6, 0x00);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        step_immediate(&mut cpu, 0xF6, 0x81);
+        assert_eq!(cpu.registers.a, 0x81);
+        assert!(!cpu.registers.f.zero);
+    }
+
+    // CP d8はAを変えずにフラグだけを設定する
+    #[test]
+    fn test_cp_immediate() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x42;
+        step_immediate(&mut cpu, 0xFE, 0x42);
+        assert_eq!(cpu.registers.a, 0x42);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.subtract);
+        step_immediate(&mut cpu, 0xFE, 0x43);
+        assert!(!cpu.registers.f.zero);
+        assert!(cpu.registers.f.carry);
+    }
+
+    // ALU A,(HL)の命令をstepで実行する（HL = 0xC000に値を置く）
+    fn step_hli(cpu: &mut CPU, opcode: u8, value: u8) {
+        cpu.pc = 0x0200;
+        cpu.bus.memory[0x0200] = opcode;
+        cpu.registers.set_hl(0xC000);
+        cpu.bus.write_byte(0xC000, value);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0201);
+    }
+
+    #[test]
+    fn test_adc_hli() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xFF;
+        cpu.registers.f.carry = true;
+        step_hli(&mut cpu, 0x8E, 0x00);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_sub_hli() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x05;
+        step_hli(&mut cpu, 0x96, 0x05);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_sbc_hli() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x20;
+        cpu.registers.f.carry = true;
+        step_hli(&mut cpu, 0x9E, 0x0F);
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_and_hli() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x3C;
+        step_hli(&mut cpu, 0xA6, 0x0F);
+        assert_eq!(cpu.registers.a, 0x0C);
+        assert!(!cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+    }
+
+    #[test]
+    fn test_xor_hli() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x5A;
+        step_hli(&mut cpu, 0xAE, 0x5A);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+    }
+
+    #[test]
+    fn test_or_hli() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x50;
+        cpu.registers.f.carry = true;
+        step_hli(&mut cpu, 0xB6, 0x05);
+        assert_eq!(cpu.registers.a, 0x55);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_cp_hli() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x10;
+        step_hli(&mut cpu, 0xBE, 0x20);
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(!cpu.registers.f.zero);
+        assert!(cpu.registers.f.subtract);
+        assert!(cpu.registers.f.carry);
+    }
+
+    // adcでキャリーが立っていない場合は通常のaddと同じ
+    #[test]
+    fn test_adc_without_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x01;
+        cpu.registers.b = 0x02;
+        cpu.registers.f.carry = false;
+        let next_pc = cpu.execute(Instruction::ADC(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0x03);
+        assert!(!cpu.registers.f.carry);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // adcでキャリーを足すことで結果が変わり、half_carryが発生する場合のテスト
+    #[test]
+    fn test_adc_carry_in_causes_half_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x0E;
+        cpu.registers.d = 0x01;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::ADC(ArithmeticTarget::D));
+        // 0x0E + 0x01 + 1 = 0x10
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+        assert!(!cpu.registers.f.zero);
+    }
+
+    // adcでキャリーを足すことでオーバーフローする場合のテスト
+    #[test]
+    fn test_adc_carry_in_causes_overflow() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xFF;
+        cpu.registers.e = 0x00;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::ADC(ArithmeticTarget::E));
+        // 0xFF + 0x00 + 1 = 0x100
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.half_carry);
+    }
+
+    // adcでA=0xFF, value=0xFF, carry=1の場合のテスト
+    #[test]
+    fn test_adc_max_values_with_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xFF;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::ADC(ArithmeticTarget::A));
+        // 0xFF + 0xFF + 1 = 0x1FF
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.subtract);
+    }
+
+    #[test]
+    fn test_sub() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x3E;
+        cpu.registers.e = 0x3E;
+        let next_pc = cpu.execute(Instruction::SUB(ArithmeticTarget::E));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.subtract);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // subで下位ニブルのボローとアンダーフローが発生する場合のテスト
+    #[test]
+    fn test_sub_underflow() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x10;
+        cpu.registers.b = 0x21;
+        cpu.execute(Instruction::SUB(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0xEF);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.half_carry);
+    }
+
+    // cpで値が等しい場合はzeroが立ち、Aは変化しない
+    #[test]
+    fn test_cp_equal() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x42;
+        cpu.registers.b = 0x42;
+        let next_pc = cpu.execute(Instruction::CP(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0x42);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // cpでAが小さい場合はボローでcarryが立つ
+    #[test]
+    fn test_cp_less_than() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x10;
+        cpu.registers.c = 0x11;
+        cpu.execute(Instruction::CP(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(!cpu.registers.f.zero);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.half_carry);
+    }
+
+    // cpでAが大きい場合はzeroもcarryも立たない
+    #[test]
+    fn test_cp_greater_than() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x80;
+        cpu.registers.l = 0x01;
+        cpu.execute(Instruction::CP(ArithmeticTarget::L));
+        assert_eq!(cpu.registers.a, 0x80);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.carry);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.subtract);
+    }
+
+    // sbcでキャリーが立っていない場合のテスト
+    #[test]
+    fn test_sbc_without_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x05;
+        cpu.registers.b = 0x03;
+        let next_pc = cpu.execute(Instruction::SBC(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0x02);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // sbcでキャリーを引くことで結果が0になる場合のテスト
+    #[test]
+    fn test_sbc_carry_in_reaches_zero() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x10;
+        cpu.registers.c = 0x0F;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::SBC(ArithmeticTarget::C));
+        // 0x10 - 0x0F - 1 = 0x00
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    // sbcでA=0x00, value=0x00, carry=1の場合は0xFFになりキャリーが立つ
+    #[test]
+    fn test_sbc_carry_in_wraps_around() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x00;
+        cpu.registers.d = 0x00;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::SBC(ArithmeticTarget::D));
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.zero);
+    }
+
+    // sbcでvalue=0xFF, carry=1の場合もボローになる
+    #[test]
+    fn test_sbc_carry_in_with_max_value() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xFF;
+        cpu.registers.e = 0xFF;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::SBC(ArithmeticTarget::E));
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.half_carry);
+    }
+
+    // andの結果が0になる場合のテスト
+    #[test]
+    fn test_and_zero_result() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xF0;
+        cpu.registers.b = 0x0F;
+        cpu.registers.f.carry = true;
+        let next_pc = cpu.execute(Instruction::AND(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(u8::from(cpu.registers.f), 0b1010_0000); // zeroとhalf_carry
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    #[test]
+    fn test_and_non_zero_result() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0b1100_1100;
+        cpu.registers.c = 0b1010_1010;
+        cpu.execute(Instruction::AND(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0b1000_1000);
+        assert_eq!(u8::from(cpu.registers.f), 0b0010_0000); // half_carryのみ
+    }
+
+    #[test]
+    fn test_or() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xF0;
+        cpu.registers.d = 0x0F;
+        cpu.registers.f.half_carry = true;
+        cpu.registers.f.carry = true;
+        let next_pc = cpu.execute(Instruction::OR(ArithmeticTarget::D));
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert_eq!(u8::from(cpu.registers.f), 0b0000_0000);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    #[test]
+    fn test_or_zero_result() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x00;
+        cpu.registers.e = 0x00;
+        cpu.execute(Instruction::OR(ArithmeticTarget::E));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(u8::from(cpu.registers.f), 0b1000_0000); // zeroのみ
+    }
+
+    // xor A,Aはレジスタを0にする定番の書き方
+    #[test]
+    fn test_xor_self_clears_a() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x5A;
+        cpu.registers.f.carry = true;
+        let next_pc = cpu.execute(Instruction::XOR(ArithmeticTarget::A));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(u8::from(cpu.registers.f), 0b1000_0000); // zeroのみ
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    #[test]
+    fn test_xor() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0b1100_1100;
+        cpu.registers.h = 0b1010_1010;
+        cpu.execute(Instruction::XOR(ArithmeticTarget::H));
+        assert_eq!(cpu.registers.a, 0b0110_0110);
+        assert_eq!(u8::from(cpu.registers.f), 0b0000_0000);
+    }
+
+    #[test]
+    fn test_inc() {
+        let mut cpu = CPU::default();
+        cpu.registers.b = 0x0F;
+        let next_pc = cpu.execute(Instruction::INC(IncDecTarget::B));
+        assert_eq!(cpu.registers.b, 0x10);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // incで0xFFが0x00に戻ってもcarryは変化しない
+    #[test]
+    fn test_inc_wrap_preserves_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0xFF;
+        cpu.registers.f.carry = false;
+        cpu.execute(Instruction::INC(IncDecTarget::A));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+
+        cpu.registers.a = 0xFF;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::INC(IncDecTarget::A));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_dec() {
+        let mut cpu = CPU::default();
+        cpu.registers.c = 0x10;
+        let next_pc = cpu.execute(Instruction::DEC(IncDecTarget::C));
+        assert_eq!(cpu.registers.c, 0x0F);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.zero);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // decで0x00が0xFFに戻ってもcarryは変化しない
+    #[test]
+    fn test_dec_wrap_preserves_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.d = 0x00;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::DEC(IncDecTarget::D));
+        assert_eq!(cpu.registers.d, 0xFF);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.half_carry);
+
+        cpu.registers.d = 0x01;
+        cpu.registers.f.carry = false;
+        cpu.execute(Instruction::DEC(IncDecTarget::D));
+        assert_eq!(cpu.registers.d, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(!cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+    }
+
+    // INC (HL)はHLが指すメモリを読み、1足して同じアドレスに書き戻す
+    #[test]
+    fn test_inc_hli_wrap_preserves_carry() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.bus.memory[0x0200] = 0x34; // INC (HL)
+        cpu.registers.set_hl(0xC000);
+        cpu.bus.write_byte(0xC000, 0xFF);
+        cpu.registers.f.carry = true;
+        assert_eq!(cpu.step_with_cycles(), Ok(12));
+        assert_eq!(cpu.bus.read_byte(0xC000), 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.subtract);
+        assert!(cpu.registers.f.carry);
+        assert_eq!(cpu.pc, 0x0201);
+    }
+
+    #[test]
+    fn test_dec_hli() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.bus.memory[0x0200] = 0x35; // DEC (HL)
+        cpu.registers.set_hl(0xC000);
+        cpu.bus.write_byte(0xC000, 0x10);
+        cpu.registers.f.carry = false;
+        assert_eq!(cpu.step_with_cycles(), Ok(12));
+        assert_eq!(cpu.bus.read_byte(0xC000), 0x0F);
+        assert!(!cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.carry);
+        assert_eq!(cpu.pc, 0x0201);
+    }
+
+    #[test]
+    fn test_add_hl() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0x1234);
+        cpu.registers.set_bc(0x0101);
+        cpu.registers.f.zero = true;
+        let next_pc = cpu.execute(Instruction::ADDHL(WordRegister::BC));
+        assert_eq!(cpu.registers.get_hl(), 0x1335);
+        assert!(cpu.registers.f.zero); // zeroは変更されない
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // add_hlでビット11から繰り上がる場合はhalf_carryが立つ
+    #[test]
+    fn test_add_hl_half_carry_boundary() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0x0FFF);
+        cpu.registers.set_de(0x0001);
+        cpu.execute(Instruction::ADDHL(WordRegister::DE));
+        assert_eq!(cpu.registers.get_hl(), 0x1000);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+
+        // ビット7からの繰り上がりではhalf_carryは立たない
+        cpu.registers.set_hl(0x00FF);
+        cpu.execute(Instruction::ADDHL(WordRegister::DE));
+        assert_eq!(cpu.registers.get_hl(), 0x0100);
+        assert!(!cpu.registers.f.half_carry);
+    }
+
+    // add_hlで16ビットがオーバーフローする場合はcarryが立つ
+    #[test]
+    fn test_add_hl_overflow() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0x8000);
+        cpu.execute(Instruction::ADDHL(WordRegister::HL));
+        assert_eq!(cpu.registers.get_hl(), 0x0000);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.zero);
+    }
+
+    #[test]
+    fn test_add_hl_sp() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0xF000);
+        cpu.sp = 0x1FFF;
+        cpu.execute(Instruction::ADDHL(WordRegister::SP));
+        assert_eq!(cpu.registers.get_hl(), 0x0FFF);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+    }
+
+    #[test]
+    fn test_add_sp_positive_offset() {
+        let mut cpu = CPU::default();
+        cpu.sp = 0xFFF8;
+        cpu.bus.write_byte(0x0001, 0x08);
+        cpu.registers.f.zero = true;
+        cpu.registers.f.subtract = true;
+        let next_pc = cpu.execute(Instruction::ADDSP);
+        assert_eq!(cpu.sp, 0x0000);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+        assert_eq!(next_pc, 0x0002);
+    }
+
+    // 負のオフセットでも、フラグは下位バイトを符号なしで足した結果で決まる
+    #[test]
+    fn test_add_sp_negative_offset() {
+        let mut cpu = CPU::default();
+        cpu.sp = 0xD000;
+        cpu.bus.write_byte(0x0001, 0xFF); // -1
+        cpu.execute(Instruction::ADDSP);
+        assert_eq!(cpu.sp, 0xCFFF);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+
+        // 0x01 + 0xFF は下位バイトで繰り上がる
+        cpu.sp = 0xD001;
+        cpu.execute(Instruction::ADDSP);
+        assert_eq!(cpu.sp, 0xD000);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_ld_hl_sp_offset() {
+        let mut cpu = CPU::default();
+        cpu.sp = 0xFFF8;
+        cpu.bus.write_byte(0x0001, 0x02);
+        let next_pc = cpu.execute(Instruction::LDHLSP);
+        assert_eq!(cpu.registers.get_hl(), 0xFFFA);
+        assert_eq!(cpu.sp, 0xFFF8); // SPは変更されない
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+        assert_eq!(next_pc, 0x0002);
+
+        cpu.bus.write_byte(0x0001, 0xFE); // -2
+        cpu.execute(Instruction::LDHLSP);
+        assert_eq!(cpu.registers.get_hl(), 0xFFF6);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+    }
+
+    #[test]
+    fn test_inc16() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_de(0x00FF);
+        let next_pc = cpu.execute(Instruction::INC16(WordRegister::DE));
+        assert_eq!(cpu.registers.get_de(), 0x0100);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // inc16で0xFFFFが0x0000に戻ってもフラグは一切変化しない
+    #[test]
+    fn test_inc16_wrap_preserves_flags() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_bc(0xFFFF);
+        cpu.registers.f = FlagsRegister::from(0b1010_0000);
+        cpu.execute(Instruction::INC16(WordRegister::BC));
+        assert_eq!(cpu.registers.get_bc(), 0x0000);
+        assert_eq!(u8::from(cpu.registers.f), 0b1010_0000);
+
+        cpu.registers.f = FlagsRegister::from(0b0101_0000);
+        cpu.sp = 0xFFFF;
+        cpu.execute(Instruction::INC16(WordRegister::SP));
+        assert_eq!(cpu.sp, 0x0000);
+        assert_eq!(u8::from(cpu.registers.f), 0b0101_0000);
+    }
+
+    // dec16で0x0000が0xFFFFに戻ってもフラグは一切変化しない
+    #[test]
+    fn test_dec16_wrap_preserves_flags() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0x0000);
+        cpu.registers.f = FlagsRegister::from(0b1111_0000);
+        let next_pc = cpu.execute(Instruction::DEC16(WordRegister::HL));
+        assert_eq!(cpu.registers.get_hl(), 0xFFFF);
+        assert_eq!(u8::from(cpu.registers.f), 0b1111_0000);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // RLCAのテスト: ビット7がビット0とcarryに入る
+    #[test]
+    fn test_rlca() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0b1000_0101;
+        let next_pc = cpu.execute(Instruction::RLCA);
+        assert_eq!(cpu.registers.a, 0b0000_1011);
+        assert!(cpu.registers.f.carry);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // RRCAのテスト: ビット0がビット7とcarryに入る
+    #[test]
+    fn test_rrca() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0b0000_0001;
+        cpu.execute(Instruction::RRCA);
+        assert_eq!(cpu.registers.a, 0b1000_0000);
+        assert!(cpu.registers.f.carry);
+    }
+
+    // RLAのテスト: 元のcarryがビット0に入り、ビット7がcarryに出る
+    #[test]
+    fn test_rla_through_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0b0100_0000;
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::RLA);
+        assert_eq!(cpu.registers.a, 0b1000_0001);
+        assert!(!cpu.registers.f.carry);
+
+        cpu.execute(Instruction::RLA);
+        assert_eq!(cpu.registers.a, 0b0000_0010);
+        assert!(cpu.registers.f.carry);
+    }
+
+    // RRAのテスト: 元のcarryがビット7に入り、ビット0がcarryに出る
+    #[test]
+    fn test_rra_through_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0b0000_0011;
+        cpu.registers.f.carry = false;
+        cpu.execute(Instruction::RRA);
+        assert_eq!(cpu.registers.a, 0b0000_0001);
+        assert!(cpu.registers.f.carry);
+
+        cpu.execute(Instruction::RRA);
+        assert_eq!(cpu.registers.a, 0b1000_0000);
+        assert!(cpu.registers.f.carry);
+    }
+
+    // Aのローテートは結果が0になってもzeroを立てず、subtractとhalf_carryもクリアする
+    #[test]
+    fn test_accumulator_rotate_never_sets_zero() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0b1000_0000;
+        cpu.registers.f = FlagsRegister::from(0b1110_0000);
+        cpu.execute(Instruction::RLA);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(u8::from(cpu.registers.f), 0b0001_0000); // carryのみ
+
+        cpu.registers.a = 0b0000_0001;
+        cpu.registers.f = FlagsRegister::from(0b1110_0000);
+        cpu.execute(Instruction::RRA);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(u8::from(cpu.registers.f), 0b0001_0000);
+
+        cpu.registers.a = 0x00;
+        cpu.execute(Instruction::RLCA);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(!cpu.registers.f.zero);
+        cpu.execute(Instruction::RRCA);
+        assert!(!cpu.registers.f.zero);
+    }
+
+    // SWAPのテスト: ニブルが入れ替わり、フラグはzero以外クリアされる
+    #[test]
+    fn test_swap() {
+        let mut cpu = CPU::default();
+        cpu.registers.b = 0xA5;
+        cpu.registers.f = FlagsRegister::from(0b0111_0000);
+        let next_pc = cpu.execute(Instruction::SWAP(PrefixTarget::B));
+        assert_eq!(cpu.registers.b, 0x5A);
+        assert_eq!(u8::from(cpu.registers.f), 0b0000_0000);
+        assert_eq!(next_pc, 0x0002);
+    }
+
+    #[test]
+    fn test_swap_zero() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x00;
+        cpu.execute(Instruction::SWAP(PrefixTarget::A));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(u8::from(cpu.registers.f), 0b1000_0000);
+    }
+
+    #[test]
+    fn test_swap_memory() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0xC000);
+        cpu.bus.write_byte(0xC000, 0x12);
+        cpu.execute(Instruction::SWAP(PrefixTarget::HLI));
+        assert_eq!(cpu.bus.read_byte(0xC000), 0x21);
+    }
+
+    // CBのローテート/シフト命令のテスト
+    // (命令, 入力, 元のcarry, 期待する結果, 期待するcarry, 期待するzero)
+    #[test]
+    fn test_prefixed_rotate_shift_matrix() {
+        type Op = fn(PrefixTarget) -> Instruction;
+        let cases: [(Op, u8, bool, u8, bool, bool); 18] = [
+            (Instruction::RLC, 0x81, false, 0x03, true, false),
+            (Instruction::RLC, 0x00, true, 0x00, false, true),
+            (Instruction::RRC, 0x01, false, 0x80, true, false),
+            (Instruction::RRC, 0x00, true, 0x00, false, true),
+            (Instruction::RL, 0x80, false, 0x00, true, true),
+            (Instruction::RL, 0x01, true, 0x03, false, false),
+            (Instruction::RR, 0x01, false, 0x00, true, true),
+            (Instruction::RR, 0x80, true, 0xC0, false, false),
+            (Instruction::SLA, 0x80, false, 0x00, true, true),
+            (Instruction::SLA, 0x41, true, 0x82, false, false),
+            // SRAはビット7を保持する
+            (Instruction::SRA, 0x81, false, 0xC0, true, false),
+            (Instruction::SRA, 0x01, false, 0x00, true, true),
+            (Instruction::SRA, 0x7E, true, 0x3F, false, false),
+            // SRLはビット7に0を入れる
+            (Instruction::SRL, 0x81, false, 0x40, true, false),
+            (Instruction::SRL, 0x01, false, 0x00, true, true),
+            (Instruction::SRL, 0xFE, true, 0x7F, false, false),
+            (Instruction::SWAP, 0xF0, true, 0x0F, false, false),
+            (Instruction::SWAP, 0x00, true, 0x00, false, true),
+        ];
+        for (op, input, carry_in, expected, carry_out, zero) in cases {
+            let mut cpu = CPU::default();
+            cpu.registers.c = input;
+            cpu.registers.f.carry = carry_in;
+            cpu.registers.f.subtract = true;
+            cpu.registers.f.half_carry = true;
+            let instruction = op(PrefixTarget::C);
+            let description = format!("{:?} 0x{:02X}", instruction, input);
+            let next_pc = cpu.execute(instruction);
+            assert_eq!(cpu.registers.c, expected, "{}", description);
+            assert_eq!(cpu.registers.f.carry, carry_out, "{}", description);
+            assert_eq!(cpu.registers.f.zero, zero, "{}", description);
+            assert!(!cpu.registers.f.subtract, "{}", description);
+            assert!(!cpu.registers.f.half_carry, "{}", description);
+            assert_eq!(next_pc, 0x0002);
+        }
+    }
+
+    #[test]
+    fn test_prefixed_shift_memory() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0xC000);
+        cpu.bus.write_byte(0xC000, 0b1000_0010);
+        cpu.execute(Instruction::SRA(PrefixTarget::HLI));
+        assert_eq!(cpu.bus.read_byte(0xC000), 0b1100_0001);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    // BITのテスト: ビットが立っている場合はzeroが立たない
+    #[test]
+    fn test_bit_set() {
+        let mut cpu = CPU::default();
+        cpu.registers.d = 0b0000_1000;
+        cpu.registers.f.subtract = true;
+        let next_pc = cpu.execute(Instruction::BIT(3, PrefixTarget::D));
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(cpu.registers.f.half_carry);
+        assert_eq!(cpu.registers.d, 0b0000_1000); // レジスタは変更されない
+        assert_eq!(next_pc, 0x0002);
+    }
+
+    // BITのテスト: ビットが立っていない場合はzeroが立ち、carryは変化しない
+    #[test]
+    fn test_bit_cleared() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0xC000);
+        cpu.bus.write_byte(0xC000, 0b0111_1111);
+        cpu.registers.f.carry = true;
+        cpu.execute(Instruction::BIT(7, PrefixTarget::HLI));
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.carry);
+
+        cpu.registers.f.carry = false;
+        cpu.execute(Instruction::BIT(7, PrefixTarget::HLI));
+        assert!(!cpu.registers.f.carry);
+    }
+
+    // SET/RESのテスト: ビットを立てて戻しても、フラグは変化しない
+    #[test]
+    fn test_set_res_round_trip() {
+        let mut cpu = CPU::default();
+        cpu.registers.e = 0x00;
+        cpu.registers.f = FlagsRegister::from(0b1010_0000);
+
+        cpu.execute(Instruction::SET(5, PrefixTarget::E));
+        assert_eq!(cpu.registers.e, 0b0010_0000);
+        cpu.execute(Instruction::SET(0, PrefixTarget::E));
+        assert_eq!(cpu.registers.e, 0b0010_0001);
+        let next_pc = cpu.execute(Instruction::RES(5, PrefixTarget::E));
+        assert_eq!(cpu.registers.e, 0b0000_0001);
+        cpu.execute(Instruction::RES(0, PrefixTarget::E));
+        assert_eq!(cpu.registers.e, 0x00);
+
+        assert_eq!(u8::from(cpu.registers.f), 0b1010_0000);
+        assert_eq!(next_pc, 0x0002);
+    }
+
+    #[test]
+    fn test_set_res_memory() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_hl(0xC000);
+        cpu.bus.write_byte(0xC000, 0xFF);
+        cpu.execute(Instruction::RES(7, PrefixTarget::HLI));
+        assert_eq!(cpu.bus.read_byte(0xC000), 0x7F);
+        cpu.execute(Instruction::SET(7, PrefixTarget::HLI));
+        assert_eq!(cpu.bus.read_byte(0xC000), 0xFF);
+    }
+
+    // stepのテスト: 0xCBと命令の2バイトを消費する
+    #[test]
+    fn test_step_prefixed_swap() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.registers.a = 0xF0;
+        cpu.bus.memory[0x0100] = 0xCB;
+        cpu.bus.memory[0x0101] = 0x37; // SWAP A
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x0F);
+        assert_eq!(cpu.pc, 0x0102);
+    }
+
+    // DAAのテスト: 0x09 + 0x01 = 0x10（BCD）
+    #[test]
+    fn test_daa_after_add_low_nibble() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x09;
+        cpu.registers.c = 0x01;
+        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0x0A);
+        let next_pc = cpu.execute(Instruction::DAA);
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(!cpu.registers.f.carry);
+        assert!(!cpu.registers.f.zero);
+        assert_eq!(next_pc, 0x0001);
+    }
+
+    // DAAのテスト: 0x08 + 0x08 = 0x16（half_carryによる補正）
+    #[test]
+    fn test_daa_after_add_half_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x08;
+        cpu.registers.c = 0x08;
+        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(cpu.registers.f.half_carry);
+        cpu.execute(Instruction::DAA);
+        assert_eq!(cpu.registers.a, 0x16);
+        assert!(!cpu.registers.f.half_carry);
+    }
+
+    // DAAのテスト: 0x99 + 0x01 = 0x00（繰り上がりでcarryが立つ）
+    #[test]
+    fn test_daa_after_add_carry_out() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x99;
+        cpu.registers.c = 0x01;
+        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0x9A);
+        cpu.execute(Instruction::DAA);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.zero);
+    }
+
+    // DAAのテスト: 0x90 + 0x90 = 0x80（carry付き）
+    #[test]
+    fn test_daa_after_add_with_binary_carry() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x90;
+        cpu.registers.c = 0x90;
+        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
+        assert_eq!(cpu.registers.a, 0x20);
+        assert!(cpu.registers.f.carry);
+        cpu.execute(Instruction::DAA);
+        assert_eq!(cpu.registers.a, 0x80);
+        assert!(cpu.registers.f.carry);
+    }
+
+    // DAAのテスト: 0x10 - 0x01 = 0x09（BCD）
+    #[test]
+    fn test_daa_after_sub() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x10;
+        cpu.registers.b = 0x01;
+        cpu.execute(Instruction::SUB(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0x0F);
+        cpu.execute(Instruction::DAA);
+        assert_eq!(cpu.registers.a, 0x09);
+        assert!(cpu.registers.f.subtract); // subtractは変更されない
+        assert!(!cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+    }
+
+    // DAAのテスト: 0x00 - 0x01 = 0x99（繰り下がりでcarryが立つ）
+    #[test]
+    fn test_daa_after_sub_borrow() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x00;
+        cpu.registers.b = 0x01;
+        cpu.execute(Instruction::SUB(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0xFF);
+        cpu.execute(Instruction::DAA);
+        assert_eq!(cpu.registers.a, 0x99);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.zero);
+    }
+
+    // CPLのテスト: Aを反転し、subtractとhalf_carryを立てる
+    #[test]
+    fn test_cpl() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0b1010_0101;
+        cpu.registers.f = FlagsRegister::from(0b1001_0000);
+        let next_pc = cpu.execute(Instruction::CPL);
+        assert_eq!(cpu.registers.a, 0b0101_1010);
+        assert_eq!(u8::from(cpu.registers.f), 0b1111_0000);
+        assert_eq!(next_pc, 0x0001);
+
+        cpu.registers.f = FlagsRegister::from(0b0000_0000);
+        cpu.execute(Instruction::CPL);
+        assert_eq!(u8::from(cpu.registers.f), 0b0110_0000);
+    }
+
+    // SCFのテスト: carryを立て、subtractとhalf_carryをクリアする
+    #[test]
+    fn test_scf() {
+        let mut cpu = CPU::default();
+        cpu.registers.f = FlagsRegister::from(0b0110_0000);
+        let next_pc = cpu.execute(Instruction::SCF);
+        assert_eq!(u8::from(cpu.registers.f), 0b0001_0000);
+        assert_eq!(next_pc, 0x0001);
+
+        cpu.registers.f = FlagsRegister::from(0b1111_0000);
+        cpu.execute(Instruction::SCF);
+        assert_eq!(u8::from(cpu.registers.f), 0b1001_0000);
+    }
+
+    // CCFのテスト: carryを反転し、subtractとhalf_carryをクリアする
+    #[test]
+    fn test_ccf() {
+        let mut cpu = CPU::default();
+        cpu.registers.f = FlagsRegister::from(0b1111_0000);
+        let next_pc = cpu.execute(Instruction::CCF);
+        assert_eq!(u8::from(cpu.registers.f), 0b1000_0000);
+        assert_eq!(next_pc, 0x0001);
+
+        cpu.execute(Instruction::CCF);
+        assert_eq!(u8::from(cpu.registers.f), 0b1001_0000);
+
+        cpu.registers.f = FlagsRegister::from(0b0110_0000);
+        cpu.execute(Instruction::CCF);
+        assert_eq!(u8::from(cpu.registers.f), 0b0001_0000);
+    }
+
+    #[test]
+    fn test_jump_not_zero_taken() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.registers.f.zero = false;
+        cpu.bus.memory[0x0101] = 0x34;
+        cpu.bus.memory[0x0102] = 0x12;
+
+        let next_pc = cpu.execute(Instruction::JP(JumpTest::NotZero));
+        assert_eq!(next_pc, 0x1234);
+    }
+
+    #[test]
+    fn test_jump_not_zero_not_taken() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.registers.f.zero = true;
+
+        let next_pc = cpu.execute(Instruction::JP(JumpTest::NotZero));
+        assert_eq!(next_pc, 0x0103);
+    }
+
+    #[test]
+    fn test_jump_carry_taken() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.registers.f.carry = true;
+        cpu.bus.memory[0x0201] = 0x78;
+        cpu.bus.memory[0x0202] = 0x56;
+
+        let next_pc = cpu.execute(Instruction::JP(JumpTest::Carry));
+        assert_eq!(next_pc, 0x5678);
+    }
+
+    #[test]
+    fn test_jump_carry_not_taken() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.registers.f.carry = false;
+
+        let next_pc = cpu.execute(Instruction::JP(JumpTest::Carry));
+        assert_eq!(next_pc, 0x0203);
+    }
+
+    #[test]
+    fn test_jump_always() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0300;
+        cpu.bus.memory[0x0301] = 0xAA;
+        cpu.bus.memory[0x0302] = 0xBB;
+
+        let next_pc = cpu.execute(Instruction::JP(JumpTest::Always));
+        assert_eq!(next_pc, 0xBBAA);
+    }
+
+    // stepを通した分岐命令のテスト: 成立すればオペランドの飛び先、不成立なら命令の長さだけ進む
+    #[test]
+    fn test_step_branches_not_taken() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.sp = 0xFFFE;
+        cpu.registers.f.zero = true;
+        let program = [
+            0xC2, 0x00, 0x40, // JP NZ,$4000
+            0x20, 0x10, // JR NZ,+$10
+            0xC4, 0x00, 0x50, // CALL NZ,$5000
+        ];
+        cpu.bus.load_at(0x0200, &program);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0203);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0205);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0208);
+        assert_eq!(cpu.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn test_step_branches_taken() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.sp = 0xFFFE;
+        cpu.registers.f.zero = false;
+        cpu.bus.memory[0x0200..0x0203].copy_from_slice(&[0xC2, 0x00, 0x40]); // JP NZ,$4000
+        cpu.bus.memory[0x4000..0x4002].copy_from_slice(&[0x20, 0x10]); // JR NZ,+$10
+        cpu.bus.memory[0x4012..0x4015].copy_from_slice(&[0xC4, 0x00, 0x50]); // CALL NZ,$5000
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x4000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x4012);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x5000);
+        // 戻り先はCALLの次の命令
+        assert_eq!(cpu.pop(), 0x4015);
+    }
+
+    // JP (HL)のテスト: HLが指すメモリではなくHLの値に飛ぶ
+    #[test]
+    fn test_jump_hl() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.registers.set_hl(0x4000);
+        cpu.bus.memory[0x4000] = 0x12;
+
+        let next_pc = cpu.execute(Instruction::JPHL);
+        assert_eq!(next_pc, 0x4000);
+    }
+
+    // JRのテスト: 前方へのジャンプ
+    #[test]
+    fn test_jump_relative_forward() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0101] = 0x05;
+
+        let next_pc = cpu.execute(Instruction::JR(JumpTest::Always));
+        assert_eq!(next_pc, 0x0107); // 0x0102 + 5
+    }
+
+    // JRのテスト: 負のオフセットで後方へのジャンプ
+    #[test]
+    fn test_jump_relative_backward() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.registers.f.zero = true;
+        cpu.bus.memory[0x0101] = 0xFE; // -2
+
+        // 0xFEはJR自身に戻る無限ループになる
+        let next_pc = cpu.execute(Instruction::JR(JumpTest::Zero));
+        assert_eq!(next_pc, 0x0100);
+
+        cpu.bus.memory[0x0101] = 0x80; // -128
+        let next_pc = cpu.execute(Instruction::JR(JumpTest::Zero));
+        assert_eq!(next_pc, 0x0082);
+    }
+
+    // JRのテスト: 条件が成立しない場合は2バイト進むだけ
+    #[test]
+    fn test_jump_relative_not_taken() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.registers.f.carry = false;
+        cpu.bus.memory[0x0101] = 0x10;
+
+        let next_pc = cpu.execute(Instruction::JR(JumpTest::Carry));
+        assert_eq!(next_pc, 0x0102);
+    }
+
+    // 未使用のオペコードはIllegalOpcodeのエラーになる
+    #[test]
+    fn test_step_illegal_opcode() {
+        for byte in [
+            0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+        ] {
+            let mut cpu = CPU::default();
+            cpu.pc = 0xC000;
+            cpu.bus.write_byte(0xC000, byte);
+            let error = cpu.step().unwrap_err();
+            assert_eq!(error, CpuError::IllegalOpcode { byte, pc: 0xC000 });
+            // 実機では止まったままなので、pcは進まない
+            assert_eq!(cpu.pc, 0xC000);
+        }
+    }
+
+    #[test]
+    fn test_illegal_opcode_error_message() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0150;
+        cpu.bus.write_byte(0x0150, 0xDB);
+        let error = cpu.step().unwrap_err();
+        assert_eq!(error.to_string(), "Illegal instruction 0xDB at 0x0150");
+    }
+
+    // DIのテスト: 即座にIMEが無効になる
+    #[test]
+    fn test_step_di_disables_immediately() {
+        let mut cpu = CPU::default();
+        cpu.ime = true;
+        cpu.bus.memory[0x0000] = 0xF3; // DI
+        cpu.step().unwrap();
+        assert!(!cpu.ime);
+        assert_eq!(cpu.pc, 0x0001);
+    }
+
+    // EIのテスト: 次の命令を実行し終えてからIMEが有効になる
+    #[test]
+    fn test_step_ei_is_delayed_by_one_instruction() {
+        let mut cpu = CPU::default();
+        cpu.bus.memory[0x0000] = 0xFB; // EI
+        cpu.bus.memory[0x0001] = 0x00; // NOP
+        cpu.bus.memory[0x0002] = 0x00; // NOP
+
+        cpu.step().unwrap();
+        assert!(!cpu.ime);
+        cpu.step().unwrap();
+        assert!(cpu.ime);
+        cpu.step().unwrap();
+        assert!(cpu.ime);
+    }
+
+    // EIの直後にDIを実行するとIMEは有効にならない
+    #[test]
+    fn test_step_ei_then_di_stays_disabled() {
+        let mut cpu = CPU::default();
+        cpu.bus.memory[0x0000] = 0xFB; // EI
+        cpu.bus.memory[0x0001] = 0xF3; // DI
+        cpu.bus.memory[0x0002] = 0x00; // NOP
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(!cpu.ime);
+        cpu.step().unwrap();
+        assert!(!cpu.ime);
+    }
+
+    // 割り込みのテスト: タイマー割り込みが要求されると0x50に飛ぶ
+    #[test]
+    fn test_step_services_timer_interrupt() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0123;
+        cpu.sp = 0xFFFE;
+        cpu.ime = true;
+        cpu.bus.write_byte(0xFFFF, 0x04); // IE: Timer
+        cpu.bus.write_byte(0xFF0F, 0x04); // IF: Timer
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0050);
+        assert!(!cpu.ime);
+        assert_eq!(cpu.bus.read_byte(0xFF0F), 0x00);
+        assert_eq!(cpu.sp, 0xFFFC);
+        assert_eq!(cpu.pop(), 0x0123);
+    }
+
+    // RETIのテスト: 割り込みで積まれた戻り先に戻り、IMEが有効になる
+    #[test]
+    fn test_step_reti_returns_from_interrupt() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0123;
+        cpu.sp = 0xFFFE;
+        cpu.ime = true;
+        cpu.bus.write_byte(0xFFFF, 0x04); // IE: Timer
+        cpu.bus.write_byte(0xFF0F, 0x04); // IF: Timer
+        cpu.bus.memory[0x0050] = 0xD9; // RETI
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0050);
+        assert!(!cpu.ime);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0123);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert!(cpu.ime);
+    }
+
+    // 割り込みのテスト: 複数の割り込みが要求されていれば優先度の高い方から処理する
+    #[test]
+    fn test_step_services_highest_priority_interrupt() {
+        let mut cpu = CPU::default();
+        cpu.sp = 0xFFFE;
+        cpu.ime = true;
+        cpu.bus.write_byte(0xFFFF, 0x1F);
+        cpu.bus.write_byte(0xFF0F, 0b0001_0010); // LCD STATとJoypad
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0048);
+        // 処理していないJoypadの要求は残る
+        assert_eq!(cpu.bus.read_byte(0xFF0F), 0b0001_0000);
+    }
+
+    // 割り込みのテスト: IMEが無効またはIEで許可されていなければ処理しない
+    #[test]
+    fn test_step_ignores_masked_interrupt() {
+        let mut cpu = CPU::default();
+        cpu.sp = 0xFFFE;
+        cpu.bus.write_byte(0xFFFF, 0x04);
+        cpu.bus.write_byte(0xFF0F, 0x04);
+
+        cpu.step().unwrap(); // NOP
+        assert_eq!(cpu.pc, 0x0001);
+
+        cpu.ime = true;
+        cpu.bus.write_byte(0xFFFF, 0x01);
+        cpu.step().unwrap(); // NOP
+        assert_eq!(cpu.pc, 0x0002);
+        assert_eq!(cpu.bus.read_byte(0xFF0F), 0x04);
+    }
+
+    // HALTのテスト: 割り込みが要求されるまでpcは進まない
+    #[test]
+    fn test_step_halt_wakes_on_interrupt() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.sp = 0xFFFE;
+        cpu.ime = true;
+        cpu.bus.write_byte(0xFFFF, 0x01); // IE: VBlank
+        cpu.bus.memory[0x0200] = 0x76; // HALT
+
+        cpu.step().unwrap();
+        assert!(cpu.halted);
+        assert_eq!(cpu.pc, 0x0201);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.halted);
+        assert_eq!(cpu.pc, 0x0201);
+
+        // VBlank割り込みで復帰して割り込みを処理する
+        cpu.bus.write_byte(0xFF0F, 0x01);
+        cpu.step().unwrap();
+        assert!(!cpu.halted);
+        assert_eq!(cpu.pc, 0x0040);
+        assert_eq!(cpu.pop(), 0x0201);
+    }
+
+    // HALTのテスト: IMEが無効でも割り込みの要求で復帰し、次の命令に進む
+    #[test]
+    fn test_step_halt_wakes_without_ime() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.bus.write_byte(0xFFFF, 0x04);
+        cpu.bus.memory[0x0200] = 0x76; // HALT
+        cpu.bus.memory[0x0201] = 0x3C; // INC A
+
+        cpu.step().unwrap();
+        assert!(cpu.halted);
+
+        cpu.bus.write_byte(0xFF0F, 0x04);
+        cpu.step().unwrap();
+        assert!(!cpu.halted);
+        assert_eq!(cpu.registers.a, 0x01);
+        assert_eq!(cpu.pc, 0x0202);
+        // IMEが無効なので割り込みは処理されずに残る
+        assert_eq!(cpu.bus.read_byte(0xFF0F), 0x04);
+    }
+
+    // HALTバグのテスト: HALTの次のバイトが2回読まれる
+    #[test]
+    fn test_step_halt_bug_double_fetch() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.bus.write_byte(0xFFFF, 0x04);
+        cpu.bus.write_byte(0xFF0F, 0x04);
+        cpu.bus.memory[0x0200] = 0x76; // HALT
+        cpu.bus.memory[0x0201] = 0x3C; // INC A
+        cpu.bus.memory[0x0202] = 0x00; // NOP
+
+        cpu.step().unwrap();
+        assert!(!cpu.halted);
+        assert_eq!(cpu.pc, 0x0201);
+
+        // INC Aが2回実行される
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x01);
+        assert_eq!(cpu.pc, 0x0201);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x02);
+        assert_eq!(cpu.pc, 0x0202);
+    }
+
+    // HALTバグのテスト: 2バイト命令はオペコード自身をオペランドとして読む
+    #[test]
+    fn test_step_halt_bug_operand() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.bus.write_byte(0xFFFF, 0x01);
+        cpu.bus.write_byte(0xFF0F, 0x01);
+        cpu.bus.memory[0x0200] = 0x76; // HALT
+        cpu.bus.memory[0x0201] = 0x06; // LD B,d8
+        cpu.bus.memory[0x0202] = 0x42;
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.b, 0x06);
+        assert_eq!(cpu.pc, 0x0202);
+    }
+
+    // サイクル数のテスト: ADD A,Cは4サイクル
+    #[test]
+    fn test_cycles_add() {
+        let mut cpu = CPU::default();
+        cpu.bus.memory[0x0000] = 0x81; // ADD A,C
+        assert_eq!(cpu.step_with_cycles().unwrap(), 4);
+    }
+
+    // サイクル数のテスト: JPは成立すると16サイクル、不成立だと12サイクル
+    #[test]
+    fn test_cycles_conditional_jump() {
+        let mut cpu = CPU::default();
+        cpu.bus.memory[0x0000] = 0xC3; // JP 0x0100
+        cpu.bus.memory[0x0001] = 0x00;
+        cpu.bus.memory[0x0002] = 0x01;
+        assert_eq!(cpu.step_with_cycles().unwrap(), 16);
+        assert_eq!(cpu.pc, 0x0100);
+
+        cpu.registers.f.zero = true;
+        cpu.bus.memory[0x0100] = 0xC2; // JP NZ,0x0000
+        assert_eq!(cpu.step_with_cycles().unwrap(), 12);
+        assert_eq!(cpu.pc, 0x0103);
+
+        cpu.registers.f.zero = false;
+        cpu.pc = 0x0100;
+        assert_eq!(cpu.step_with_cycles().unwrap(), 16);
+    }
+
+    // サイクル数のテスト: CALL/RET/JRの成立と不成立
+    #[test]
+    fn test_cycles_conditional_call_ret_jr() {
+        let mut cpu = CPU::default();
+        cpu.sp = 0xFFFE;
+        cpu.registers.f.carry = true;
+        cpu.bus.memory[0x0000] = 0xDC; // CALL C,0x0200
+        cpu.bus.memory[0x0001] = 0x00;
+        cpu.bus.memory[0x0002] = 0x02;
+        cpu.bus.memory[0x0200] = 0xD0; // RET NC
+        cpu.bus.memory[0x0201] = 0xD8; // RET C
+        cpu.bus.memory[0x0003] = 0x38; // JR C,+0
+        cpu.bus.memory[0x0004] = 0x00;
+        cpu.bus.memory[0x0005] = 0x30; // JR NC,+0
+        cpu.bus.memory[0x0006] = 0x00;
+
+        assert_eq!(cpu.step_with_cycles().unwrap(), 24);
+        assert_eq!(cpu.step_with_cycles().unwrap(), 8);
+        assert_eq!(cpu.step_with_cycles().unwrap(), 20);
+        assert_eq!(cpu.pc, 0x0003);
+        assert_eq!(cpu.step_with_cycles().unwrap(), 12);
+        assert_eq!(cpu.step_with_cycles().unwrap(), 8);
+        assert_eq!(cpu.pc, 0x0007);
+
+        cpu.registers.f.carry = false;
+        cpu.pc = 0x0000;
+        assert_eq!(cpu.step_with_cycles().unwrap(), 12);
+    }
+
+    // サイクル数のテスト: stepは消費したサイクル数を積算する
+    #[test]
+    fn test_step_accumulates_cycles() {
+        let mut cpu = CPU::default();
+        cpu.bus.memory[0x0000] = 0x00; // NOP: 4
+        cpu.bus.memory[0x0001] = 0x06; // LD B,d8: 8
+        cpu.bus.memory[0x0003] = 0xCB; // RES 0,(HL): 16
+        cpu.bus.memory[0x0004] = 0x86;
+        cpu.bus.memory[0x0005] = 0xCB; // BIT 0,(HL): 12
+        cpu.bus.memory[0x0006] = 0x46;
+        cpu.registers.set_hl(0xC000);
+
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.cycles, 4 + 8 + 16 + 12);
+    }
+
+    // タイマーのテスト: stepで消費したサイクル数だけDIVが進む
+    #[test]
+    fn test_step_advances_div() {
+        let mut cpu = CPU::default();
+        // メモリは0で埋められているので、NOPを64回（256Tサイクル）実行する
+        for _ in 0..63 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.bus.read_byte(0xFF04), 0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xFF04), 1);
+    }
+
+    // フレームのテスト: 1フレーム分のサイクルだけ進み、VBlankが1回起きる
+    #[test]
+    fn test_run_frame() {
+        let mut cpu = CPU::default();
+        cpu.bus.write_byte(LCDC_ADDRESS, 0x80);
+        // メモリは0で埋められているのでNOPを実行し続ける
+        let framebuffer_len = cpu.run_frame().unwrap().len();
+        assert_eq!(framebuffer_len, 160 * 144);
+        assert_eq!(cpu.cycles, CYCLES_PER_FRAME);
+        assert_eq!(cpu.bus.read_byte(0xFF44), 0);
+        assert_eq!(cpu.bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x01, 0x01);
+
+        cpu.run_frame().unwrap();
+        assert_eq!(cpu.cycles, 2 * CYCLES_PER_FRAME);
+    }
+
+    // run_frameごとにフレームのコールバックがちょうど1回呼ばれる
+    #[test]
+    fn test_frame_callback_once_per_frame() {
+        let mut cpu = CPU::default();
+        cpu.bus.write_byte(LCDC_ADDRESS, 0x80);
+        cpu.bus.memory[0x0000] = 0x18; // JR -2
+        cpu.bus.memory[0x0001] = 0xFE;
+        let frames = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = frames.clone();
+        cpu.set_frame_callback(Box::new(move |framebuffer| {
+            assert_eq!(framebuffer.len(), 160 * 144);
+            counter.set(counter.get() + 1);
+        }));
+
+        for frame in 1..=3 {
+            cpu.run_frame().unwrap();
+            assert_eq!(frames.get(), frame);
+        }
+    }
+
+    // LD (BC),Aのテスト: BCが指すアドレスにAを書き込む
+    #[test]
+    fn test_step_ld_indirect_bc_from_a() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0x02;
+        cpu.registers.a = 0x5A;
+        cpu.registers.set_bc(0xC123);
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xC123), 0x5A);
+        assert_eq!(cpu.pc, 0x0101);
+    }
+
+    // LD A,(DE)のテスト: DEが指すアドレスからAに読み込む
+    #[test]
+    fn test_step_ld_a_from_indirect_de() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0x1A;
+        cpu.bus.write_byte(0xC200, 0x77);
+        cpu.registers.set_de(0xC200);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x77);
+        assert_eq!(cpu.pc, 0x0101);
+    }
+
+    // LD (HL+),Aのテスト: 書き込んだあとにHLが1増える
+    #[test]
+    fn test_step_ld_hl_increment_from_a() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0x22;
+        cpu.registers.a = 0x11;
+        cpu.registers.set_hl(0xC000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xC000), 0x11);
+        assert_eq!(cpu.registers.get_hl(), 0xC001);
+    }
+
+    // LD A,(HL-)のテスト: 読み込んだあとにHLが1減る
+    #[test]
+    fn test_step_ld_a_from_hl_decrement() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0x3A;
+        cpu.bus.write_byte(0xC010, 0x99);
+        cpu.registers.set_hl(0xC010);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x99);
+        assert_eq!(cpu.registers.get_hl(), 0xC00F);
+    }
+
+    // LD (a16),AとLD A,(a16)のテスト: 即値のアドレス（リトルエンディアン）を読み書きし、3バイト進む
+    #[test]
+    fn test_step_ld_absolute() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0xEA; // LD (0xC000),A
+        cpu.bus.memory[0x0101] = 0x00;
+        cpu.bus.memory[0x0102] = 0xC0;
+        cpu.bus.memory[0x0103] = 0xFA; // LD A,(0xC000)
+        cpu.bus.memory[0x0104] = 0x00;
+        cpu.bus.memory[0x0105] = 0xC0;
+        cpu.registers.a = 0x3C;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xC000), 0x3C);
+        assert_eq!(cpu.pc, 0x0103);
+
+        cpu.registers.a = 0x00;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x3C);
+        assert_eq!(cpu.pc, 0x0106);
+    }
+
+    // LDH (a8),Aのテスト: 0xFF00 + a8（ここではBGP）にAを書き込み、2バイト進む
+    #[test]
+    fn test_step_ldh_to_io_register() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0xE0;
+        cpu.bus.memory[0x0101] = 0x47;
+        cpu.registers.a = 0xE4;
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(BGP_ADDRESS), 0xE4);
+        assert_eq!(cpu.bus.ppu.bgp, 0xE4);
+        assert_eq!(cpu.pc, 0x0102);
+    }
+
+    // LDH A,(a8)のテスト: 高位RAMから読み込む
+    #[test]
+    fn test_step_ldh_from_high_ram() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0xF0;
+        cpu.bus.memory[0x0101] = 0x80;
+        cpu.bus.write_byte(0xFF80, 0x42);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x42);
+        assert_eq!(cpu.pc, 0x0102);
+    }
 
-    // addでオーバーフローが発生しない場合のテスト
+    // LD (C),AとLD A,(C)のテスト: 0xFF00 + Cを読み書きし、1バイト進む
     #[test]
-    fn test_add_no_overflow() {
+    fn test_step_ld_high_c() {
         let mut cpu = CPU::default();
-        cpu.registers.a = 0x01;
-        cpu.registers.c = 0x02;
-        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
-        assert_eq!(cpu.registers.a, 0x03);
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0xE2;
+        cpu.bus.memory[0x0101] = 0xF2;
+        cpu.registers.c = 0x90;
+        cpu.registers.a = 0x12;
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xFF90), 0x12);
+        assert_eq!(cpu.pc, 0x0101);
+
+        cpu.registers.a = 0x00;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x12);
+        assert_eq!(cpu.pc, 0x0102);
     }
 
-    // addでオーバーフローが発生する場合のテスト
+    // LD (a16),SPのテスト: SPの下位バイト、上位バイトの順に書き込み、3バイト進む
     #[test]
-    fn test_add_overflow() {
+    fn test_step_ld_absolute_sp() {
         let mut cpu = CPU::default();
-        cpu.registers.a = 0xFF;
-        cpu.registers.c = 0x01;
-        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
-        assert_eq!(cpu.registers.a, 0x00);
+        cpu.pc = 0x0100;
+        cpu.sp = 0xFFFE;
+        cpu.bus.memory[0x0100] = 0x08;
+        cpu.bus.memory[0x0101] = 0x00;
+        cpu.bus.memory[0x0102] = 0xC1;
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xC100), 0xFE);
+        assert_eq!(cpu.bus.read_byte(0xC101), 0xFF);
+        assert_eq!(cpu.pc, 0x0103);
     }
 
-    // addでhalf_carryが発生する場合のテスト
+    // LD SP,HLのテスト: フラグは変更せず1バイト進む
     #[test]
-    fn test_add_half_carry() {
+    fn test_ld_sp_hl() {
         let mut cpu = CPU::default();
-        cpu.registers.a = 0x0F;
-        cpu.registers.c = 0x01;
-        cpu.execute(Instruction::ADD(ArithmeticTarget::C));
-        assert_eq!(cpu.registers.a, 0x10);
+        cpu.registers.set_hl(0xC100);
+        cpu.registers.f.carry = true;
+        let next_pc = cpu.execute(Instruction::LD(LoadType::Word(
+            LoadWordTarget::SP,
+            LoadWordSource::HL,
+        )));
+        assert_eq!(cpu.sp, 0xC100);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.zero);
+        assert_eq!(next_pc, 0x0001);
     }
 
+    // STOPのテスト: 2バイト進み、DIVをリセットしてボタンが押されるまで止まる
     #[test]
-    fn test_jump_not_zero_taken() {
+    fn test_step_stop() {
         let mut cpu = CPU::default();
-        cpu.pc = 0x0100;
-        cpu.registers.f.zero = false;
-        cpu.bus.memory[0x0101] = 0x34;
-        cpu.bus.memory[0x0102] = 0x12;
+        cpu.pc = 0xC000;
+        cpu.bus.write_byte(0xC000, 0x10);
+        cpu.bus.write_byte(0xC001, 0x00);
+        cpu.bus.write_byte(0xC002, 0x3C); // INC A
+        for _ in 0..100 {
+            cpu.bus.tick(4);
+        }
+        assert_ne!(cpu.bus.read_byte(DIV_ADDRESS), 0);
 
-        let next_pc = cpu.execute(Instruction::JP(JumpTest::NotZero));
-        assert_eq!(next_pc, 0x1234);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0xC002);
+        assert!(cpu.stopped);
+        assert_eq!(cpu.bus.read_byte(DIV_ADDRESS), 0);
+
+        // ボタンが押されるまでは命令を実行せず、DIVも進まない
+        for _ in 0..100 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0xC002);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(cpu.bus.read_byte(DIV_ADDRESS), 0);
+
+        cpu.bus.press_button(crate::joypad::Button::Start);
+        cpu.step().unwrap();
+        assert!(!cpu.stopped);
+        assert_eq!(cpu.registers.a, 0x01);
     }
 
+    // KEY1のビット0を立ててSTOPを実行すると、止まらずに速度が切り替わる
     #[test]
-    fn test_jump_not_zero_not_taken() {
+    fn test_stop_switches_speed() {
         let mut cpu = CPU::default();
-        cpu.pc = 0x0100;
-        cpu.registers.f.zero = true;
+        cpu.pc = 0xC000;
+        cpu.bus.load_at(0xC000, &[0x10, 0x00, 0x10, 0x00]); // STOP, STOP
+        assert_eq!(cpu.bus.read_byte(KEY1_ADDRESS), 0x7E);
+        cpu.bus.write_byte(KEY1_ADDRESS, 0x01);
+        assert_eq!(cpu.bus.read_byte(KEY1_ADDRESS), 0x7F);
 
-        let next_pc = cpu.execute(Instruction::JP(JumpTest::NotZero));
-        assert_eq!(next_pc, 0x0103);
+        cpu.step().unwrap();
+        assert!(cpu.double_speed());
+        assert!(!cpu.stopped);
+        assert_eq!(cpu.pc, 0xC002);
+        assert_eq!(cpu.bus.read_byte(KEY1_ADDRESS), 0xFE);
+
+        // 準備していなければ、通常のSTOPとして止まる
+        cpu.step().unwrap();
+        assert!(cpu.double_speed());
+        assert!(cpu.stopped);
     }
 
+    // 倍速モードではDIVが2倍の速さで進む
     #[test]
-    fn test_jump_carry_taken() {
+    fn test_double_speed_div() {
         let mut cpu = CPU::default();
-        cpu.pc = 0x0200;
-        cpu.registers.f.carry = true;
-        cpu.bus.memory[0x0201] = 0x78;
-        cpu.bus.memory[0x0202] = 0x56;
+        cpu.bus.tick(128);
+        assert_eq!(cpu.bus.read_byte(DIV_ADDRESS), 0);
 
-        let next_pc = cpu.execute(Instruction::JP(JumpTest::Carry));
-        assert_eq!(next_pc, 0x5678);
+        cpu.bus.write_byte(KEY1_ADDRESS, 0x01);
+        cpu.bus.write_byte(DIV_ADDRESS, 0);
+        cpu.pc = 0xC000;
+        cpu.bus.write_byte(0xC000, 0x10); // STOP
+        cpu.step().unwrap();
+        assert!(cpu.double_speed());
+        cpu.bus.write_byte(DIV_ADDRESS, 0);
+        cpu.bus.tick(128);
+        assert_eq!(cpu.bus.read_byte(DIV_ADDRESS), 1);
     }
 
+    // ブレークポイントのテスト: 3命令目の手前で止まり、それまでの命令は実行されている
     #[test]
-    fn test_jump_carry_not_taken() {
+    fn test_run_until_breakpoint() {
         let mut cpu = CPU::default();
-        cpu.pc = 0x0200;
-        cpu.registers.f.carry = false;
+        cpu.pc = 0xC000;
+        cpu.bus.write_byte(0xC000, 0x06); // LD B,0x12
+        cpu.bus.write_byte(0xC001, 0x12);
+        cpu.bus.write_byte(0xC002, 0x0E); // LD C,0x34
+        cpu.bus.write_byte(0xC003, 0x34);
+        cpu.bus.write_byte(0xC004, 0x04); // INC B
+        cpu.bus.write_byte(0xC005, 0x0C); // INC C
 
-        let next_pc = cpu.execute(Instruction::JP(JumpTest::Carry));
-        assert_eq!(next_pc, 0x0203);
+        let breakpoints = HashSet::from([0xC004]);
+        assert_eq!(cpu.run_until_breakpoint(&breakpoints).unwrap(), 0xC004);
+        assert_eq!(cpu.registers.b, 0x12);
+        assert_eq!(cpu.registers.c, 0x34);
+
+        // ブレークポイントから再開すると、その命令を実行して先に進む
+        let breakpoints = HashSet::from([0xC004, 0xC005]);
+        assert_eq!(cpu.run_until_breakpoint(&breakpoints).unwrap(), 0xC005);
+        assert_eq!(cpu.registers.b, 0x13);
+        assert_eq!(cpu.registers.c, 0x34);
     }
 
+    // HALTで止まった場合はブレークポイントが無くても戻る
     #[test]
-    fn test_jump_always() {
+    fn test_run_until_breakpoint_stops_on_halt() {
         let mut cpu = CPU::default();
-        cpu.pc = 0x0300;
-        cpu.bus.memory[0x0301] = 0xAA;
-        cpu.bus.memory[0x0302] = 0xBB;
+        cpu.pc = 0xC000;
+        cpu.bus.write_byte(0xC000, 0x00); // NOP
+        cpu.bus.write_byte(0xC001, 0x76); // HALT
+        assert_eq!(cpu.run_until_breakpoint(&HashSet::new()).unwrap(), 0xC002);
+        assert!(cpu.halted);
+    }
 
-        let next_pc = cpu.execute(Instruction::JP(JumpTest::Always));
-        assert_eq!(next_pc, 0xBBAA);
+    // トレースのテスト: gameboy-doctorの形式で状態を出力する
+    #[test]
+    fn test_log_state() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_af(0x01B0);
+        cpu.registers.set_bc(0x0013);
+        cpu.registers.set_de(0x00D8);
+        cpu.registers.set_hl(0x014D);
+        cpu.sp = 0xFFFE;
+        cpu.pc = 0x0100;
+        cpu.bus.memory[0x0100] = 0x00;
+        cpu.bus.memory[0x0101] = 0xC3;
+        cpu.bus.memory[0x0102] = 0x13;
+        cpu.bus.memory[0x0103] = 0x02;
+        assert_eq!(
+            cpu.log_state(),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02"
+        );
     }
 
+    // トレースを有効にすると、各ステップの前の状態が1行ずつ書き出される
     #[test]
-    #[should_panic(expected = "Unkown instruction found for: 0x00")]
-    fn test_step_non_prefixed_unknown_instruction() {
+    fn test_trace_before_each_step() {
+        let path = std::env::temp_dir().join(format!("gameboy_trace_{}.log", std::process::id()));
         let mut cpu = CPU::default();
-        cpu.bus.memory[0] = 0x00; // 未知の非プレフィックス命令
-        cpu.step();
+        cpu.pc = 0xC000;
+        cpu.bus.write_byte(0xC000, 0x3C); // INC A
+        cpu.enable_trace(Box::new(std::fs::File::create(&path).unwrap()));
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        drop(cpu);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("A:00 F:00"));
+        assert!(lines[0].contains("PC:C000 PCMEM:3C,00,00,00"));
+        assert!(lines[1].starts_with("A:01 F:00"));
+        assert!(lines[1].contains("PC:C001"));
     }
 
+    // NOPのテスト: レジスタを変更せずに1バイト進む
     #[test]
-    #[should_panic(expected = "Unkown instruction found for: 0xCB00")]
-    fn test_step_prefixed_unknown_instruction() {
+    fn test_step_nop() {
         let mut cpu = CPU::default();
-        cpu.bus.memory[0] = 0xCB;
-        cpu.bus.memory[1] = 0x00; // 未知のプレフィックス命令
-        cpu.step();
+        cpu.pc = 0x0100;
+        cpu.sp = 0xFFFE;
+        cpu.registers.set_af(0x12F0);
+        cpu.registers.set_bc(0x3456);
+        cpu.registers.set_de(0x789A);
+        cpu.registers.set_hl(0xBCDE);
+        cpu.bus.memory[0x0100] = 0x00;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0101);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.registers.get_af(), 0x12F0);
+        assert_eq!(cpu.registers.get_bc(), 0x3456);
+        assert_eq!(cpu.registers.get_de(), 0x789A);
+        assert_eq!(cpu.registers.get_hl(), 0xBCDE);
     }
 
     // LD命令のテスト: レジスタ間のロード
@@ -327,6 +3509,32 @@ mod tests {
         assert_eq!(next_pc, 0x0202); // D8の場合は2バイト進む
     }
 
+    // LD r,d8の8つのオペコードはすべて、オペコードの次のバイトを読んで2バイト進む
+    #[test]
+    fn test_step_ld_immediate_all_targets() {
+        let opcodes = [0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E, 0x36, 0x3E];
+        for (index, opcode) in opcodes.into_iter().enumerate() {
+            let mut cpu = CPU::default();
+            cpu.pc = 0x0200;
+            cpu.registers.set_hl(0xC000);
+            cpu.bus.memory[0x0200] = opcode;
+            cpu.bus.memory[0x0201] = 0x80 + index as u8;
+            cpu.step().unwrap();
+            let value = match opcode {
+                0x06 => cpu.registers.b,
+                0x0E => cpu.registers.c,
+                0x16 => cpu.registers.d,
+                0x1E => cpu.registers.e,
+                0x26 => cpu.registers.h,
+                0x2E => cpu.registers.l,
+                0x36 => cpu.bus.read_byte(0xC000),
+                _ => cpu.registers.a,
+            };
+            assert_eq!(value, 0x80 + index as u8, "opcode 0x{:02X}", opcode);
+            assert_eq!(cpu.pc, 0x0202, "opcode 0x{:02X}", opcode);
+        }
+    }
+
     // LD命令のテスト: メモリ（HLI）からレジスタへのロード
     #[test]
     fn test_ld_memory_to_register() {
@@ -376,6 +3584,49 @@ mod tests {
         assert_eq!(next_pc, 0x0502);
     }
 
+    // LD命令のテスト: 即値（D16）からSPへのロード
+    #[test]
+    fn test_ld_sp_immediate() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        // read_next_wordはself.pc + 1とself.pc + 2から読み込む（little endian）
+        cpu.bus.memory[0x0101] = 0xFE;
+        cpu.bus.memory[0x0102] = 0xFF;
+        let next_pc = cpu.execute(Instruction::LD(LoadType::Word(
+            LoadWordTarget::SP,
+            LoadWordSource::D16,
+        )));
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(next_pc, 0x0103); // D16の場合は3バイト進む
+    }
+
+    // LD命令のテスト: 即値（D16）からHLへのロード
+    #[test]
+    fn test_ld_hl_immediate() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        cpu.bus.memory[0x0201] = 0x34;
+        cpu.bus.memory[0x0202] = 0x12;
+        let next_pc = cpu.execute(Instruction::LD(LoadType::Word(
+            LoadWordTarget::HL,
+            LoadWordSource::D16,
+        )));
+        assert_eq!(cpu.registers.get_hl(), 0x1234);
+        assert_eq!(next_pc, 0x0203);
+    }
+
+    // stepのテスト: メモリ上の 0x31 0xFE 0xFF (LD SP,d16) をデコードして実行する
+    #[test]
+    fn test_step_ld_sp_immediate() {
+        let mut cpu = CPU::default();
+        cpu.bus.memory[0x0000] = 0x31;
+        cpu.bus.memory[0x0001] = 0xFE;
+        cpu.bus.memory[0x0002] = 0xFF;
+        cpu.step().unwrap();
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.pc, 0x0003);
+    }
+
     // push/popのテスト: 基本的なpushとpop
     #[test]
     fn test_push_pop() {
@@ -428,8 +3679,8 @@ mod tests {
 
         // little endianで保存される
         // 最下位バイトが先（sp）、最上位バイトが後（sp+1）
-        assert_eq!(cpu.bus.memory[0xFFFC], 0xCD); // 最下位バイト
-        assert_eq!(cpu.bus.memory[0xFFFD], 0xAB); // 最上位バイト
+        assert_eq!(cpu.bus.read_byte(0xFFFC), 0xCD); // 最下位バイト
+        assert_eq!(cpu.bus.read_byte(0xFFFD), 0xAB); // 最上位バイト
     }
 
     // push/popのテスト: 境界値（0x0000と0xFFFF）
@@ -447,6 +3698,77 @@ mod tests {
         assert_eq!(cpu.pop(), 0xFFFF);
     }
 
+    // PUSH/POP命令のテスト: BCをpushしてDEにpopする
+    #[test]
+    fn test_instruction_push_bc_pop_de() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.sp = 0xFFFE;
+        cpu.registers.set_bc(0xABCD);
+
+        let next_pc = cpu.execute(Instruction::PUSH(StackTarget::BC));
+        assert_eq!(next_pc, 0x0101);
+        assert_eq!(cpu.sp, 0xFFFC);
+        assert_eq!(cpu.bus.read_byte(0xFFFD), 0xAB); // 最上位バイト
+        assert_eq!(cpu.bus.read_byte(0xFFFC), 0xCD); // 最下位バイト
+
+        let next_pc = cpu.execute(Instruction::POP(StackTarget::DE));
+        assert_eq!(next_pc, 0x0101);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.registers.get_de(), 0xABCD);
+    }
+
+    // POP AFのテスト: Fの下位4ビットは0になる
+    #[test]
+    fn test_instruction_pop_af_masks_flags() {
+        let mut cpu = CPU::default();
+        cpu.sp = 0xFFFE;
+        cpu.push(0x12FF);
+
+        cpu.execute(Instruction::POP(StackTarget::AF));
+        assert_eq!(cpu.registers.a, 0x12);
+        assert_eq!(cpu.registers.get_af(), 0x12F0);
+
+        cpu.execute(Instruction::PUSH(StackTarget::AF));
+        assert_eq!(cpu.bus.read_byte(0xFFFC), 0xF0);
+    }
+
+    // RST命令のテスト: ベクタに飛び、戻り先がスタックに積まれる
+    #[test]
+    fn test_instruction_rst() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0150;
+        cpu.sp = 0xFFFE;
+
+        let next_pc = cpu.execute(Instruction::RST(0x38));
+        assert_eq!(next_pc, 0x0038);
+        assert_eq!(cpu.sp, 0xFFFC);
+        assert_eq!(cpu.pop(), 0x0151);
+    }
+
+    // stepのテスト: すべてのRSTオペコードが正しいベクタに飛ぶ
+    #[test]
+    fn test_step_rst_vectors() {
+        for (opcode, vector) in [
+            (0xC7, 0x00),
+            (0xCF, 0x08),
+            (0xD7, 0x10),
+            (0xDF, 0x18),
+            (0xE7, 0x20),
+            (0xEF, 0x28),
+            (0xF7, 0x30),
+            (0xFF, 0x38),
+        ] {
+            let mut cpu = CPU::default();
+            cpu.pc = 0x0200;
+            cpu.sp = 0xFFFE;
+            cpu.bus.memory[0x0200] = opcode;
+            cpu.step().unwrap();
+            assert_eq!(cpu.pc, vector);
+            assert_eq!(cpu.pop(), 0x0201);
+        }
+    }
+
     // callのテスト: ジャンプする場合
     #[test]
     fn test_call_jump() {
@@ -577,6 +3899,22 @@ mod tests {
         assert_eq!(cpu.sp, 0xFFFE);
     }
 
+    // CALL命令のテスト: 無条件
+    #[test]
+    fn test_instruction_call_always() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0A00;
+        cpu.sp = 0xFFFE;
+        cpu.registers.f.zero = true;
+        cpu.bus.memory[0x0A01] = 0x00;
+        cpu.bus.memory[0x0A02] = 0x20;
+
+        let next_pc = cpu.execute(Instruction::CALL(JumpTest::Always));
+
+        assert_eq!(next_pc, 0x2000);
+        assert_eq!(cpu.pop(), 0x0A03);
+    }
+
     // RET命令のテスト: ジャンプする場合
     #[test]
     fn test_instruction_ret_jump() {
@@ -594,6 +3932,177 @@ mod tests {
         assert_eq!(cpu.sp, 0xFFFE);
     }
 
+    // RET命令のテスト: Carry条件
+    #[test]
+    fn test_instruction_ret_carry() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0B00;
+        cpu.sp = 0xFFFE;
+        cpu.push(0x4321);
+
+        cpu.registers.f.carry = false;
+        assert_eq!(cpu.execute(Instruction::RET(JumpTest::Carry)), 0x0B01);
+
+        cpu.registers.f.carry = true;
+        assert_eq!(cpu.execute(Instruction::RET(JumpTest::Carry)), 0x4321);
+        assert_eq!(cpu.sp, 0xFFFE);
+    }
+
+    // stepのテスト: デコードした命令を順番に実行する
+    #[test]
+    fn test_step_decoded_program() {
+        let mut cpu = CPU::default();
+        // LD A,0x05 / LD B,0x03 / LD C,0x02 / ADD A,C / JP 0x0000
+        let program = [0x3E, 0x05, 0x06, 0x03, 0x0E, 0x02, 0x81, 0xC3, 0x00, 0x00];
+        cpu.bus.memory[..program.len()].copy_from_slice(&program);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x05);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.b, 0x03);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.c, 0x02);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x07);
+        assert_eq!(cpu.pc, 0x0007);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0000);
+    }
+
+    // ウォッチポイントには書き込んだ命令のpcが記録される
+    #[test]
+    fn test_step_records_watch_event() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0100;
+        cpu.registers.a = 0x5A;
+        cpu.bus.memory[0x0100] = 0x00; // NOP
+        cpu.bus.memory[0x0101] = 0xEA; // LD ($C000),A
+        cpu.bus.memory[0x0102] = 0x00;
+        cpu.bus.memory[0x0103] = 0xC0;
+        cpu.bus.add_watch(0xC000, WatchKind::Both);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        let events = cpu.bus.take_watch_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].pc, 0x0101);
+        assert_eq!(events[0].value, 0x5A);
+        assert_eq!(events[0].kind, WatchKind::Write);
+    }
+
+    // 状態の表示やトレースのための読み込みは、ウォッチポイントに記録されない
+    #[test]
+    fn test_debug_output_not_watched() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0xC000;
+        cpu.bus.load_at(0xC000, &[0x3E, 0x12]); // LD A,$12
+        cpu.bus.add_watch(0xC000, WatchKind::Read);
+        cpu.bus.add_watch(0xC001, WatchKind::Read);
+
+        cpu.log_state();
+        cpu.trace_line();
+        assert!(cpu.bus.take_watch_events().is_empty());
+    }
+
+    // ステップバックのテスト: 3命令進めて2回戻ると、1命令目を実行した直後の状態になる
+    #[test]
+    fn test_step_back() {
+        let mut cpu = CPU::default();
+        cpu.enable_step_back(8);
+        let program = [0x3E, 0x01, 0x06, 0x02, 0x0E, 0x03]; // LD A,1; LD B,2; LD C,3
+        cpu.bus.memory[..program.len()].copy_from_slice(&program);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.c, 0x03);
+
+        assert!(cpu.step_back());
+        assert!(cpu.step_back());
+        assert_eq!(cpu.pc, 0x0002);
+        assert_eq!(cpu.registers.a, 0x01);
+        assert_eq!(cpu.registers.b, 0x00);
+        assert_eq!(cpu.registers.c, 0x00);
+        assert_eq!(cpu.cycles, 8);
+
+        assert!(cpu.step_back());
+        assert_eq!(cpu.pc, 0x0000);
+        assert!(!cpu.step_back());
+    }
+
+    // 深さを超えた古いスナップショットは捨てられる
+    #[test]
+    fn test_step_back_depth() {
+        let mut cpu = CPU::default();
+        cpu.enable_step_back(2);
+        for _ in 0..5 {
+            cpu.step().unwrap(); // NOP
+        }
+        assert!(cpu.step_back());
+        assert!(cpu.step_back());
+        assert_eq!(cpu.pc, 0x0003);
+        assert!(!cpu.step_back());
+    }
+
+    // スナップショットのテスト: 状態を変えてから戻すと、保存したときと同じ状態になる
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut cpu = CPU::default();
+        cpu.registers.set_af(0x12F0);
+        cpu.registers.set_hl(0xC123);
+        cpu.pc = 0x0150;
+        cpu.sp = 0xDFF0;
+        cpu.ime = true;
+        cpu.halted = true;
+        cpu.cycles = 12345;
+        cpu.bus.write_byte(0xC000, 0xAB);
+        cpu.bus.write_byte(0x8000, 0xCD); // VRAM
+        cpu.bus.write_byte(0xFF05, 0x42); // TIMA
+        cpu.bus.write_byte(0xFF40, 0x91); // LCDC
+        cpu.bus.tick(200);
+        let snapshot = cpu.snapshot();
+
+        cpu.registers.set_af(0x0000);
+        cpu.registers.set_hl(0x0000);
+        cpu.pc = 0x0000;
+        cpu.sp = 0xFFFE;
+        cpu.ime = false;
+        cpu.halted = false;
+        cpu.cycles = 0;
+        cpu.bus.write_byte(0xC000, 0x00);
+        cpu.bus.write_byte(0x8000, 0x00);
+        cpu.bus.write_byte(0xFF05, 0x00);
+        cpu.bus.tick(100);
+
+        cpu.restore(&snapshot).unwrap();
+        assert_eq!(cpu.registers.get_af(), 0x12F0);
+        assert_eq!(cpu.registers.get_hl(), 0xC123);
+        assert_eq!(cpu.pc, 0x0150);
+        assert_eq!(cpu.sp, 0xDFF0);
+        assert!(cpu.ime);
+        assert!(cpu.halted);
+        assert_eq!(cpu.cycles, 12345);
+        assert_eq!(cpu.bus.read_byte(0xC000), 0xAB);
+        assert_eq!(cpu.bus.read_byte(0x8000), 0xCD);
+        assert_eq!(cpu.bus.read_byte(0xFF05), 0x42);
+        assert_eq!(cpu.bus.ppu.mode(), crate::ppu::PpuMode::PixelTransfer);
+        assert_eq!(cpu.snapshot(), snapshot);
+    }
+
+    // 壊れたスナップショットは読み込まず、状態も変えない
+    #[test]
+    fn test_restore_rejects_invalid_snapshot() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0150;
+        let mut snapshot = cpu.snapshot();
+        cpu.pc = 0x0200;
+
+        assert_eq!(cpu.restore(b"XXXX"), Err(SnapshotError::InvalidMagic));
+        snapshot.truncate(snapshot.len() - 1);
+        assert_eq!(cpu.restore(&snapshot), Err(SnapshotError::UnexpectedEnd));
+        assert_eq!(cpu.pc, 0x0200);
+    }
+
     // RET命令のテスト: ジャンプしない場合
     #[test]
     fn test_instruction_ret_no_jump() {
@@ -607,4 +4116,254 @@ mod tests {
         assert_eq!(next_pc, 0x0901);
         assert_eq!(cpu.sp, 0xFFFE);
     }
+
+    // 記録した入力を同じ状態から再生すると、同じ状態になる
+    #[test]
+    fn test_replay_reaches_recorded_state() {
+        let mut cpu = CPU::default();
+        cpu.bus.write_byte(0xFFFF, 0x10); // IE: ジョイパッド
+        cpu.bus.write_byte(0xFF00, 0x10); // P1: ボタンを選択
+        let start = cpu.snapshot();
+
+        cpu.start_recording();
+        cpu.run_frame().unwrap();
+        cpu.bus.set_buttons(0x10); // A
+        cpu.run_frame().unwrap();
+        cpu.run_frame().unwrap();
+        cpu.bus.set_buttons(0x80); // スタート
+        cpu.run_frame().unwrap();
+        cpu.bus.set_buttons(0x00);
+        cpu.run_frame().unwrap();
+        let log = cpu.stop_recording();
+        let end = cpu.snapshot();
+        assert_eq!(log.entries, vec![(0, 0x00), (1, 0x10), (3, 0x80), (4, 0x00)]);
+
+        cpu.restore(&start).unwrap();
+        cpu.replay(&log);
+        for _ in 0..5 {
+            cpu.run_frame().unwrap();
+        }
+        assert_eq!(cpu.snapshot(), end);
+    }
+
+    #[test]
+    fn test_debug_state() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x12;
+        cpu.registers.c = 0x34;
+        cpu.registers.l = 0x56;
+        cpu.registers.f.zero = true;
+        cpu.registers.f.carry = true;
+        cpu.pc = 0x0150;
+        cpu.sp = 0xDFF0;
+        cpu.ime = true;
+        assert_eq!(
+            cpu.debug_state(),
+            CpuDebugState {
+                a: 0x12,
+                b: 0x00,
+                c: 0x34,
+                d: 0x00,
+                e: 0x00,
+                f: 0x90,
+                h: 0x00,
+                l: 0x56,
+                zero: true,
+                subtract: false,
+                half_carry: false,
+                carry: true,
+                pc: 0x0150,
+                sp: 0xDFF0,
+                ime: true,
+                halted: false,
+            }
+        );
+    }
+
+    // アドレス空間の末尾にあるJPのオペランドは0x0000に折り返して読む
+    #[test]
+    fn test_jump_wraps_at_top_of_address_space() {
+        let mut cpu = CPU::default();
+        cpu.bus.memory[0xFFFF] = 0x34;
+        cpu.bus.memory[0x0000] = 0x12;
+        cpu.bus.memory[0x0001] = 0x56;
+        cpu.pc = 0xFFFE;
+        assert_eq!(cpu.jump(true), 0x1234);
+        cpu.pc = 0xFFFF;
+        assert_eq!(cpu.jump(true), 0x5612);
+        assert_eq!(cpu.jump(false), 0x0002);
+    }
+
+    // 0xFFFFにあるプレフィックスの次のオペコードは0x0000から読む
+    #[test]
+    fn test_prefix_wraps_at_top_of_address_space() {
+        let mut cpu = CPU::default();
+        cpu.bus.memory[0xFFFF] = 0xCB;
+        cpu.bus.memory[0x0000] = 0x37; // SWAP A
+        cpu.registers.a = 0x12;
+        cpu.pc = 0xFFFF;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x21);
+        assert_eq!(cpu.pc, 0x0001);
+    }
+
+    // 読み書きしたアドレスを順に記録するだけのメモリ
+    #[derive(Default)]
+    struct RecordingMemory {
+        accesses: std::cell::RefCell<Vec<(u16, Option<u8>)>>,
+    }
+
+    impl Memory for RecordingMemory {
+        fn read_byte(&self, address: u16) -> u8 {
+            self.accesses.borrow_mut().push((address, None));
+            0x42
+        }
+
+        fn write_byte(&mut self, address: u16, value: u8) {
+            self.accesses.get_mut().push((address, Some(value)));
+        }
+    }
+
+    #[test]
+    fn test_execute_with_mock_memory() {
+        let mut cpu = CPU::with_memory(RecordingMemory::default());
+        cpu.pc = 0x0200;
+        cpu.sp = 0xFFFE;
+        let next_pc = cpu.execute(Instruction::LD(LoadType::Byte(
+            LoadByteTarget::B,
+            LoadByteSource::D8,
+        )));
+        assert_eq!(cpu.registers.b, 0x42);
+        assert_eq!(next_pc, 0x0202);
+
+        cpu.registers.set_bc(0x1234);
+        cpu.execute(Instruction::PUSH(StackTarget::BC));
+        assert_eq!(
+            cpu.bus.accesses.take(),
+            vec![(0x0201, None), (0xFFFD, Some(0x12)), (0xFFFC, Some(0x34))]
+        );
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut cpu = CPU::default();
+        cpu.pc = 0x0200;
+        let program = [
+            0x00, // NOP
+            0x3E, 0x12, // LD A,d8
+            0x21, 0x00, 0xC0, // LD HL,d16
+            0x77, // LD (HL),A
+            0xCB, 0x37, // SWAP A
+        ];
+        cpu.bus.memory[0x0200..0x0200 + program.len()].copy_from_slice(&program);
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.instructions_executed(), 5);
+        let expected = [0x00, 0x3E, 0x21, 0x77]
+            .iter()
+            .map(|&byte| CYCLES[byte] as u64)
+            .sum::<u64>()
+            + CB_CYCLES[0x37] as u64;
+        assert_eq!(cpu.cycles_elapsed(), expected);
+
+        cpu.reset_counters();
+        assert_eq!(cpu.instructions_executed(), 0);
+        assert_eq!(cpu.cycles_elapsed(), 0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.instructions_executed(), 1);
+    }
+
+    #[test]
+    fn test_trace_line() {
+        let mut cpu = CPU::default();
+        cpu.registers.a = 0x42;
+        cpu.registers.f.zero = true;
+        cpu.registers.f.half_carry = true;
+        cpu.registers.set_bc(0x0013);
+        cpu.registers.set_de(0x00D8);
+        cpu.registers.set_hl(0x014D);
+        cpu.sp = 0xFFFE;
+        cpu.pc = 0x0150;
+        cpu.bus.memory[0x0150] = 0x78; // LD A,B
+        assert_eq!(
+            cpu.trace_line(),
+            "0x0150: LD A,B        A:42 F:Z-H- BC:0013 DE:00D8 HL:014D SP:FFFE"
+        );
+
+        // JP $0150
+        cpu.pc = 0x0160;
+        cpu.bus.memory[0x0160] = 0xC3;
+        cpu.bus.memory[0x0161] = 0x50;
+        cpu.bus.memory[0x0162] = 0x01;
+        cpu.set_symbols(HashMap::from([
+            (0x0150, "main".to_string()),
+            (0x0160, "loop".to_string()),
+        ]));
+        assert_eq!(
+            cpu.trace_line(),
+            "0x0160 loop: JP main       A:42 F:Z-H- BC:0013 DE:00D8 HL:014D SP:FFFE"
+        );
+    }
+
+    #[test]
+    fn test_step_through_loaded_program() {
+        let mut cpu = CPU::default();
+        cpu.bus.load_at(
+            0x0200,
+            &[
+                0x3E, 0x0F, // LD A,$0F
+                0xC6, 0x01, // ADD A,$01
+                0xEA, 0x00, 0xC0, // LD ($C000),A
+            ],
+        );
+        cpu.pc = 0x0200;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x0F);
+        assert_eq!(cpu.pc, 0x0202);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(cpu.registers.f.half_carry);
+        assert_eq!(cpu.pc, 0x0204);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xC000), 0x10);
+        assert_eq!(cpu.pc, 0x0207);
+    }
+
+    thread_local! {
+        static FAKE_NOW: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+        static SLEEP_CALLS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    }
+
+    // 実時間の代わりに、fake_sleepで待った分だけ進む時計
+    fn fake_clock() -> u64 {
+        FAKE_NOW.with(|now| now.get())
+    }
+
+    fn fake_sleep(ms: u64) {
+        FAKE_NOW.with(|now| now.set(now.get() + ms));
+        SLEEP_CALLS.with(|calls| calls.set(calls.get() + 1));
+    }
+
+    #[test]
+    fn test_run_for_duration() {
+        let mut cpu = CPU::default();
+        // 100ミリ秒分のサイクル数に達した命令で止まる（NOPは4サイクル）
+        cpu.run_for_duration(100).unwrap();
+        let budget = CLOCK_SPEED as u64 / 10;
+        assert!((budget..budget + 4).contains(&cpu.cycles));
+
+        // スロットル中は、時計が指定した時間だけ進むまで待ってから戻る
+        cpu.set_throttle_clock(fake_clock, fake_sleep);
+        let start = cpu.cycles;
+        cpu.run_for_duration(30).unwrap();
+        let budget = 30 * CLOCK_SPEED as u64 / 1000;
+        assert!((budget..budget + 4).contains(&(cpu.cycles - start)));
+        // 実行中は時計が進まないので、残りの30ミリ秒を1回で待つ
+        assert_eq!(fake_clock(), 30);
+        assert_eq!(SLEEP_CALLS.with(|calls| calls.get()), 1);
+    }
 }
@@ -0,0 +1,263 @@
+use std::fmt::Debug;
+
+use crate::instruction::{
+    ArithmeticTarget, HighOffset, Indirect, Instruction, JumpTest, LoadByteSource, LoadType,
+    LoadWordSource, LoadWordTarget,
+};
+use crate::memory::MemoryBus;
+
+impl Instruction {
+    // pcにある命令を「LD A,B」や「JP NZ,$1234」のような文字列にし、命令のバイト数と一緒に返す
+    // 即値はバスから読み込んで埋め込む（ウォッチポイントには記録しない）
+    // 未知のオペコードは「.byte $XX」として1バイト進める
+    pub fn disassemble(bus: &MemoryBus, pc: u16) -> (String, u16) {
+        let byte = bus.peek(pc);
+        let prefixed = byte == 0xCB;
+        let opcode = if prefixed {
+            bus.peek(pc.wrapping_add(1))
+        } else {
+            byte
+        };
+        let Some(instruction) = Instruction::from_byte(opcode, prefixed) else {
+            return (format!(".byte ${:02X}", byte), 1);
+        };
+        if prefixed {
+            return (instruction.prefixed_mnemonic(), 2);
+        }
+
+        let d8 = bus.peek(pc.wrapping_add(1));
+        let d16 = u16::from_le_bytes([d8, bus.peek(pc.wrapping_add(2))]);
+        match instruction {
+            Instruction::NOP => ("NOP".to_string(), 1),
+            Instruction::STOP => ("STOP".to_string(), 2),
+            Instruction::Illegal(byte) => (format!(".byte ${:02X}", byte), 1),
+            Instruction::ADD(target) => arithmetic("ADD A,", target, d8),
+            Instruction::ADC(target) => arithmetic("ADC A,", target, d8),
+            Instruction::SUB(target) => arithmetic("SUB ", target, d8),
+            Instruction::SBC(target) => arithmetic("SBC A,", target, d8),
+            Instruction::AND(target) => arithmetic("AND ", target, d8),
+            Instruction::OR(target) => arithmetic("OR ", target, d8),
+            Instruction::XOR(target) => arithmetic("XOR ", target, d8),
+            Instruction::CP(target) => arithmetic("CP ", target, d8),
+            Instruction::INC(target) => (format!("INC {}", operand(&target)), 1),
+            Instruction::DEC(target) => (format!("DEC {}", operand(&target)), 1),
+            Instruction::ADDHL(register) => (format!("ADD HL,{:?}", register), 1),
+            Instruction::ADDSP => (format!("ADD SP,{}", signed(d8)), 2),
+            Instruction::LDHLSP => {
+                let offset = signed(d8);
+                let sign = if offset.starts_with('-') { "" } else { "+" };
+                (format!("LD HL,SP{}{}", sign, offset), 2)
+            }
+            Instruction::INC16(register) => (format!("INC {:?}", register), 1),
+            Instruction::DEC16(register) => (format!("DEC {:?}", register), 1),
+            Instruction::JP(test) => (branch("JP", &test, Some(format!("${:04X}", d16))), 3),
+            Instruction::JPHL => ("JP HL".to_string(), 1),
+            // 飛び先は次の命令のアドレスからの相対位置
+            Instruction::JR(test) => {
+                let destination = pc.wrapping_add(2).wrapping_add_signed(d8 as i8 as i16);
+                (
+                    branch("JR", &test, Some(format!("${:04X}", destination))),
+                    2,
+                )
+            }
+            Instruction::LD(LoadType::Byte(target, LoadByteSource::D8)) => {
+                (format!("LD {},${:02X}", operand(&target), d8), 2)
+            }
+            Instruction::LD(LoadType::Byte(target, source)) => {
+                (format!("LD {},{}", operand(&target), operand(&source)), 1)
+            }
+            Instruction::LD(LoadType::Word(LoadWordTarget::A16, source)) => {
+                (format!("LD (${:04X}),{:?}", d16, source), 3)
+            }
+            Instruction::LD(LoadType::Word(target, LoadWordSource::D16)) => {
+                (format!("LD {:?},${:04X}", target, d16), 3)
+            }
+            Instruction::LD(LoadType::Word(target, source)) => {
+                (format!("LD {:?},{:?}", target, source), 1)
+            }
+            Instruction::LD(LoadType::IndirectFromA(indirect)) => {
+                (format!("LD {},A", indirect_operand(&indirect)), 1)
+            }
+            Instruction::LD(LoadType::AFromIndirect(indirect)) => {
+                (format!("LD A,{}", indirect_operand(&indirect)), 1)
+            }
+            Instruction::LD(LoadType::AbsoluteFromA) => (format!("LD (${:04X}),A", d16), 3),
+            Instruction::LD(LoadType::AFromAbsolute) => (format!("LD A,(${:04X})", d16), 3),
+            Instruction::LD(LoadType::HighFromA(HighOffset::A8)) => {
+                (format!("LDH (${:02X}),A", d8), 2)
+            }
+            Instruction::LD(LoadType::AFromHigh(HighOffset::A8)) => {
+                (format!("LDH A,(${:02X})", d8), 2)
+            }
+            Instruction::LD(LoadType::HighFromA(HighOffset::C)) => ("LD (C),A".to_string(), 1),
+            Instruction::LD(LoadType::AFromHigh(HighOffset::C)) => ("LD A,(C)".to_string(), 1),
+            Instruction::CALL(test) => (branch("CALL", &test, Some(format!("${:04X}", d16))), 3),
+            Instruction::RET(test) => (branch("RET", &test, None), 1),
+            Instruction::RETI => ("RETI".to_string(), 1),
+            Instruction::PUSH(target) => (format!("PUSH {:?}", target), 1),
+            Instruction::POP(target) => (format!("POP {:?}", target), 1),
+            Instruction::RST(vector) => (format!("RST ${:02X}", vector), 1),
+            other => (format!("{:?}", other), 1),
+        }
+    }
+
+    // 0xCBプレフィックス命令の文字列
+    fn prefixed_mnemonic(&self) -> String {
+        match self {
+            Instruction::RLC(target) => format!("RLC {}", operand(target)),
+            Instruction::RRC(target) => format!("RRC {}", operand(target)),
+            Instruction::RL(target) => format!("RL {}", operand(target)),
+            Instruction::RR(target) => format!("RR {}", operand(target)),
+            Instruction::SLA(target) => format!("SLA {}", operand(target)),
+            Instruction::SRA(target) => format!("SRA {}", operand(target)),
+            Instruction::SWAP(target) => format!("SWAP {}", operand(target)),
+            Instruction::SRL(target) => format!("SRL {}", operand(target)),
+            Instruction::BIT(bit, target) => format!("BIT {},{}", bit, operand(target)),
+            Instruction::RES(bit, target) => format!("RES {},{}", bit, operand(target)),
+            Instruction::SET(bit, target) => format!("SET {},{}", bit, operand(target)),
+            other => format!("{:?}", other),
+        }
+    }
+}
+
+// ALU命令: 対象がd8なら即値を読んで2バイト、それ以外は1バイト
+fn arithmetic(prefix: &str, target: ArithmeticTarget, d8: u8) -> (String, u16) {
+    match target {
+        ArithmeticTarget::D8 => (format!("{}${:02X}", prefix, d8), 2),
+        _ => (format!("{}{}", prefix, operand(&target)), 1),
+    }
+}
+
+// 分岐命令: 条件があれば「JP NZ,$1234」のように飛び先の前に付ける
+fn branch(mnemonic: &str, test: &JumpTest, destination: Option<String>) -> String {
+    let condition = match test {
+        JumpTest::NotZero => Some("NZ"),
+        JumpTest::Zero => Some("Z"),
+        JumpTest::NotCarry => Some("NC"),
+        JumpTest::Carry => Some("C"),
+        JumpTest::Always => None,
+    };
+    let operands: Vec<String> = condition
+        .map(str::to_string)
+        .into_iter()
+        .chain(destination)
+        .collect();
+    if operands.is_empty() {
+        mnemonic.to_string()
+    } else {
+        format!("{} {}", mnemonic, operands.join(","))
+    }
+}
+
+// 符号付き即値: 負の値は「-$02」のように絶対値で表す
+fn signed(d8: u8) -> String {
+    let offset = d8 as i8;
+    if offset < 0 {
+        format!("-${:02X}", offset.unsigned_abs())
+    } else {
+        format!("${:02X}", offset)
+    }
+}
+
+fn indirect_operand(indirect: &Indirect) -> &'static str {
+    match indirect {
+        Indirect::BC => "(BC)",
+        Indirect::DE => "(DE)",
+        Indirect::HLInc => "(HL+)",
+        Indirect::HLDec => "(HL-)",
+    }
+}
+
+// レジスタ名のオペランド
+// 各ターゲットのenumはレジスタ名と同じ名前なので、HLIだけ「(HL)」に置き換える
+fn operand<T: Debug>(target: &T) -> String {
+    match format!("{:?}", target).as_str() {
+        "HLI" => "(HL)".to_string(),
+        name => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble(bytes: &[u8]) -> (String, u16) {
+        let mut bus = MemoryBus::default();
+        bus.load_at(0xC000, bytes);
+        Instruction::disassemble(&bus, 0xC000)
+    }
+
+    #[test]
+    fn test_disassemble_register_forms() {
+        assert_eq!(disassemble(&[0x81]), ("ADD A,C".to_string(), 1));
+        assert_eq!(disassemble(&[0x78]), ("LD A,B".to_string(), 1));
+        assert_eq!(disassemble(&[0x77]), ("LD (HL),A".to_string(), 1));
+        assert_eq!(disassemble(&[0xA8]), ("XOR B".to_string(), 1));
+        assert_eq!(disassemble(&[0x34]), ("INC (HL)".to_string(), 1));
+        assert_eq!(disassemble(&[0x02]), ("LD (BC),A".to_string(), 1));
+        assert_eq!(disassemble(&[0x3A]), ("LD A,(HL-)".to_string(), 1));
+        assert_eq!(disassemble(&[0x29]), ("ADD HL,HL".to_string(), 1));
+        assert_eq!(disassemble(&[0xF5]), ("PUSH AF".to_string(), 1));
+        assert_eq!(disassemble(&[0xF9]), ("LD SP,HL".to_string(), 1));
+        assert_eq!(disassemble(&[0xFF]), ("RST $38".to_string(), 1));
+        assert_eq!(disassemble(&[0x76]), ("HALT".to_string(), 1));
+        assert_eq!(disassemble(&[0x10, 0x00]), ("STOP".to_string(), 2));
+    }
+
+    #[test]
+    fn test_disassemble_immediates() {
+        assert_eq!(disassemble(&[0x0E, 0x42]), ("LD C,$42".to_string(), 2));
+        assert_eq!(disassemble(&[0xC6, 0x05]), ("ADD A,$05".to_string(), 2));
+        assert_eq!(
+            disassemble(&[0x21, 0x34, 0x12]),
+            ("LD HL,$1234".to_string(), 3)
+        );
+        assert_eq!(
+            disassemble(&[0xC2, 0x34, 0x12]),
+            ("JP NZ,$1234".to_string(), 3)
+        );
+        assert_eq!(
+            disassemble(&[0xCD, 0x00, 0x40]),
+            ("CALL $4000".to_string(), 3)
+        );
+        // JRは次の命令のアドレス（0xC002）からの相対位置
+        assert_eq!(disassemble(&[0x38, 0xFE]), ("JR C,$C000".to_string(), 2));
+        assert_eq!(disassemble(&[0xC8]), ("RET Z".to_string(), 1));
+        assert_eq!(disassemble(&[0xC9]), ("RET".to_string(), 1));
+        assert_eq!(disassemble(&[0xD9]), ("RETI".to_string(), 1));
+        assert_eq!(disassemble(&[0xE8, 0x05]), ("ADD SP,$05".to_string(), 2));
+        assert_eq!(disassemble(&[0xF8, 0xFE]), ("LD HL,SP-$02".to_string(), 2));
+    }
+
+    #[test]
+    fn test_disassemble_prefixed() {
+        assert_eq!(disassemble(&[0xCB, 0x37]), ("SWAP A".to_string(), 2));
+        assert_eq!(disassemble(&[0xCB, 0x7E]), ("BIT 7,(HL)".to_string(), 2));
+        assert_eq!(disassemble(&[0xCB, 0x11]), ("RL C".to_string(), 2));
+    }
+
+    // デコードできるすべてのオペコードで、逆アセンブラとInstruction::lengthのバイト数が一致する
+    #[test]
+    fn test_length_matches_disassembler() {
+        for byte in 0..=0xFFu8 {
+            if byte != 0xCB && Instruction::from_byte(byte, false).is_some() {
+                let (_, length) = disassemble(&[byte]);
+                assert_eq!(
+                    Instruction::length(byte, false) as u16,
+                    length,
+                    "0x{:02X}",
+                    byte
+                );
+            }
+        }
+        assert_eq!(
+            disassemble(&[0xCB, 0x00]).1,
+            Instruction::length(0x00, true) as u16
+        );
+    }
+
+    #[test]
+    fn test_disassemble_unknown_opcode() {
+        assert_eq!(disassemble(&[0xD3]), (".byte $D3".to_string(), 1));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::cpu::CPU;
+use crate::instruction::{Instruction, LoadType};
+
+// オペコードを実行し、消費したTサイクル数を返す関数
+pub type OpcodeHandler = fn(&mut CPU) -> u8;
+
+// 上位4ビットがhighの16個のオペコード
+macro_rules! opcode_row {
+    ($prefixed:literal, $high:literal) => {
+        [
+            entry::<{ $high * 16 + 0x0 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x1 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x2 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x3 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x4 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x5 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x6 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x7 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x8 }, $prefixed>(),
+            entry::<{ $high * 16 + 0x9 }, $prefixed>(),
+            entry::<{ $high * 16 + 0xA }, $prefixed>(),
+            entry::<{ $high * 16 + 0xB }, $prefixed>(),
+            entry::<{ $high * 16 + 0xC }, $prefixed>(),
+            entry::<{ $high * 16 + 0xD }, $prefixed>(),
+            entry::<{ $high * 16 + 0xE }, $prefixed>(),
+            entry::<{ $high * 16 + 0xF }, $prefixed>(),
+        ]
+    };
+}
+
+// オペコードのバイトで直接引けるディスパッチテーブル
+// デコードはコンパイル時に済ませておくので、実行時にInstruction::from_byteを通らない
+// 未使用のオペコードはNone
+static TABLE: [Option<OpcodeHandler>; 256] = flatten([
+    opcode_row!(false, 0x0),
+    opcode_row!(false, 0x1),
+    opcode_row!(false, 0x2),
+    opcode_row!(false, 0x3),
+    opcode_row!(false, 0x4),
+    opcode_row!(false, 0x5),
+    opcode_row!(false, 0x6),
+    opcode_row!(false, 0x7),
+    opcode_row!(false, 0x8),
+    opcode_row!(false, 0x9),
+    opcode_row!(false, 0xA),
+    opcode_row!(false, 0xB),
+    opcode_row!(false, 0xC),
+    opcode_row!(false, 0xD),
+    opcode_row!(false, 0xE),
+    opcode_row!(false, 0xF),
+]);
+
+// 0xCBプレフィックス命令のテーブル
+static PREFIXED_TABLE: [Option<OpcodeHandler>; 256] = flatten([
+    opcode_row!(true, 0x0),
+    opcode_row!(true, 0x1),
+    opcode_row!(true, 0x2),
+    opcode_row!(true, 0x3),
+    opcode_row!(true, 0x4),
+    opcode_row!(true, 0x5),
+    opcode_row!(true, 0x6),
+    opcode_row!(true, 0x7),
+    opcode_row!(true, 0x8),
+    opcode_row!(true, 0x9),
+    opcode_row!(true, 0xA),
+    opcode_row!(true, 0xB),
+    opcode_row!(true, 0xC),
+    opcode_row!(true, 0xD),
+    opcode_row!(true, 0xE),
+    opcode_row!(true, 0xF),
+]);
+
+pub fn handler(byte: u8, prefixed: bool) -> Option<OpcodeHandler> {
+    if prefixed {
+        PREFIXED_TABLE[byte as usize]
+    } else {
+        TABLE[byte as usize]
+    }
+}
+
+const fn entry<const OPCODE: u8, const PREFIXED: bool>() -> Option<OpcodeHandler> {
+    match Instruction::from_byte(OPCODE, PREFIXED) {
+        // 未使用のオペコードは実行せず、run_oneでエラーにする
+        None | Some(Instruction::Illegal(_)) => None,
+        Some(_) => Some(execute::<OPCODE, PREFIXED>),
+    }
+}
+
+// ALU命令、INC/DEC r、8ビットのLDは、命令の種類と対象がコンパイル時に決まるので、
+// enumの大きなmatch（CPU::execute）を通らずにその操作を直接呼ぶ
+// それ以外の命令は、デコードだけを済ませてCPU::executeで実行する
+fn execute<const OPCODE: u8, const PREFIXED: bool>(cpu: &mut CPU) -> u8 {
+    let next_pc = match const { Instruction::from_byte(OPCODE, PREFIXED) } {
+        Some(Instruction::ADD(target)) => cpu.arithmetic(target, CPU::add),
+        Some(Instruction::ADC(target)) => cpu.arithmetic(target, CPU::adc),
+        Some(Instruction::SUB(target)) => cpu.arithmetic(target, CPU::sub),
+        Some(Instruction::SBC(target)) => cpu.arithmetic(target, CPU::sbc),
+        Some(Instruction::AND(target)) => cpu.arithmetic(target, CPU::and),
+        Some(Instruction::OR(target)) => cpu.arithmetic(target, CPU::or),
+        Some(Instruction::XOR(target)) => cpu.arithmetic(target, CPU::xor),
+        Some(Instruction::CP(target)) => cpu.arithmetic(target, CPU::compare),
+        Some(Instruction::INC(target)) => cpu.inc_dec(target, CPU::inc),
+        Some(Instruction::DEC(target)) => cpu.inc_dec(target, CPU::dec),
+        Some(Instruction::LD(LoadType::Byte(target, source))) => cpu.load_byte(target, source),
+        Some(instruction) => return cpu.execute_opcode(instruction, OPCODE, PREFIXED),
+        // entryでデコードできるオペコードだけをテーブルに入れている
+        None => unreachable!(),
+    };
+    // これらの命令は分岐しない
+    cpu.pc = next_pc;
+    Instruction::cycles(OPCODE, PREFIXED, false)
+}
+
+const fn flatten(rows: [[Option<OpcodeHandler>; 16]; 16]) -> [Option<OpcodeHandler>; 256] {
+    let mut table = [None; 256];
+    let mut index = 0;
+    while index < 256 {
+        table[index] = rows[index / 16][index % 16];
+        index += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // テーブルには、デコードできて未使用でないオペコードだけが入っている
+    #[test]
+    fn test_table_matches_decoder() {
+        for byte in 0..=0xFF {
+            assert_eq!(
+                handler(byte, false).is_some(),
+                !matches!(
+                    Instruction::from_byte(byte, false),
+                    None | Some(Instruction::Illegal(_))
+                )
+            );
+            assert!(handler(byte, true).is_some());
+        }
+    }
+
+    // テーブル経由でもenum経由でも、ADD A,Cの結果は同じ
+    #[test]
+    fn test_table_dispatches_like_execute() {
+        let mut by_table = CPU::default();
+        by_table.registers.a = 0xF0;
+        by_table.registers.c = 0x21;
+        let mut by_enum = CPU::default();
+        by_enum.registers.a = 0xF0;
+        by_enum.registers.c = 0x21;
+
+        let cycles = handler(0x81, false).unwrap()(&mut by_table);
+        by_enum.pc = by_enum.execute(Instruction::from_byte(0x81, false).unwrap());
+
+        assert_eq!(cycles, 4);
+        assert_eq!(by_table.registers.a, 0x11);
+        assert_eq!(by_table.registers.a, by_enum.registers.a);
+        assert_eq!(by_table.registers.f, by_enum.registers.f);
+        assert_eq!(by_table.pc, by_enum.pc);
+    }
+
+    // 直接操作を呼ぶLD r,r'とALU命令（0x40〜0xBF、HALTを除く）も、enum経由と同じ結果になる
+    #[test]
+    fn test_direct_handlers_match_execute() {
+        for byte in (0x40..=0xBF).filter(|&byte| byte != 0x76) {
+            let setup = |cpu: &mut CPU| {
+                cpu.pc = 0x0200;
+                cpu.registers.a = 0x3C;
+                cpu.registers.b = 0x12;
+                cpu.registers.c = 0xF1;
+                cpu.registers.d = 0x0F;
+                cpu.registers.e = 0x80;
+                cpu.registers.set_hl(0xC010);
+                cpu.registers.f.carry = true;
+                cpu.bus.write_byte(0xC010, 0x9A);
+            };
+            let mut by_table = CPU::default();
+            setup(&mut by_table);
+            let mut by_enum = CPU::default();
+            setup(&mut by_enum);
+
+            let cycles = handler(byte, false).unwrap()(&mut by_table);
+            by_enum.pc = by_enum.execute(Instruction::from_byte(byte, false).unwrap());
+
+            assert_eq!(cycles, Instruction::cycles(byte, false, false));
+            // レジスタ、フラグ、pc、spをまとめて比べる
+            assert_eq!(
+                by_table.debug_state(),
+                by_enum.debug_state(),
+                "0x{:02X}",
+                byte
+            );
+            assert_eq!(
+                by_table.bus.read_byte(0xC010),
+                by_enum.bus.read_byte(0xC010),
+                "0x{:02X}",
+                byte
+            );
+        }
+    }
+
+    // NOPだけのループを何度も回す（テーブル経由の実行経路を多く通す）
+    #[test]
+    fn test_nop_loop() {
+        let mut cpu = CPU::default();
+        // 0xC000: NOP, NOP, NOP, JR -5
+        cpu.pc = 0xC000;
+        cpu.bus.write_byte(0xC003, 0x18);
+        cpu.bus.write_byte(0xC004, 0xFB);
+        for _ in 0..100_000 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0xC000);
+        assert_eq!(cpu.cycles, 25_000 * (4 * 3 + 12));
+    }
+}
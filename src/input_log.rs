@@ -0,0 +1,96 @@
+// 入力の記録と再生
+// フレームごとのボタンの状態（並びはJoypad::set_stateと同じ）を、変化したときだけ記録する
+// フレーム番号は記録を始めたフレームを0とする
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputLog {
+    // (フレーム番号, ボタンの状態)をフレーム番号の順に並べる
+    pub entries: Vec<(u64, u8)>,
+}
+
+// フレームの始めのボタンの状態を記録していく
+#[derive(Default)]
+pub struct InputRecorder {
+    log: InputLog,
+    frame: u64,
+    last: Option<u8>,
+}
+
+impl InputRecorder {
+    // 1フレームを実行する直前に呼ぶ
+    pub fn record(&mut self, buttons: u8) {
+        if self.last != Some(buttons) {
+            self.log.entries.push((self.frame, buttons));
+            self.last = Some(buttons);
+        }
+        self.frame += 1;
+    }
+
+    pub fn finish(self) -> InputLog {
+        self.log
+    }
+}
+
+// 記録したボタンの状態を、同じフレームで取り出す
+pub struct InputReplayer {
+    log: InputLog,
+    frame: u64,
+    position: usize,
+}
+
+impl InputReplayer {
+    pub fn new(log: &InputLog) -> InputReplayer {
+        InputReplayer {
+            log: log.clone(),
+            frame: 0,
+            position: 0,
+        }
+    }
+
+    // 1フレームを実行する直前に呼び、このフレームで設定するボタンの状態を返す
+    pub fn next_frame(&mut self) -> Option<u8> {
+        let mut buttons = None;
+        while let Some(&(frame, state)) = self.log.entries.get(self.position) {
+            if frame > self.frame {
+                break;
+            }
+            buttons = Some(state);
+            self.position += 1;
+        }
+        self.frame += 1;
+        buttons
+    }
+
+    // すべての記録を再生し終えたか
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.log.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_only_changes() {
+        let mut recorder = InputRecorder::default();
+        for buttons in [0x00, 0x00, 0x10, 0x10, 0x00] {
+            recorder.record(buttons);
+        }
+        assert_eq!(
+            recorder.finish().entries,
+            vec![(0, 0x00), (2, 0x10), (4, 0x00)]
+        );
+    }
+
+    #[test]
+    fn test_replays_on_recorded_frames() {
+        let log = InputLog {
+            entries: vec![(0, 0x00), (2, 0x10), (4, 0x00)],
+        };
+        let mut replayer = InputReplayer::new(&log);
+        let frames: Vec<Option<u8>> = (0..5).map(|_| replayer.next_frame()).collect();
+        assert_eq!(frames, vec![Some(0x00), None, Some(0x10), None, Some(0x00)]);
+        assert!(replayer.is_finished());
+    }
+}
@@ -1,14 +1,70 @@
 // すべての命令が定義される中心的な場所
+#[derive(Debug, PartialEq)]
 pub enum Instruction {
+    NOP,
     ADD(ArithmeticTarget),
+    ADC(ArithmeticTarget),
+    SUB(ArithmeticTarget),
+    SBC(ArithmeticTarget),
+    AND(ArithmeticTarget),
+    OR(ArithmeticTarget),
+    XOR(ArithmeticTarget),
+    CP(ArithmeticTarget),
+    INC(IncDecTarget),
+    DEC(IncDecTarget),
+    ADDHL(WordRegister),
+    // ADD SP,e8: 符号付き8ビット即値をSPに足す
+    ADDSP,
+    // LD HL,SP+e8: SPに符号付き8ビット即値を足した値をHLに入れる
+    LDHLSP,
+    INC16(WordRegister),
+    DEC16(WordRegister),
     JP(JumpTest),
+    // JP (HL): メモリではなくHLレジスタの値そのものに飛ぶ
+    JPHL,
+    JR(JumpTest),
     LD(LoadType),
     CALL(JumpTest),
     RET(JumpTest),
+    // RETと同じく戻り先をポップし、IMEを即座に有効にする
+    RETI,
+    PUSH(StackTarget),
+    POP(StackTarget),
+    // 飛び先のリスタートベクタ（0x00, 0x08, ..., 0x38）を持つ
+    RST(u8),
+    RLCA,
+    RRCA,
+    RLA,
+    RRA,
+    DAA,
+    CPL,
+    SCF,
+    CCF,
+    DI,
+    EI,
+    HALT,
+    // 0x10 0x00の2バイト命令
+    STOP,
+    // 実機では使われていないオペコード（実行するとCPUが止まる）
+    Illegal(u8),
+
+    // 0xCBプレフィックス命令
+    RLC(PrefixTarget),
+    RRC(PrefixTarget),
+    RL(PrefixTarget),
+    RR(PrefixTarget),
+    SLA(PrefixTarget),
+    SRA(PrefixTarget),
+    SWAP(PrefixTarget),
+    SRL(PrefixTarget),
+    // ビット番号（0〜7）と対象を持つ
+    BIT(u8, PrefixTarget),
+    RES(u8, PrefixTarget),
+    SET(u8, PrefixTarget),
 }
 
 impl Instruction {
-    pub fn from_byte(byte: u8, prefixed: bool) -> Option<Instruction> {
+    pub const fn from_byte(byte: u8, prefixed: bool) -> Option<Instruction> {
         if prefixed {
             Instruction::from_byte_prefixed(byte)
         } else {
@@ -16,19 +72,369 @@ impl Instruction {
         }
     }
 
-    fn from_byte_prefixed(byte: u8) -> Option<Instruction> {
+    // オペコードの実行にかかるTサイクル数
+    // branch_takenは条件分岐（JP/JR/CALL/RET）が成立したかどうか
+    pub fn cycles(byte: u8, prefixed: bool, branch_taken: bool) -> u8 {
+        if prefixed {
+            return CB_CYCLES[byte as usize];
+        }
+        let extra = if branch_taken {
+            taken_branch_cycles(byte)
+        } else {
+            0
+        };
+        CYCLES[byte as usize] + extra
+    }
+
+    // オペコードが何バイトの命令か（オペランドを含む）
+    // 0xCBプレフィックス命令はプレフィックスを含めて常に2バイト
+    pub fn length(byte: u8, prefixed: bool) -> u8 {
+        if prefixed {
+            return 2;
+        }
         match byte {
-            _ => None,
+            // d16 / a16を読む命令
+            0x01 | 0x11 | 0x21 | 0x31 | 0x08 => 3,
+            0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA => 3,
+            0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => 3,
+            0xEA | 0xFA => 3,
+            // d8 / a8 / e8を読む命令
+            0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => 2,
+            0x18 | 0x20 | 0x28 | 0x30 | 0x38 => 2,
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => 2,
+            0xE0 | 0xF0 | 0xE8 | 0xF8 => 2,
+            // STOPは0x10 0x00、プレフィックスはCBと続くオペコード
+            0x10 | 0xCB => 2,
+            _ => 1,
         }
     }
 
-    fn from_byte_not_prefixed(byte: u8) -> Option<Instruction> {
+    const fn from_byte_prefixed(byte: u8) -> Option<Instruction> {
+        // 下位3ビットで対象（B,C,D,E,H,L,(HL),Aの順）を指定する
+        let target = prefix_target(byte);
         match byte {
+            0x00..=0x07 => Some(Instruction::RLC(target)),
+            0x08..=0x0F => Some(Instruction::RRC(target)),
+            0x10..=0x17 => Some(Instruction::RL(target)),
+            0x18..=0x1F => Some(Instruction::RR(target)),
+            0x20..=0x27 => Some(Instruction::SLA(target)),
+            0x28..=0x2F => Some(Instruction::SRA(target)),
+            0x30..=0x37 => Some(Instruction::SWAP(target)),
+            0x38..=0x3F => Some(Instruction::SRL(target)),
+            // ビット5-3でビット番号を指定する
+            0x40..=0x7F => Some(Instruction::BIT((byte >> 3) & 0x07, target)),
+            0x80..=0xBF => Some(Instruction::RES((byte >> 3) & 0x07, target)),
+            0xC0..=0xFF => Some(Instruction::SET((byte >> 3) & 0x07, target)),
+        }
+    }
+
+    const fn from_byte_not_prefixed(byte: u8) -> Option<Instruction> {
+        match byte {
+            0x00 => Some(Instruction::NOP),
+
+            // LD rr,d16
+            0x01 => Some(Instruction::LD(LoadType::Word(
+                LoadWordTarget::BC,
+                LoadWordSource::D16,
+            ))),
+            0x11 => Some(Instruction::LD(LoadType::Word(
+                LoadWordTarget::DE,
+                LoadWordSource::D16,
+            ))),
+            0x21 => Some(Instruction::LD(LoadType::Word(
+                LoadWordTarget::HL,
+                LoadWordSource::D16,
+            ))),
+            0x31 => Some(Instruction::LD(LoadType::Word(
+                LoadWordTarget::SP,
+                LoadWordSource::D16,
+            ))),
+
+            // LD (rr),A / LD A,(rr)
+            0x02 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::BC))),
+            0x12 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::DE))),
+            0x22 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::HLInc))),
+            0x32 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::HLDec))),
+            0x0A => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::BC))),
+            0x1A => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::DE))),
+            0x2A => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::HLInc))),
+            0x3A => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::HLDec))),
+
+            // LD (a16),A / LD A,(a16)
+            0xEA => Some(Instruction::LD(LoadType::AbsoluteFromA)),
+            0xFA => Some(Instruction::LD(LoadType::AFromAbsolute)),
+
+            // LDH (a8),A / LDH A,(a8) / LD (C),A / LD A,(C)
+            0xE0 => Some(Instruction::LD(LoadType::HighFromA(HighOffset::A8))),
+            0xF0 => Some(Instruction::LD(LoadType::AFromHigh(HighOffset::A8))),
+            0xE2 => Some(Instruction::LD(LoadType::HighFromA(HighOffset::C))),
+            0xF2 => Some(Instruction::LD(LoadType::AFromHigh(HighOffset::C))),
+
+            // LD (a16),SP
+            0x08 => Some(Instruction::LD(LoadType::Word(
+                LoadWordTarget::A16,
+                LoadWordSource::SP,
+            ))),
+            // LD SP,HL
+            0xF9 => Some(Instruction::LD(LoadType::Word(
+                LoadWordTarget::SP,
+                LoadWordSource::HL,
+            ))),
+
+            // Aレジスタのローテート
+            0x07 => Some(Instruction::RLCA),
+            0x0F => Some(Instruction::RRCA),
+            0x17 => Some(Instruction::RLA),
+            0x1F => Some(Instruction::RRA),
+
+            0x27 => Some(Instruction::DAA),
+            0x2F => Some(Instruction::CPL),
+            0x37 => Some(Instruction::SCF),
+            0x3F => Some(Instruction::CCF),
+
+            // INC rr / DEC rr / ADD HL,rr
+            0x03 => Some(Instruction::INC16(WordRegister::BC)),
+            0x13 => Some(Instruction::INC16(WordRegister::DE)),
+            0x23 => Some(Instruction::INC16(WordRegister::HL)),
+            0x33 => Some(Instruction::INC16(WordRegister::SP)),
+            0x0B => Some(Instruction::DEC16(WordRegister::BC)),
+            0x1B => Some(Instruction::DEC16(WordRegister::DE)),
+            0x2B => Some(Instruction::DEC16(WordRegister::HL)),
+            0x3B => Some(Instruction::DEC16(WordRegister::SP)),
+            0x09 => Some(Instruction::ADDHL(WordRegister::BC)),
+            0x19 => Some(Instruction::ADDHL(WordRegister::DE)),
+            0x29 => Some(Instruction::ADDHL(WordRegister::HL)),
+            0x39 => Some(Instruction::ADDHL(WordRegister::SP)),
+
+            // INC r / DEC r / LD r,d8
+            // ビット5-3でレジスタを指定する（B,C,D,E,H,L,(HL),Aの順）
+            0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => {
+                Some(Instruction::INC(inc_dec_target(byte >> 3)))
+            }
+            0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => {
+                Some(Instruction::DEC(inc_dec_target(byte >> 3)))
+            }
+            0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => Some(Instruction::LD(
+                LoadType::Byte(load_byte_target(byte >> 3), LoadByteSource::D8),
+            )),
+
+            // LD r,r
+            // 0x76はLD (HL),(HL)ではなくHALT
+            0x76 => Some(Instruction::HALT),
+            0x40..=0x7F => Some(Instruction::LD(LoadType::Byte(
+                load_byte_target(byte >> 3),
+                load_byte_source(byte),
+            ))),
+
+            // ALU A,d8
+            0xC6 => Some(Instruction::ADD(ArithmeticTarget::D8)),
+            0xCE => Some(Instruction::ADC(ArithmeticTarget::D8)),
+            0xD6 => Some(Instruction::SUB(ArithmeticTarget::D8)),
+            0xDE => Some(Instruction::SBC(ArithmeticTarget::D8)),
+            0xE6 => Some(Instruction::AND(ArithmeticTarget::D8)),
+            0xEE => Some(Instruction::XOR(ArithmeticTarget::D8)),
+            0xF6 => Some(Instruction::OR(ArithmeticTarget::D8)),
+            0xFE => Some(Instruction::CP(ArithmeticTarget::D8)),
+
+            // ALU A,r / ALU A,(HL)
+            // ビット5-3で演算の種類、ビット2-0でレジスタを指定する
+            0x80..=0xBF => {
+                let target = arithmetic_target(byte);
+                match (byte >> 3) & 0x07 {
+                    0 => Some(Instruction::ADD(target)),
+                    1 => Some(Instruction::ADC(target)),
+                    2 => Some(Instruction::SUB(target)),
+                    3 => Some(Instruction::SBC(target)),
+                    4 => Some(Instruction::AND(target)),
+                    5 => Some(Instruction::XOR(target)),
+                    6 => Some(Instruction::OR(target)),
+                    _ => Some(Instruction::CP(target)),
+                }
+            }
+
+            // JR
+            0x18 => Some(Instruction::JR(JumpTest::Always)),
+            0x20 => Some(Instruction::JR(JumpTest::NotZero)),
+            0x28 => Some(Instruction::JR(JumpTest::Zero)),
+            0x30 => Some(Instruction::JR(JumpTest::NotCarry)),
+            0x38 => Some(Instruction::JR(JumpTest::Carry)),
+
+            // JP / CALL / RET
+            0xC2 => Some(Instruction::JP(JumpTest::NotZero)),
+            0xCA => Some(Instruction::JP(JumpTest::Zero)),
+            0xD2 => Some(Instruction::JP(JumpTest::NotCarry)),
+            0xDA => Some(Instruction::JP(JumpTest::Carry)),
+            0xC3 => Some(Instruction::JP(JumpTest::Always)),
+            0xE9 => Some(Instruction::JPHL),
+            0xE8 => Some(Instruction::ADDSP),
+            0xF8 => Some(Instruction::LDHLSP),
+            0xC4 => Some(Instruction::CALL(JumpTest::NotZero)),
+            0xCC => Some(Instruction::CALL(JumpTest::Zero)),
+            0xD4 => Some(Instruction::CALL(JumpTest::NotCarry)),
+            0xDC => Some(Instruction::CALL(JumpTest::Carry)),
+            0xCD => Some(Instruction::CALL(JumpTest::Always)),
+            0xC0 => Some(Instruction::RET(JumpTest::NotZero)),
+            0xC8 => Some(Instruction::RET(JumpTest::Zero)),
+            0xD0 => Some(Instruction::RET(JumpTest::NotCarry)),
+            0xD8 => Some(Instruction::RET(JumpTest::Carry)),
+            0xC9 => Some(Instruction::RET(JumpTest::Always)),
+            0xD9 => Some(Instruction::RETI),
+
+            0xF3 => Some(Instruction::DI),
+            0xFB => Some(Instruction::EI),
+            0x10 => Some(Instruction::STOP),
+
+            // PUSH / POP
+            0xC5 => Some(Instruction::PUSH(StackTarget::BC)),
+            0xD5 => Some(Instruction::PUSH(StackTarget::DE)),
+            0xE5 => Some(Instruction::PUSH(StackTarget::HL)),
+            0xF5 => Some(Instruction::PUSH(StackTarget::AF)),
+            0xC1 => Some(Instruction::POP(StackTarget::BC)),
+            0xD1 => Some(Instruction::POP(StackTarget::DE)),
+            0xE1 => Some(Instruction::POP(StackTarget::HL)),
+            0xF1 => Some(Instruction::POP(StackTarget::AF)),
+
+            // RST
+            // ビット5-3がそのままベクタのアドレスになる
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                Some(Instruction::RST(byte & 0x38))
+            }
+
+            0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+                Some(Instruction::Illegal(byte))
+            }
+
             _ => None,
         }
     }
 }
 
+// オペコードごとの基本のTサイクル数（条件分岐は不成立の場合）
+// 行が上位4ビット、列が下位4ビットに対応する。未使用のオペコードは0
+#[rustfmt::skip]
+pub const CYCLES: [u8; 256] = [
+    4, 12, 8, 8, 4, 4, 8, 4, 20, 8, 8, 8, 4, 4, 8, 4, // 0x0_
+    4, 12, 8, 8, 4, 4, 8, 4, 12, 8, 8, 8, 4, 4, 8, 4, // 0x1_
+    8, 12, 8, 8, 4, 4, 8, 4, 8, 8, 8, 8, 4, 4, 8, 4, // 0x2_
+    8, 12, 8, 8, 12, 12, 12, 4, 8, 8, 8, 8, 4, 4, 8, 4, // 0x3_
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 0x4_
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 0x5_
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 0x6_
+    8, 8, 8, 8, 8, 8, 4, 8, 4, 4, 4, 4, 4, 4, 8, 4, // 0x7_
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 0x8_
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 0x9_
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 0xA_
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, // 0xB_
+    8, 12, 12, 16, 12, 16, 8, 16, 8, 16, 12, 4, 12, 24, 8, 16, // 0xC_
+    8, 12, 12, 0, 12, 16, 8, 16, 8, 16, 12, 0, 12, 0, 8, 16, // 0xD_
+    12, 12, 8, 0, 0, 16, 8, 16, 16, 4, 16, 0, 0, 0, 8, 16, // 0xE_
+    12, 12, 8, 4, 0, 16, 8, 16, 12, 8, 16, 4, 0, 0, 8, 16, // 0xF_
+];
+
+// 0xCBプレフィックス命令のTサイクル数（プレフィックスのフェッチを含む）
+// (HL)を対象とする命令は読み書きの分だけ長く、BIT n,(HL)は書き戻しがないので短い
+#[rustfmt::skip]
+pub const CB_CYCLES: [u8; 256] = [
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0x0_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0x1_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0x2_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0x3_
+    8, 8, 8, 8, 8, 8, 12, 8, 8, 8, 8, 8, 8, 8, 12, 8, // 0x4_
+    8, 8, 8, 8, 8, 8, 12, 8, 8, 8, 8, 8, 8, 8, 12, 8, // 0x5_
+    8, 8, 8, 8, 8, 8, 12, 8, 8, 8, 8, 8, 8, 8, 12, 8, // 0x6_
+    8, 8, 8, 8, 8, 8, 12, 8, 8, 8, 8, 8, 8, 8, 12, 8, // 0x7_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0x8_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0x9_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0xA_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0xB_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0xC_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0xD_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0xE_
+    8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8, // 0xF_
+];
+
+// 条件分岐が成立した場合に、CYCLESに加えるTサイクル数
+pub const fn taken_branch_cycles(byte: u8) -> u8 {
+    match byte {
+        // JR cc
+        0x20 | 0x28 | 0x30 | 0x38 => 4,
+        // JP cc
+        0xC2 | 0xCA | 0xD2 | 0xDA => 4,
+        // CALL cc
+        0xC4 | 0xCC | 0xD4 | 0xDC => 12,
+        // RET cc
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => 12,
+        _ => 0,
+    }
+}
+
+// オペコードの下位3ビットで指定されるレジスタ（B,C,D,E,H,L,(HL),Aの順）
+const fn arithmetic_target(index: u8) -> ArithmeticTarget {
+    match index & 0x07 {
+        0 => ArithmeticTarget::B,
+        1 => ArithmeticTarget::C,
+        2 => ArithmeticTarget::D,
+        3 => ArithmeticTarget::E,
+        4 => ArithmeticTarget::H,
+        5 => ArithmeticTarget::L,
+        6 => ArithmeticTarget::HLI,
+        _ => ArithmeticTarget::A,
+    }
+}
+
+const fn prefix_target(index: u8) -> PrefixTarget {
+    match index & 0x07 {
+        0 => PrefixTarget::B,
+        1 => PrefixTarget::C,
+        2 => PrefixTarget::D,
+        3 => PrefixTarget::E,
+        4 => PrefixTarget::H,
+        5 => PrefixTarget::L,
+        6 => PrefixTarget::HLI,
+        _ => PrefixTarget::A,
+    }
+}
+
+const fn inc_dec_target(index: u8) -> IncDecTarget {
+    match index & 0x07 {
+        0 => IncDecTarget::B,
+        1 => IncDecTarget::C,
+        2 => IncDecTarget::D,
+        3 => IncDecTarget::E,
+        4 => IncDecTarget::H,
+        5 => IncDecTarget::L,
+        6 => IncDecTarget::HLI,
+        _ => IncDecTarget::A,
+    }
+}
+
+const fn load_byte_target(index: u8) -> LoadByteTarget {
+    match index & 0x07 {
+        0 => LoadByteTarget::B,
+        1 => LoadByteTarget::C,
+        2 => LoadByteTarget::D,
+        3 => LoadByteTarget::E,
+        4 => LoadByteTarget::H,
+        5 => LoadByteTarget::L,
+        6 => LoadByteTarget::HLI,
+        _ => LoadByteTarget::A,
+    }
+}
+
+const fn load_byte_source(index: u8) -> LoadByteSource {
+    match index & 0x07 {
+        0 => LoadByteSource::B,
+        1 => LoadByteSource::C,
+        2 => LoadByteSource::D,
+        3 => LoadByteSource::E,
+        4 => LoadByteSource::H,
+        5 => LoadByteSource::L,
+        6 => LoadByteSource::HLI,
+        _ => LoadByteSource::A,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ArithmeticTarget {
     A,
     B,
@@ -37,8 +443,51 @@ pub enum ArithmeticTarget {
     E,
     H,
     L,
+    HLI,
+    D8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IncDecTarget {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    HLI,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WordRegister {
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StackTarget {
+    BC,
+    DE,
+    HL,
+    AF,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrefixTarget {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    HLI,
 }
 
+#[derive(Debug, PartialEq)]
 pub enum JumpTest {
     NotZero,
     Zero,
@@ -47,6 +496,7 @@ pub enum JumpTest {
     Always,
 }
 
+#[derive(Debug, PartialEq)]
 pub enum LoadByteTarget {
     A,
     B,
@@ -58,6 +508,7 @@ pub enum LoadByteTarget {
     HLI,
 }
 
+#[derive(Debug, PartialEq)]
 pub enum LoadByteSource {
     A,
     B,
@@ -70,6 +521,410 @@ pub enum LoadByteSource {
     HLI,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum LoadWordTarget {
+    BC,
+    DE,
+    HL,
+    SP,
+    // 16ビットの即値が指すアドレス（下位バイトから順に書き込む）
+    A16,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoadWordSource {
+    D16,
+    SP,
+    HL,
+}
+
+// 0xFF00に足すオフセット（8ビットの即値か、Cレジスタ）
+#[derive(Debug, PartialEq)]
+pub enum HighOffset {
+    A8,
+    C,
+}
+
+// (BC)、(DE)と、アクセスしたあとにHLを増減する(HL+)、(HL-)
+#[derive(Debug, PartialEq)]
+pub enum Indirect {
+    BC,
+    DE,
+    HLInc,
+    HLDec,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum LoadType {
     Byte(LoadByteTarget, LoadByteSource),
+    Word(LoadWordTarget, LoadWordSource),
+    // LD (rr),A
+    IndirectFromA(Indirect),
+    // LD A,(rr)
+    AFromIndirect(Indirect),
+    // LD (a16),A: 16ビットの即値が指すアドレスにAを書き込む
+    AbsoluteFromA,
+    // LD A,(a16)
+    AFromAbsolute,
+    // LDH (a8),A / LD (C),A: 0xFF00 + オフセットのアドレスにAを書き込む
+    HighFromA(HighOffset),
+    // LDH A,(a8) / LD A,(C)
+    AFromHigh(HighOffset),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(byte: u8) -> Option<Instruction> {
+        Instruction::from_byte(byte, false)
+    }
+
+    fn ld_byte(target: LoadByteTarget, source: LoadByteSource) -> Instruction {
+        Instruction::LD(LoadType::Byte(target, source))
+    }
+
+    fn ld_word(target: LoadWordTarget) -> Instruction {
+        Instruction::LD(LoadType::Word(target, LoadWordSource::D16))
+    }
+
+    #[test]
+    fn test_decode_table() {
+        let table = [
+            (0x00, Instruction::NOP),
+            (0x01, ld_word(LoadWordTarget::BC)),
+            (0x11, ld_word(LoadWordTarget::DE)),
+            (0x21, ld_word(LoadWordTarget::HL)),
+            (0x31, ld_word(LoadWordTarget::SP)),
+            (0x07, Instruction::RLCA),
+            (0x0F, Instruction::RRCA),
+            (0x17, Instruction::RLA),
+            (0x1F, Instruction::RRA),
+            (0x27, Instruction::DAA),
+            (0x2F, Instruction::CPL),
+            (0x37, Instruction::SCF),
+            (0x3F, Instruction::CCF),
+            (0x03, Instruction::INC16(WordRegister::BC)),
+            (0x33, Instruction::INC16(WordRegister::SP)),
+            (0x0B, Instruction::DEC16(WordRegister::BC)),
+            (0x3B, Instruction::DEC16(WordRegister::SP)),
+            (0x09, Instruction::ADDHL(WordRegister::BC)),
+            (0x19, Instruction::ADDHL(WordRegister::DE)),
+            (0x29, Instruction::ADDHL(WordRegister::HL)),
+            (0x39, Instruction::ADDHL(WordRegister::SP)),
+            (0x04, Instruction::INC(IncDecTarget::B)),
+            (0x0C, Instruction::INC(IncDecTarget::C)),
+            (0x34, Instruction::INC(IncDecTarget::HLI)),
+            (0x3C, Instruction::INC(IncDecTarget::A)),
+            (0x05, Instruction::DEC(IncDecTarget::B)),
+            (0x2D, Instruction::DEC(IncDecTarget::L)),
+            (0x3D, Instruction::DEC(IncDecTarget::A)),
+            (0x06, ld_byte(LoadByteTarget::B, LoadByteSource::D8)),
+            (0x0E, ld_byte(LoadByteTarget::C, LoadByteSource::D8)),
+            (0x16, ld_byte(LoadByteTarget::D, LoadByteSource::D8)),
+            (0x1E, ld_byte(LoadByteTarget::E, LoadByteSource::D8)),
+            (0x26, ld_byte(LoadByteTarget::H, LoadByteSource::D8)),
+            (0x2E, ld_byte(LoadByteTarget::L, LoadByteSource::D8)),
+            (0x36, ld_byte(LoadByteTarget::HLI, LoadByteSource::D8)),
+            (0x3E, ld_byte(LoadByteTarget::A, LoadByteSource::D8)),
+            (0x40, ld_byte(LoadByteTarget::B, LoadByteSource::B)),
+            (0x46, ld_byte(LoadByteTarget::B, LoadByteSource::HLI)),
+            (0x4F, ld_byte(LoadByteTarget::C, LoadByteSource::A)),
+            (0x70, ld_byte(LoadByteTarget::HLI, LoadByteSource::B)),
+            (0x77, ld_byte(LoadByteTarget::HLI, LoadByteSource::A)),
+            (0x78, ld_byte(LoadByteTarget::A, LoadByteSource::B)),
+            (0x7E, ld_byte(LoadByteTarget::A, LoadByteSource::HLI)),
+            (0x7F, ld_byte(LoadByteTarget::A, LoadByteSource::A)),
+            (0x80, Instruction::ADD(ArithmeticTarget::B)),
+            (0x81, Instruction::ADD(ArithmeticTarget::C)),
+            (0x82, Instruction::ADD(ArithmeticTarget::D)),
+            (0x83, Instruction::ADD(ArithmeticTarget::E)),
+            (0x84, Instruction::ADD(ArithmeticTarget::H)),
+            (0x85, Instruction::ADD(ArithmeticTarget::L)),
+            (0x87, Instruction::ADD(ArithmeticTarget::A)),
+            (0x88, Instruction::ADC(ArithmeticTarget::B)),
+            (0x97, Instruction::SUB(ArithmeticTarget::A)),
+            (0x9A, Instruction::SBC(ArithmeticTarget::D)),
+            (0xA3, Instruction::AND(ArithmeticTarget::E)),
+            (0xAF, Instruction::XOR(ArithmeticTarget::A)),
+            (0xB4, Instruction::OR(ArithmeticTarget::H)),
+            (0xB9, Instruction::CP(ArithmeticTarget::C)),
+            (0x18, Instruction::JR(JumpTest::Always)),
+            (0x20, Instruction::JR(JumpTest::NotZero)),
+            (0x28, Instruction::JR(JumpTest::Zero)),
+            (0x30, Instruction::JR(JumpTest::NotCarry)),
+            (0x38, Instruction::JR(JumpTest::Carry)),
+            (0xC2, Instruction::JP(JumpTest::NotZero)),
+            (0xC3, Instruction::JP(JumpTest::Always)),
+            (0xCA, Instruction::JP(JumpTest::Zero)),
+            (0xD2, Instruction::JP(JumpTest::NotCarry)),
+            (0xDA, Instruction::JP(JumpTest::Carry)),
+            (0xE9, Instruction::JPHL),
+            (0xE8, Instruction::ADDSP),
+            (0xF8, Instruction::LDHLSP),
+            (0xC4, Instruction::CALL(JumpTest::NotZero)),
+            (0xCC, Instruction::CALL(JumpTest::Zero)),
+            (0xD4, Instruction::CALL(JumpTest::NotCarry)),
+            (0xDC, Instruction::CALL(JumpTest::Carry)),
+            (0xCD, Instruction::CALL(JumpTest::Always)),
+            (0xC0, Instruction::RET(JumpTest::NotZero)),
+            (0xC8, Instruction::RET(JumpTest::Zero)),
+            (0xD0, Instruction::RET(JumpTest::NotCarry)),
+            (0xD8, Instruction::RET(JumpTest::Carry)),
+            (0xC9, Instruction::RET(JumpTest::Always)),
+            (0xD9, Instruction::RETI),
+            (0xC5, Instruction::PUSH(StackTarget::BC)),
+            (0xD5, Instruction::PUSH(StackTarget::DE)),
+            (0xE5, Instruction::PUSH(StackTarget::HL)),
+            (0xF5, Instruction::PUSH(StackTarget::AF)),
+            (0xC1, Instruction::POP(StackTarget::BC)),
+            (0xD1, Instruction::POP(StackTarget::DE)),
+            (0xE1, Instruction::POP(StackTarget::HL)),
+            (0xF1, Instruction::POP(StackTarget::AF)),
+            (0xF3, Instruction::DI),
+            (0xFB, Instruction::EI),
+            (0x10, Instruction::STOP),
+            (0x02, Instruction::LD(LoadType::IndirectFromA(Indirect::BC))),
+            (0x12, Instruction::LD(LoadType::IndirectFromA(Indirect::DE))),
+            (
+                0x22,
+                Instruction::LD(LoadType::IndirectFromA(Indirect::HLInc)),
+            ),
+            (
+                0x32,
+                Instruction::LD(LoadType::IndirectFromA(Indirect::HLDec)),
+            ),
+            (0x0A, Instruction::LD(LoadType::AFromIndirect(Indirect::BC))),
+            (0x1A, Instruction::LD(LoadType::AFromIndirect(Indirect::DE))),
+            (
+                0x2A,
+                Instruction::LD(LoadType::AFromIndirect(Indirect::HLInc)),
+            ),
+            (
+                0x3A,
+                Instruction::LD(LoadType::AFromIndirect(Indirect::HLDec)),
+            ),
+            (0xEA, Instruction::LD(LoadType::AbsoluteFromA)),
+            (0xFA, Instruction::LD(LoadType::AFromAbsolute)),
+            (0xE0, Instruction::LD(LoadType::HighFromA(HighOffset::A8))),
+            (0xF0, Instruction::LD(LoadType::AFromHigh(HighOffset::A8))),
+            (0xE2, Instruction::LD(LoadType::HighFromA(HighOffset::C))),
+            (0xF2, Instruction::LD(LoadType::AFromHigh(HighOffset::C))),
+            (
+                0x08,
+                Instruction::LD(LoadType::Word(LoadWordTarget::A16, LoadWordSource::SP)),
+            ),
+            (
+                0xF9,
+                Instruction::LD(LoadType::Word(LoadWordTarget::SP, LoadWordSource::HL)),
+            ),
+            (0xC7, Instruction::RST(0x00)),
+            (0xCF, Instruction::RST(0x08)),
+            (0xD7, Instruction::RST(0x10)),
+            (0xDF, Instruction::RST(0x18)),
+            (0xE7, Instruction::RST(0x20)),
+            (0xEF, Instruction::RST(0x28)),
+            (0xF7, Instruction::RST(0x30)),
+            (0xFF, Instruction::RST(0x38)),
+        ];
+        for (byte, expected) in table {
+            assert_eq!(decode(byte), Some(expected), "opcode 0x{:02X}", byte);
+        }
+    }
+
+    // LD r,rのブロックは0x76（HALT）を除いてすべてLDになる
+    #[test]
+    fn test_decode_ld_register_grid() {
+        for byte in 0x40..=0x7F {
+            let instruction = decode(byte);
+            if byte == 0x76 {
+                assert_eq!(instruction, Some(Instruction::HALT));
+            } else {
+                assert!(
+                    matches!(instruction, Some(Instruction::LD(LoadType::Byte(_, _)))),
+                    "opcode 0x{:02X}",
+                    byte
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_add_memory_and_immediate() {
+        assert_eq!(decode(0x86), Some(Instruction::ADD(ArithmeticTarget::HLI)));
+        assert_eq!(decode(0xC6), Some(Instruction::ADD(ArithmeticTarget::D8)));
+    }
+
+    #[test]
+    fn test_decode_alu_immediate() {
+        let table = [
+            (0xCE, Instruction::ADC(ArithmeticTarget::D8)),
+            (0xD6, Instruction::SUB(ArithmeticTarget::D8)),
+            (0xDE, Instruction::SBC(ArithmeticTarget::D8)),
+            (0xE6, Instruction::AND(ArithmeticTarget::D8)),
+            (0xEE, Instruction::XOR(ArithmeticTarget::D8)),
+            (0xF6, Instruction::OR(ArithmeticTarget::D8)),
+            (0xFE, Instruction::CP(ArithmeticTarget::D8)),
+        ];
+        for (byte, instruction) in table {
+            assert_eq!(decode(byte), Some(instruction), "opcode 0x{:02X}", byte);
+        }
+    }
+
+    #[test]
+    fn test_decode_alu_hli() {
+        let table = [
+            (0x8E, Instruction::ADC(ArithmeticTarget::HLI)),
+            (0x96, Instruction::SUB(ArithmeticTarget::HLI)),
+            (0x9E, Instruction::SBC(ArithmeticTarget::HLI)),
+            (0xA6, Instruction::AND(ArithmeticTarget::HLI)),
+            (0xAE, Instruction::XOR(ArithmeticTarget::HLI)),
+            (0xB6, Instruction::OR(ArithmeticTarget::HLI)),
+            (0xBE, Instruction::CP(ArithmeticTarget::HLI)),
+        ];
+        for (byte, instruction) in table {
+            assert_eq!(decode(byte), Some(instruction), "opcode 0x{:02X}", byte);
+        }
+    }
+
+    #[test]
+    fn test_decode_prefixed_swap() {
+        let table = [
+            (0x30, PrefixTarget::B),
+            (0x31, PrefixTarget::C),
+            (0x32, PrefixTarget::D),
+            (0x33, PrefixTarget::E),
+            (0x34, PrefixTarget::H),
+            (0x35, PrefixTarget::L),
+            (0x36, PrefixTarget::HLI),
+            (0x37, PrefixTarget::A),
+        ];
+        for (byte, target) in table {
+            assert_eq!(
+                Instruction::from_byte(byte, true),
+                Some(Instruction::SWAP(target))
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_prefixed_rotate_shift() {
+        let table = [
+            (0x00, Instruction::RLC(PrefixTarget::B)),
+            (0x07, Instruction::RLC(PrefixTarget::A)),
+            (0x0E, Instruction::RRC(PrefixTarget::HLI)),
+            (0x11, Instruction::RL(PrefixTarget::C)),
+            (0x1A, Instruction::RR(PrefixTarget::D)),
+            (0x23, Instruction::SLA(PrefixTarget::E)),
+            (0x2C, Instruction::SRA(PrefixTarget::H)),
+            (0x3D, Instruction::SRL(PrefixTarget::L)),
+        ];
+        for (byte, expected) in table {
+            assert_eq!(Instruction::from_byte(byte, true), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_decode_prefixed_bit_operations() {
+        let table = [
+            (0x40, Instruction::BIT(0, PrefixTarget::B)),
+            (0x7E, Instruction::BIT(7, PrefixTarget::HLI)),
+            (0x5F, Instruction::BIT(3, PrefixTarget::A)),
+            (0x80, Instruction::RES(0, PrefixTarget::B)),
+            (0xBF, Instruction::RES(7, PrefixTarget::A)),
+            (0x9A, Instruction::RES(3, PrefixTarget::D)),
+            (0xC0, Instruction::SET(0, PrefixTarget::B)),
+            (0xFF, Instruction::SET(7, PrefixTarget::A)),
+            (0xE6, Instruction::SET(4, PrefixTarget::HLI)),
+        ];
+        for (byte, expected) in table {
+            assert_eq!(Instruction::from_byte(byte, true), Some(expected));
+        }
+    }
+
+    // 0xCBプレフィックス命令は256個すべてが定義されている
+    #[test]
+    fn test_decode_prefixed_table_is_complete() {
+        for byte in 0x00..=0xFF {
+            assert!(
+                Instruction::from_byte(byte, true).is_some(),
+                "opcode 0xCB{:02X}",
+                byte
+            );
+        }
+    }
+
+    #[test]
+    fn test_cycle_tables() {
+        assert_eq!(CYCLES[0x00], 4); // NOP
+        assert_eq!(CYCLES[0x36], 12); // LD (HL),d8
+        assert_eq!(CYCLES[0xCD], 24); // CALL a16
+        assert_eq!(CYCLES[0x08], 20); // LD (a16),SP
+        assert_eq!(CYCLES[0xF8], 12); // LD HL,SP+e8
+        assert_eq!(CYCLES[0xD3], 0); // 未使用
+        assert_eq!(CB_CYCLES[0x11], 8); // RL C
+        assert_eq!(CB_CYCLES[0x46], 12); // BIT 0,(HL)
+        assert_eq!(CB_CYCLES[0xC6], 16); // SET 0,(HL)
+        assert_eq!(taken_branch_cycles(0xC4), 12); // CALL NZ
+        assert_eq!(taken_branch_cycles(0xC3), 0); // JPは無条件
+    }
+
+    #[test]
+    fn test_cycles() {
+        assert_eq!(Instruction::cycles(0x00, false, false), 4); // NOP
+        assert_eq!(Instruction::cycles(0x81, false, false), 4); // ADD A,C
+        assert_eq!(Instruction::cycles(0x86, false, false), 8); // ADD A,(HL)
+        assert_eq!(Instruction::cycles(0x46, false, false), 8); // LD B,(HL)
+        assert_eq!(Instruction::cycles(0x70, false, false), 8); // LD (HL),B
+        assert_eq!(Instruction::cycles(0x36, false, false), 12); // LD (HL),d8
+        assert_eq!(Instruction::cycles(0x34, false, false), 12); // INC (HL)
+        assert_eq!(Instruction::cycles(0x3E, false, false), 8); // LD A,d8
+        assert_eq!(Instruction::cycles(0x01, false, false), 12); // LD BC,d16
+        assert_eq!(Instruction::cycles(0xC3, false, false), 16); // JP a16
+        assert_eq!(Instruction::cycles(0xC2, false, false), 12); // JP NZ（不成立）
+        assert_eq!(Instruction::cycles(0xC2, false, true), 16); // JP NZ（成立）
+        assert_eq!(Instruction::cycles(0x20, false, false), 8); // JR NZ（不成立）
+        assert_eq!(Instruction::cycles(0x20, false, true), 12); // JR NZ（成立）
+        assert_eq!(Instruction::cycles(0xCD, false, true), 24); // CALL a16
+        assert_eq!(Instruction::cycles(0xC4, false, true), 24); // CALL NZ（成立）
+        assert_eq!(Instruction::cycles(0xC0, false, true), 20); // RET NZ（成立）
+        assert_eq!(Instruction::cycles(0xC9, false, true), 16); // RET
+        assert_eq!(Instruction::cycles(0xC5, false, false), 16); // PUSH BC
+        assert_eq!(Instruction::cycles(0x37, true, false), 8); // SWAP A
+        assert_eq!(Instruction::cycles(0x46, true, false), 12); // BIT 0,(HL)
+        assert_eq!(Instruction::cycles(0xC6, true, false), 16); // SET 0,(HL)
+    }
+
+    #[test]
+    fn test_length() {
+        assert_eq!(Instruction::length(0x00, false), 1); // NOP
+        assert_eq!(Instruction::length(0x0E, false), 2); // LD C,d8
+        assert_eq!(Instruction::length(0xC3, false), 3); // JP a16
+        assert_eq!(Instruction::length(0x37, true), 2); // SWAP A
+        assert_eq!(Instruction::length(0x10, false), 2); // STOP
+        assert_eq!(Instruction::length(0xE0, false), 2); // LDH (a8),A
+        assert_eq!(Instruction::length(0x08, false), 3); // LD (a16),SP
+    }
+
+    // 実機で使われていないオペコードはIllegalになり、それ以外はすべてデコードできる
+    // 0xCBはプレフィックスなので、単独ではデコードしない
+    #[test]
+    fn test_decode_illegal_opcodes() {
+        let illegal = [
+            0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+        ];
+        for byte in 0..=0xFF {
+            if illegal.contains(&byte) {
+                assert_eq!(decode(byte), Some(Instruction::Illegal(byte)));
+            } else if byte == 0xCB {
+                assert_eq!(decode(byte), None);
+            } else {
+                assert!(
+                    !matches!(decode(byte), None | Some(Instruction::Illegal(_))),
+                    "0x{:02X}",
+                    byte
+                );
+            }
+        }
+    }
 }
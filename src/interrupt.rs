@@ -0,0 +1,58 @@
+// 割り込みの種類
+// IE/IFのビット番号の順に並べてあり、番号が小さいほど優先度が高い
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    VBlank,
+    LcdStat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    // IE/IFでのビット
+    pub fn bit(&self) -> u8 {
+        1 << (*self as u8)
+    }
+
+    // 割り込みを処理するときに飛ぶアドレス（0x40から8バイトごと）
+    pub fn vector(&self) -> u16 {
+        0x40 + (*self as u16) * 8
+    }
+}
+
+// IE & IFの値から、次に処理する（最も優先度が高い）割り込みを選ぶ
+pub fn highest_priority(pending: u8) -> Option<Interrupt> {
+    match (pending & 0x1F).trailing_zeros() {
+        0 => Some(Interrupt::VBlank),
+        1 => Some(Interrupt::LcdStat),
+        2 => Some(Interrupt::Timer),
+        3 => Some(Interrupt::Serial),
+        4 => Some(Interrupt::Joypad),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highest_priority() {
+        // VBlankとTimerが両方要求されていればVBlankを選ぶ
+        assert_eq!(highest_priority(0b0_0101), Some(Interrupt::VBlank));
+        assert_eq!(highest_priority(0b1_1000), Some(Interrupt::Serial));
+        assert_eq!(highest_priority(0), None);
+        // ビット5〜7は割り込みではない
+        assert_eq!(highest_priority(0xE0), None);
+    }
+
+    #[test]
+    fn test_bit_and_vector() {
+        assert_eq!(Interrupt::VBlank.bit(), 0x01);
+        assert_eq!(Interrupt::Joypad.bit(), 0x10);
+        assert_eq!(Interrupt::VBlank.vector(), 0x40);
+        assert_eq!(Interrupt::Timer.vector(), 0x50);
+        assert_eq!(Interrupt::Joypad.vector(), 0x60);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// ボタン
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    // P1の下位4ビットのどのビットに対応するか
+    fn bit(&self) -> u8 {
+        match self {
+            Button::Right | Button::A => 1 << 0,
+            Button::Left | Button::B => 1 << 1,
+            Button::Up | Button::Select => 1 << 2,
+            Button::Down | Button::Start => 1 << 3,
+        }
+    }
+
+    fn is_direction(&self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+}
+
+// P1のビット4: 0のとき方向キーを読む
+const SELECT_DIRECTIONS: u8 = 1 << 4;
+// P1のビット5: 0のときボタンを読む
+const SELECT_BUTTONS: u8 = 1 << 5;
+
+// ジョイパッド（P1/JOYP、0xFF00）
+// 押されているボタンのビットが0として読める（アクティブロー）
+#[derive(Default)]
+pub struct Joypad {
+    // 書き込まれた選択ビット（ビット4〜5）
+    select: u8,
+    // 押されている方向キーとボタン（1が押されている）
+    directions: u8,
+    buttons: u8,
+}
+
+impl Joypad {
+    pub fn read(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pressed |= self.directions;
+        }
+        if self.select & SELECT_BUTTONS == 0 {
+            pressed |= self.buttons;
+        }
+        // 使われていない上位2ビットは1が読める
+        0xC0 | self.select | (!pressed & 0x0F)
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.select = value & (SELECT_DIRECTIONS | SELECT_BUTTONS);
+    }
+
+    // ボタンを押す
+    // 読み出されるビットが1から0に変わる場合は、ジョイパッド割り込みを要求するためにtrueを返す
+    pub fn press(&mut self, button: Button) -> bool {
+        let before = self.read();
+        if button.is_direction() {
+            self.directions |= button.bit();
+        } else {
+            self.buttons |= button.bit();
+        }
+        before & !self.read() & 0x0F != 0
+    }
+
+    // どれかのボタンが押されているか（選択ビットに関係なく）
+    pub fn any_pressed(&self) -> bool {
+        self.directions | self.buttons != 0
+    }
+
+    // すべてのボタンの状態をまとめて設定する（1が押されている）
+    // ビット0〜7がそれぞれ右、左、上、下、A、B、セレクト、スタートに対応する
+    // pressと同じく、読み出されるビットが1から0に変わる場合はtrueを返す
+    pub fn set_state(&mut self, buttons: u8) -> bool {
+        let before = self.read();
+        self.directions = buttons & 0x0F;
+        self.buttons = buttons >> 4;
+        before & !self.read() & 0x0F != 0
+    }
+
+    // set_stateと同じ並びの、現在のボタンの状態
+    pub fn state(&self) -> u8 {
+        self.buttons << 4 | self.directions
+    }
+
+    pub fn release(&mut self, button: Button) {
+        if button.is_direction() {
+            self.directions &= !button.bit();
+        } else {
+            self.buttons &= !button.bit();
+        }
+    }
+}
+
+impl Snapshot for Joypad {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(self.select);
+        writer.write_u8(self.directions);
+        writer.write_u8(self.buttons);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.select = reader.read_u8()?;
+        self.directions = reader.read_u8()?;
+        self.buttons = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nothing_pressed_reads_high() {
+        let joypad = Joypad::default();
+        assert_eq!(joypad.read(), 0xCF);
+    }
+
+    // ボタンを選んでAを押すとビット0が0になる
+    #[test]
+    fn test_press_a_with_buttons_selected() {
+        let mut joypad = Joypad::default();
+        joypad.write(SELECT_DIRECTIONS);
+        assert!(joypad.press(Button::A));
+        assert_eq!(joypad.read(), 0xDE);
+        // 方向キーを選んでいるときはAは見えない
+        joypad.write(SELECT_BUTTONS);
+        assert_eq!(joypad.read(), 0xEF);
+
+        joypad.release(Button::A);
+        joypad.write(SELECT_DIRECTIONS);
+        assert_eq!(joypad.read(), 0xDF);
+    }
+
+    #[test]
+    fn test_press_direction() {
+        let mut joypad = Joypad::default();
+        joypad.write(SELECT_BUTTONS);
+        assert!(joypad.press(Button::Down));
+        assert!(joypad.press(Button::Left));
+        assert_eq!(joypad.read(), 0xE5);
+    }
+
+    // 選ばれていないグループや、すでに押されているボタンでは割り込みを要求しない
+    #[test]
+    fn test_press_interrupt_only_on_falling_edge() {
+        let mut joypad = Joypad::default();
+        joypad.write(SELECT_BUTTONS);
+        assert!(!joypad.press(Button::Start));
+        assert!(joypad.press(Button::Up));
+        assert!(!joypad.press(Button::Up));
+    }
+
+    #[test]
+    fn test_set_state() {
+        let mut joypad = Joypad::default();
+        joypad.write(SELECT_DIRECTIONS);
+        // A（ビット4）とスタート（ビット7）、右（ビット0）を押す
+        assert!(joypad.set_state(0b1001_0001));
+        assert_eq!(joypad.read(), 0xD6);
+        assert_eq!(joypad.state(), 0b1001_0001);
+        joypad.write(SELECT_BUTTONS);
+        assert_eq!(joypad.read(), 0xEE);
+
+        // 離すだけでは割り込みは要求されない
+        assert!(!joypad.set_state(0));
+        assert_eq!(joypad.read(), 0xEF);
+        assert!(!joypad.any_pressed());
+    }
+}
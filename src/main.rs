@@ -1,7 +1,29 @@
+// エミュレータはまだ組み立て途中なので、未使用の定義を許容する
+#![allow(dead_code)]
+// 命令名はニーモニック（ADD, LD, CPUなど）に合わせて大文字で表記する
+#![allow(clippy::upper_case_acronyms)]
+// テストではDefaultで生成したあとにフィールドを個別に設定する書き方を使う
+#![cfg_attr(test, allow(clippy::field_reassign_with_default))]
+
+mod apu;
+#[cfg(test)]
+mod blargg;
+mod cartridge;
 mod cpu;
+mod disassembler;
+mod dispatch;
+mod input_log;
 mod instruction;
+mod interrupt;
+mod joypad;
+mod mbc;
 mod memory;
+mod ppu;
 mod registers;
+mod serial;
+mod snapshot;
+mod timer;
+mod watch;
 
 fn main() {
     println!("Hello, world!");
@@ -0,0 +1,729 @@
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// メモリバンクコントローラ（MBC）
+// カートリッジのROM/RAMを持ち、0x0000〜0x7FFFと0xA000〜0xBFFFへのアクセスを担当する
+pub enum Mbc {
+    Mbc1(Mbc1),
+    Mbc3(Mbc3),
+    Mbc5(Mbc5),
+}
+
+impl Mbc {
+    // 0x0000〜0x7FFFの読み込み
+    pub fn read_rom(&self, address: u16) -> u8 {
+        match self {
+            Mbc::Mbc1(mbc) => mbc.read_rom(address),
+            Mbc::Mbc3(mbc) => mbc.read_rom(address),
+            Mbc::Mbc5(mbc) => mbc.read_rom(address),
+        }
+    }
+
+    // 0x0000〜0x7FFFへの書き込みはROMではなくMBCのレジスタに対する操作になる
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        match self {
+            Mbc::Mbc1(mbc) => mbc.write_rom(address, value),
+            Mbc::Mbc3(mbc) => mbc.write_rom(address, value),
+            Mbc::Mbc5(mbc) => mbc.write_rom(address, value),
+        }
+    }
+
+    // 0xA000〜0xBFFFの読み込み
+    pub fn read_ram(&self, address: u16) -> u8 {
+        match self {
+            Mbc::Mbc1(mbc) => mbc.read_ram(address),
+            Mbc::Mbc3(mbc) => mbc.read_ram(address),
+            Mbc::Mbc5(mbc) => mbc.read_ram(address),
+        }
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        match self {
+            Mbc::Mbc1(mbc) => mbc.write_ram(address, value),
+            Mbc::Mbc3(mbc) => mbc.write_ram(address, value),
+            Mbc::Mbc5(mbc) => mbc.write_ram(address, value),
+        }
+    }
+
+    // スナップショットで種類を区別するための番号
+    fn kind(&self) -> u8 {
+        match self {
+            Mbc::Mbc1(_) => 1,
+            Mbc::Mbc3(_) => 3,
+            Mbc::Mbc5(_) => 5,
+        }
+    }
+
+    // 外部RAMの全バンク（セーブデータの保存・読み込みに使う）
+    pub fn ram(&self) -> &[u8] {
+        match self {
+            Mbc::Mbc1(mbc) => &mbc.ram,
+            Mbc::Mbc3(mbc) => &mbc.ram,
+            Mbc::Mbc5(mbc) => &mbc.ram,
+        }
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        match self {
+            Mbc::Mbc1(mbc) => &mut mbc.ram,
+            Mbc::Mbc3(mbc) => &mut mbc.ram,
+            Mbc::Mbc5(mbc) => &mut mbc.ram,
+        }
+    }
+}
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+// バンク内のaddressのバイト
+// ROMの大きさを超えるバンク番号は折り返す
+fn rom_byte(rom: &[u8], bank: usize, address: u16) -> u8 {
+    let bank_count = (rom.len() / ROM_BANK_SIZE).max(1);
+    let offset = (address as usize) % ROM_BANK_SIZE;
+    let index = (bank % bank_count) * ROM_BANK_SIZE + offset;
+    rom.get(index).copied().unwrap_or(0xFF)
+}
+
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // 0x2000〜0x3FFF: ROMバンク番号の下位5ビット
+    rom_bank: u8,
+    // 0x4000〜0x5FFF: RAMバンク番号、またはROMバンク番号の上位2ビット
+    bank_high: u8,
+    // 0x6000〜0x7FFF: falseならROMバンキングモード、trueならRAMバンキングモード
+    ram_banking_mode: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Mbc1 {
+        Mbc1 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            bank_high: 0,
+            ram_banking_mode: false,
+        }
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            // RAMバンキングモードでは0x0000〜0x3FFFにも上位ビットが効く
+            0x0000..=0x3FFF if self.ram_banking_mode => (self.bank_high as usize) << 5,
+            0x0000..=0x3FFF => 0,
+            _ => {
+                // バンク0は選べず、0を書き込むとバンク1になる
+                let low = if self.rom_bank == 0 { 1 } else { self.rom_bank };
+                ((self.bank_high as usize) << 5) | low as usize
+            }
+        };
+        rom_byte(&self.rom, bank, address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x1F,
+            0x4000..=0x5FFF => self.bank_high = value & 0x03,
+            _ => self.ram_banking_mode = value & 0x01 != 0,
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let bank = if self.ram_banking_mode {
+            self.bank_high as usize
+        } else {
+            0
+        };
+        let index = bank * RAM_BANK_SIZE + (address as usize - 0xA000);
+        Some(index % self.ram.len())
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        // RAMが無効な場合は0xFFが読める
+        match self.ram_index(address) {
+            Some(index) => self.ram[index],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(index) = self.ram_index(address) {
+            self.ram[index] = value;
+        }
+    }
+}
+
+// MBC3: 7ビットのROMバンク番号と、4つのRAMバンクまたはRTCのレジスタを切り替える
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    // 0x0000〜0x1FFF: RAMとRTCの有効化
+    ram_enabled: bool,
+    // 0x2000〜0x3FFF: ROMバンク番号（0はバンク1になる）
+    rom_bank: u8,
+    // 0x4000〜0x5FFF: 0x00〜0x03ならRAMバンク、0x08〜0x0CならRTCのレジスタ
+    ram_select: u8,
+    // 0x6000〜0x7FFFに0x00が書き込まれ、次に0x01が書き込まれるとラッチする
+    latch_prepared: bool,
+    rtc: Rtc,
+    // ラッチした時点のRTCのレジスタ（0xA000〜0xBFFFから読めるのはこちら）
+    latched: [u8; RTC_REGISTER_COUNT],
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Mbc3 {
+        Mbc3::with_clock(rom, ram_size, host_clock)
+    }
+
+    // RTCが参照する時計（UNIX時間の秒）を指定して作る
+    pub fn with_clock(rom: Vec<u8>, ram_size: usize, clock: fn() -> u64) -> Mbc3 {
+        Mbc3 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            latch_prepared: false,
+            rtc: Rtc::new(clock),
+            latched: [0; RTC_REGISTER_COUNT],
+        }
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank.max(1) as usize,
+        };
+        rom_byte(&self.rom, bank, address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_select = value,
+            _ => {
+                if self.latch_prepared && value == 0x01 {
+                    self.latched = self.rtc.registers();
+                }
+                self.latch_prepared = value == 0x00;
+            }
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let index = self.ram_select as usize * RAM_BANK_SIZE + (address as usize - 0xA000);
+        Some(index % self.ram.len())
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match self.ram_select {
+            0x00..=0x03 => self
+                .ram_index(address)
+                .map_or(0xFF, |index| self.ram[index]),
+            RTC_SECONDS..=RTC_DAYS_HIGH => self.latched[(self.ram_select - RTC_SECONDS) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        match self.ram_select {
+            0x00..=0x03 => {
+                if let Some(index) = self.ram_index(address) {
+                    self.ram[index] = value;
+                }
+            }
+            RTC_SECONDS..=RTC_DAYS_HIGH => self.rtc.write(self.ram_select - RTC_SECONDS, value),
+            _ => {}
+        }
+    }
+}
+
+// MBC5: 9ビットのROMバンク番号と4ビットのRAMバンク番号を持つ
+// MBC1やMBC3と違い、0x4000〜0x7FFFにバンク0を割り当てられる
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    // 0x0000〜0x1FFF: RAMの有効化
+    ram_enabled: bool,
+    // 0x2000〜0x2FFF: 下位8ビット、0x3000〜0x3FFF: 9ビット目
+    rom_bank: u16,
+    // 0x4000〜0x5FFF: RAMバンク番号
+    ram_bank: u8,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Mbc5 {
+        Mbc5 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        };
+        rom_byte(&self.rom, bank, address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8)
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let index = self.ram_bank as usize * RAM_BANK_SIZE + (address as usize - 0xA000);
+        Some(index % self.ram.len())
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.ram_index(address) {
+            Some(index) => self.ram[index],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(index) = self.ram_index(address) {
+            self.ram[index] = value;
+        }
+    }
+}
+
+// RTCのレジスタを選ぶ値（秒、分、時、日の下位8ビット、日の上位ビットとフラグ）
+const RTC_SECONDS: u8 = 0x08;
+const RTC_DAYS_HIGH: u8 = 0x0C;
+const RTC_REGISTER_COUNT: usize = 5;
+// DHのビット0: 日の9ビット目、ビット6: 停止、ビット7: 日のオーバーフロー
+const RTC_DAY_HIGH_BIT: u8 = 1 << 0;
+const RTC_HALT: u8 = 1 << 6;
+const RTC_DAY_CARRY: u8 = 1 << 7;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+// 日のカウンタは9ビット
+const RTC_DAYS: u64 = 512;
+
+fn host_clock() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// カートリッジの時計（RTC）
+// 経過秒数をホストの時計との差で持ち、レジスタはそこから計算する
+struct Rtc {
+    clock: fn() -> u64,
+    // 経過秒数が0だったときのホストの時刻
+    base: u64,
+    // 停止中は経過秒数が進まないので、停止したときの値を持つ
+    halted: Option<u64>,
+    // 書き込みで消されるまで残る日のオーバーフロー
+    carry: bool,
+}
+
+impl Rtc {
+    fn new(clock: fn() -> u64) -> Rtc {
+        Rtc {
+            clock,
+            base: clock(),
+            halted: None,
+            carry: false,
+        }
+    }
+
+    fn elapsed(&self) -> u64 {
+        self.halted
+            .unwrap_or_else(|| (self.clock)().saturating_sub(self.base))
+    }
+
+    fn registers(&self) -> [u8; RTC_REGISTER_COUNT] {
+        let elapsed = self.elapsed();
+        let total_days = elapsed / SECONDS_PER_DAY;
+        let days = total_days % RTC_DAYS;
+        let mut days_high = (days >> 8) as u8;
+        if self.halted.is_some() {
+            days_high |= RTC_HALT;
+        }
+        if self.carry || total_days >= RTC_DAYS {
+            days_high |= RTC_DAY_CARRY;
+        }
+        [
+            (elapsed % 60) as u8,
+            (elapsed / 60 % 60) as u8,
+            (elapsed / 3600 % 24) as u8,
+            days as u8,
+            days_high,
+        ]
+    }
+
+    // レジスタを1つ書き換え、その値から経過秒数を計算し直す
+    fn write(&mut self, register: u8, value: u8) {
+        let mut registers = self.registers();
+        registers[register as usize] = value;
+        let [seconds, minutes, hours, days_low, days_high] = registers;
+        let days = ((days_high & RTC_DAY_HIGH_BIT) as u64) << 8 | days_low as u64;
+        let elapsed = days * SECONDS_PER_DAY
+            + (hours & 0x1F) as u64 * 3600
+            + (minutes & 0x3F) as u64 * 60
+            + (seconds & 0x3F) as u64;
+        self.carry = days_high & RTC_DAY_CARRY != 0;
+        if days_high & RTC_HALT != 0 {
+            self.halted = Some(elapsed);
+        } else {
+            self.halted = None;
+            self.base = (self.clock)().saturating_sub(elapsed);
+        }
+    }
+}
+
+// 先頭にMBCの種類を書き、読み込むときに今のカートリッジと同じ種類か確かめる
+impl Snapshot for Mbc {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(self.kind());
+        match self {
+            Mbc::Mbc1(mbc) => mbc.save_state(writer),
+            Mbc::Mbc3(mbc) => mbc.save_state(writer),
+            Mbc::Mbc5(mbc) => mbc.save_state(writer),
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        if reader.read_u8()? != self.kind() {
+            return Err(SnapshotError::CartridgeMismatch);
+        }
+        match self {
+            Mbc::Mbc1(mbc) => mbc.load_state(reader),
+            Mbc::Mbc3(mbc) => mbc.load_state(reader),
+            Mbc::Mbc5(mbc) => mbc.load_state(reader),
+        }
+    }
+}
+
+// ROMはカートリッジから読み直せるので、外部RAMとバンク切り替えのレジスタだけを保存する
+impl Snapshot for Mbc1 {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_sized_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.bank_high);
+        writer.write_bool(self.ram_banking_mode);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let ram = reader.read_sized_bytes()?;
+        if ram.len() != self.ram.len() {
+            return Err(SnapshotError::CartridgeMismatch);
+        }
+        self.ram = ram;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u8()?;
+        self.bank_high = reader.read_u8()?;
+        self.ram_banking_mode = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+// RTCはホストの時計との差を保存するので、読み込んだあとも実際の時間に合わせて進む
+impl Snapshot for Mbc3 {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_sized_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.ram_select);
+        writer.write_bool(self.latch_prepared);
+        writer.write_bytes(&self.latched);
+        writer.write_u64(self.rtc.base);
+        writer.write_bool(self.rtc.halted.is_some());
+        writer.write_u64(self.rtc.halted.unwrap_or(0));
+        writer.write_bool(self.rtc.carry);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let ram = reader.read_sized_bytes()?;
+        if ram.len() != self.ram.len() {
+            return Err(SnapshotError::CartridgeMismatch);
+        }
+        self.ram = ram;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u8()?;
+        self.ram_select = reader.read_u8()?;
+        self.latch_prepared = reader.read_bool()?;
+        reader.read_bytes(&mut self.latched)?;
+        self.rtc.base = reader.read_u64()?;
+        let halted = reader.read_bool()?;
+        let halted_elapsed = reader.read_u64()?;
+        self.rtc.halted = halted.then_some(halted_elapsed);
+        self.rtc.carry = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Mbc5 {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_sized_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u16(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let ram = reader.read_sized_bytes()?;
+        if ram.len() != self.ram.len() {
+            return Err(SnapshotError::CartridgeMismatch);
+        }
+        self.ram = ram;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u16()?;
+        self.ram_bank = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 各バンクの先頭バイトにバンク番号を書いたROM
+    fn build_rom(bank_count: usize) -> Vec<u8> {
+        let mut rom = vec![0; bank_count * ROM_BANK_SIZE];
+        for bank in 0..bank_count {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_mbc1_default_bank_is_one() {
+        let mbc = Mbc1::new(build_rom(4), 0);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+    }
+
+    #[test]
+    fn test_mbc1_switch_rom_bank() {
+        let mut mbc = Mbc1::new(build_rom(4), 0);
+        mbc.write_rom(0x2000, 0x02);
+        assert_eq!(mbc.read_rom(0x4000), 2);
+        mbc.write_rom(0x3FFF, 0x03);
+        assert_eq!(mbc.read_rom(0x4000), 3);
+        // 0x0000〜0x3FFFは常にバンク0
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    // バンク0を選ぶとバンク1になる
+    #[test]
+    fn test_mbc1_bank_zero_maps_to_one() {
+        let mut mbc = Mbc1::new(build_rom(4), 0);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        // 下位5ビットが0になる値（0x20）も同じ
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+    }
+
+    // 0x4000〜0x5FFFで上位2ビットを指定する
+    #[test]
+    fn test_mbc1_upper_rom_bank_bits() {
+        let mut mbc = Mbc1::new(build_rom(64), 0);
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x02);
+        assert_eq!(mbc.read_rom(0x4000), 0x22);
+        // ROMバンキングモードでは0x0000〜0x3FFFはバンク0のまま
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        // RAMバンキングモードでは0x0000〜0x3FFFもバンク0x20になる
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+    }
+
+    #[test]
+    fn test_mbc1_ram_enable_and_banking() {
+        let mut mbc = Mbc1::new(build_rom(4), 0x8000);
+        // 有効にするまでは書き込めず、0xFFが読める
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        // RAMバンキングモードでバンク1に切り替える
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_ram(0xA000, 0x34);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    // テストで使う時計（スレッドごとに別の時刻を持つ）
+    thread_local! {
+        static NOW: std::cell::Cell<u64> = const { std::cell::Cell::new(1_000_000) };
+    }
+
+    fn test_clock() -> u64 {
+        NOW.with(|now| now.get())
+    }
+
+    fn advance_clock(seconds: u64) {
+        NOW.with(|now| now.set(now.get() + seconds));
+    }
+
+    #[test]
+    fn test_mbc3_rom_bank_switch() {
+        let mut mbc = Mbc3::new(build_rom(128), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        // 7ビットすべてを使える（MBC1と違い0x20なども選べる）
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 0x20);
+        mbc.write_rom(0x2000, 0x7F);
+        assert_eq!(mbc.read_rom(0x4000), 0x7F);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn test_mbc3_ram_bank_switch() {
+        let mut mbc = Mbc3::new(build_rom(4), 0x8000);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(0xA000, 0x33);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_rom(0x4000, 0x03);
+        assert_eq!(mbc.read_ram(0xA000), 0x33);
+    }
+
+    // 0x00、0x01の順に書き込むと、その時点のRTCがラッチされる
+    #[test]
+    fn test_mbc3_rtc_latch() {
+        let mut mbc = Mbc3::with_clock(build_rom(4), 0x2000, test_clock);
+        mbc.write_rom(0x0000, 0x0A);
+        // 1日と2時間3分4秒進める
+        advance_clock(SECONDS_PER_DAY + 2 * 3600 + 3 * 60 + 4);
+
+        // ラッチするまでは0のまま
+        mbc.write_rom(0x4000, RTC_SECONDS);
+        assert_eq!(mbc.read_ram(0xA000), 0);
+
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        let read = |mbc: &mut Mbc3, register: u8| {
+            mbc.write_rom(0x4000, register);
+            mbc.read_ram(0xA000)
+        };
+        assert_eq!(read(&mut mbc, 0x08), 4);
+        assert_eq!(read(&mut mbc, 0x09), 3);
+        assert_eq!(read(&mut mbc, 0x0A), 2);
+        assert_eq!(read(&mut mbc, 0x0B), 1);
+        assert_eq!(read(&mut mbc, 0x0C), 0);
+
+        // 時計が進んでも、次にラッチするまで読める値は変わらない
+        advance_clock(10);
+        assert_eq!(read(&mut mbc, 0x08), 4);
+        // 0x00を挟まずに0x01を書き込んでもラッチしない
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(read(&mut mbc, 0x08), 4);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(read(&mut mbc, 0x08), 14);
+    }
+
+    // 停止ビットを立てると時計が止まり、レジスタへの書き込みで時刻を設定できる
+    #[test]
+    fn test_mbc3_rtc_halt_and_write() {
+        let mut mbc = Mbc3::with_clock(build_rom(4), 0, test_clock);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, RTC_DAYS_HIGH);
+        mbc.write_ram(0xA000, RTC_HALT | RTC_DAY_HIGH_BIT);
+        mbc.write_rom(0x4000, RTC_SECONDS);
+        mbc.write_ram(0xA000, 30);
+        advance_clock(100);
+
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 30);
+        mbc.write_rom(0x4000, RTC_DAYS_HIGH);
+        assert_eq!(mbc.read_ram(0xA000), RTC_HALT | RTC_DAY_HIGH_BIT);
+    }
+
+    // 各バンクの先頭2バイトにバンク番号（リトルエンディアン）を書いたROM
+    fn build_large_rom(bank_count: usize) -> Vec<u8> {
+        let mut rom = vec![0; bank_count * ROM_BANK_SIZE];
+        for bank in 0..bank_count {
+            rom[bank * ROM_BANK_SIZE..bank * ROM_BANK_SIZE + 2]
+                .copy_from_slice(&(bank as u16).to_le_bytes());
+        }
+        rom
+    }
+
+    // 9ビット目を使うとバンク0x100以降を選べる
+    #[test]
+    fn test_mbc5_high_rom_bank() {
+        let mut mbc = Mbc5::new(build_large_rom(512), 0);
+        mbc.write_rom(0x2000, 0x00);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 0x00);
+        assert_eq!(mbc.read_rom(0x4001), 0x01);
+        mbc.write_rom(0x2000, 0x23);
+        assert_eq!(mbc.read_rom(0x4000), 0x23);
+        assert_eq!(mbc.read_rom(0x4001), 0x01);
+        mbc.write_rom(0x3000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x23);
+        assert_eq!(mbc.read_rom(0x4001), 0x00);
+    }
+
+    // MBC5ではバンク0を選べる
+    #[test]
+    fn test_mbc5_bank_zero_is_selectable() {
+        let mut mbc = Mbc5::new(build_rom(4), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0);
+    }
+
+    #[test]
+    fn test_mbc5_ram_banking() {
+        let mut mbc = Mbc5::new(build_rom(4), 0x20000);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(0xA000, 0x34);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_rom(0x4000, 0x0F);
+        assert_eq!(mbc.read_ram(0xA000), 0x34);
+    }
+}
@@ -1,21 +1,935 @@
+use crate::apu::Apu;
+use crate::cartridge::{CartridgeHeader, CartridgeType};
+use crate::interrupt::Interrupt;
+use crate::joypad::{Button, Joypad};
+use crate::mbc::{Mbc, Mbc1, Mbc3, Mbc5};
+use crate::ppu::{OAM_START, Ppu};
+use crate::serial::Serial;
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::timer::Timer;
+use crate::watch::{WatchEvent, WatchKind, Watchpoints};
+
+// ジョイパッド（P1/JOYP）
+pub const JOYPAD_ADDRESS: u16 = 0xFF00;
+// シリアル転送データ（SB）と制御（SC）
+pub const SB_ADDRESS: u16 = 0xFF01;
+pub const SC_ADDRESS: u16 = 0xFF02;
+// 割り込み要求フラグ（IF）
+pub const INTERRUPT_FLAG_ADDRESS: u16 = 0xFF0F;
+// 割り込み有効フラグ（IE）
+pub const INTERRUPT_ENABLE_ADDRESS: u16 = 0xFFFF;
+// ディバイダレジスタ（DIV）
+pub const DIV_ADDRESS: u16 = 0xFF04;
+// タイマーカウンタ（TIMA）
+pub const TIMA_ADDRESS: u16 = 0xFF05;
+// タイマーモジュロ（TMA）
+pub const TMA_ADDRESS: u16 = 0xFF06;
+// タイマー制御（TAC）
+pub const TAC_ADDRESS: u16 = 0xFF07;
+// LCD制御（LCDC）
+pub const LCDC_ADDRESS: u16 = 0xFF40;
+// LCDステータス（STAT）
+pub const STAT_ADDRESS: u16 = 0xFF41;
+// 背景のスクロール位置（SCY、SCX）
+pub const SCY_ADDRESS: u16 = 0xFF42;
+pub const SCX_ADDRESS: u16 = 0xFF43;
+// 現在のライン（LY）と比較用のライン（LYC）
+pub const LY_ADDRESS: u16 = 0xFF44;
+pub const LYC_ADDRESS: u16 = 0xFF45;
+// 速度切り替え（KEY1、ゲームボーイカラーのみ）
+pub const KEY1_ADDRESS: u16 = 0xFF4D;
+// 書き込むとブートROMが外れる（値は問わない）
+pub const BOOT_ROM_DISABLE_ADDRESS: u16 = 0xFF50;
+// OAM DMA転送の開始（DMA）
+pub const DMA_ADDRESS: u16 = 0xFF46;
+// 背景のパレット（BGP）
+pub const BGP_ADDRESS: u16 = 0xFF47;
+// スプライトのパレット（OBP0、OBP1）
+pub const OBP0_ADDRESS: u16 = 0xFF48;
+pub const OBP1_ADDRESS: u16 = 0xFF49;
+// ウィンドウの位置（WY、WX）
+pub const WY_ADDRESS: u16 = 0xFF4A;
+pub const WX_ADDRESS: u16 = 0xFF4B;
+
+// エコーRAM（0xE000〜0xFDFF）はワークRAM（0xC000〜0xDDFF）のミラー
+const ECHO_RAM_OFFSET: u16 = 0x2000;
+
+// ワークRAM（WRAM）は4KiBのバンクが8つ
+// 0xC000〜0xCFFFは常にバンク0、0xD000〜0xDFFFはSVBKで選んだバンク1〜7（DMGではバンク1だけ）
+const WRAM_START: u16 = 0xC000;
+const WRAM_BANK_SIZE: usize = 0x1000;
+const WRAM_BANK_COUNT: usize = 8;
+// ワークRAMのバンクの選択（SVBK、ゲームボーイカラーのみ）
+pub const SVBK_ADDRESS: u16 = 0xFF70;
+
+// ハイRAM（HRAM、0xFF80〜0xFFFE）の127バイト
+const HRAM_START: u16 = 0xFF80;
+const HRAM_SIZE: usize = 0x7F;
+
+// DMA転送でOAMにコピーされるバイト数
+const DMA_LENGTH: u16 = 0xA0;
+
+// ブートROMが重なる範囲（0x0000〜0x00FF）の大きさ
+const BOOT_ROM_SIZE: u16 = 0x100;
+
+// バンク切り替えなしで見えるカートリッジROMの大きさ（0x0000〜0x7FFF）
+const ROM_REGION_SIZE: usize = 0x8000;
+
+// CPUから見たメモリ（1バイト単位の読み書きだけ）
+// テストでは実際のバスの代わりにアクセスを記録するメモリなどを渡せる
+pub trait Memory {
+    fn read_byte(&self, address: u16) -> u8;
+    fn write_byte(&mut self, address: u16, value: u8);
+
+    // リトルエンディアンの16ビット値（下位バイトがaddress、上位バイトがaddress + 1）
+    // 0xFFFFの次は0x0000に折り返す
+    fn read_word(&self, address: u16) -> u16 {
+        u16::from_le_bytes([
+            self.read_byte(address),
+            self.read_byte(address.wrapping_add(1)),
+        ])
+    }
+
+    // 下位バイト、上位バイトの順に書き込む
+    fn write_word(&mut self, address: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.write_byte(address, low);
+        self.write_byte(address.wrapping_add(1), high);
+    }
+
+    // IEとIFの両方で立っている割り込み
+    fn pending_interrupts(&self) -> u8 {
+        self.read_byte(INTERRUPT_ENABLE_ADDRESS) & self.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x1F
+    }
+
+    // STOPの実行時に呼ばれ、速度の切り替えが準備されていれば切り替えてtrueを返す
+    fn switch_speed(&mut self) -> bool {
+        false
+    }
+}
+
 pub struct MemoryBus {
-    pub memory: [u8; 0xFFFF],
+    // アドレス空間は0x0000〜0xFFFFの両端を含むので0x10000バイト
+    pub memory: [u8; 0x10000],
+    // MBCを持つカートリッジの場合はROMと外部RAMへのアクセスをMBCに任せる
+    mbc: Option<Mbc>,
+    // 外部RAMがバッテリーでバックアップされているか（セーブデータを残すか）
+    battery: bool,
+    // 読み込まれている間は0x0000〜0x00FFがカートリッジではなくブートROMになる
+    boot_rom: Option<Vec<u8>>,
+    wram: Vec<u8>,
+    // 0xD000〜0xDFFFに見えるワークRAMのバンク（1〜7）
+    wram_bank: u8,
+    hram: [u8; HRAM_SIZE],
+    timer: Timer,
+    pub ppu: Ppu,
+    joypad: Joypad,
+    serial: Serial,
+    pub apu: Apu,
+    watchpoints: Watchpoints,
+    // 倍速モード（ゲームボーイカラー）の間はタイマーがCPUのサイクルの2倍の速さで進む
+    double_speed: bool,
+    // KEY1のビット0: 次のSTOPで速度を切り替える
+    speed_switch_armed: bool,
 }
 
 impl MemoryBus {
+    // カートリッジのROMを読み込む
+    // MBCを持つカートリッジはROM全体をMBCに渡し、バンク切り替えで参照する
+    // MBCを持たない場合は0x0000〜0x7FFFにコピーし、収まらない部分は切り捨てる
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        let header = CartridgeHeader::parse(rom);
+        self.mbc = match header.cartridge_type {
+            CartridgeType::Mbc1 | CartridgeType::Mbc1Ram | CartridgeType::Mbc1RamBattery => {
+                Some(Mbc::Mbc1(Mbc1::new(rom.to_vec(), header.ram_size_bytes())))
+            }
+            CartridgeType::Mbc3
+            | CartridgeType::Mbc3Ram
+            | CartridgeType::Mbc3RamBattery
+            | CartridgeType::Mbc3TimerBattery
+            | CartridgeType::Mbc3TimerRamBattery => {
+                Some(Mbc::Mbc3(Mbc3::new(rom.to_vec(), header.ram_size_bytes())))
+            }
+            CartridgeType::Mbc5
+            | CartridgeType::Mbc5Ram
+            | CartridgeType::Mbc5RamBattery
+            | CartridgeType::Mbc5Rumble
+            | CartridgeType::Mbc5RumbleRam
+            | CartridgeType::Mbc5RumbleRamBattery => {
+                Some(Mbc::Mbc5(Mbc5::new(rom.to_vec(), header.ram_size_bytes())))
+            }
+            _ => None,
+        };
+        self.battery = header.cartridge_type.has_battery();
+        let length = rom.len().min(ROM_REGION_SIZE);
+        self.memory[..length].copy_from_slice(&rom[..length]);
+    }
+
+    // ブートROMを0x0000〜0x00FFに重ねる
+    // 0xFF50に書き込まれるまでは、その下のカートリッジのバイトは見えない
+    pub fn load_boot_rom(&mut self, boot: &[u8]) {
+        let length = boot.len().min(BOOT_ROM_SIZE as usize);
+        self.boot_rom = Some(boot[..length].to_vec());
+    }
+
+    // バイト列をaddressから順にメモリへ置く（テストで命令列を用意するときなどに使う）
+    // 各アドレスの実体（VRAMやOAM、I/Oレジスタ、今のバンクのワークRAMなど）に置き、
+    // 0xFFFFを超えた分は0x0000に折り返す
+    // MBCがなければROMの範囲にも置ける。ウォッチポイントには記録しない
+    pub fn load_at(&mut self, address: u16, bytes: &[u8]) {
+        for (offset, &byte) in bytes.iter().enumerate() {
+            self.write_mapped(address.wrapping_add(offset as u16), byte);
+        }
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
-        self.memory[address as usize]
+        let value = self.read_mapped(address);
+        self.watchpoints.record(address, value, WatchKind::Read);
+        value
+    }
+
+    // ウォッチポイントに記録せずに読む（デバッガの表示など、プログラム自身の読み込みではないもの）
+    pub fn peek(&self, address: u16) -> u8 {
+        self.read_mapped(address)
+    }
+
+    fn read_mapped(&self, address: u16) -> u8 {
+        if address < BOOT_ROM_SIZE
+            && let Some(boot_rom) = &self.boot_rom
+        {
+            return boot_rom.get(address as usize).copied().unwrap_or(0xFF);
+        }
+        match (address, &self.mbc) {
+            (0x0000..=0x7FFF, Some(mbc)) => mbc.read_rom(address),
+            (0x8000..=0x9FFF, _) => self.ppu.read_vram(address),
+            (0xA000..=0xBFFF, Some(mbc)) => mbc.read_ram(address),
+            (0xC000..=0xDFFF, _) => self.wram[self.wram_index(address)],
+            (0xE000..=0xFDFF, _) => self.wram[self.wram_index(address - ECHO_RAM_OFFSET)],
+            (0xFE00..=0xFE9F, _) => self.ppu.read_oam(address),
+            // 使用禁止領域は常に0xFFが読める
+            (0xFEA0..=0xFEFF, _) => 0xFF,
+            (0xFF00..=0xFF7F | INTERRUPT_ENABLE_ADDRESS, _) => self.read_io(address),
+            (0xFF80..=0xFFFE, _) => self.hram[(address - HRAM_START) as usize],
+            _ => self.memory[address as usize],
+        }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
-        self.memory[address as usize] = value;
+        self.watchpoints.record(address, value, WatchKind::Write);
+        self.write_mapped(address, value);
+    }
+
+    fn write_mapped(&mut self, address: u16, value: u8) {
+        match (address, &mut self.mbc) {
+            (0x0000..=0x7FFF, Some(mbc)) => mbc.write_rom(address, value),
+            (0x8000..=0x9FFF, _) => self.ppu.write_vram(address, value),
+            (0xA000..=0xBFFF, Some(mbc)) => mbc.write_ram(address, value),
+            (0xC000..=0xDFFF, _) => {
+                let index = self.wram_index(address);
+                self.wram[index] = value;
+            }
+            (0xE000..=0xFDFF, _) => {
+                let index = self.wram_index(address - ECHO_RAM_OFFSET);
+                self.wram[index] = value;
+            }
+            (0xFE00..=0xFE9F, _) => self.ppu.write_oam(address, value),
+            // 使用禁止領域への書き込みは無視する
+            (0xFEA0..=0xFEFF, _) => {}
+            (0xFF00..=0xFF7F | INTERRUPT_ENABLE_ADDRESS, _) => self.write_io(address, value),
+            (0xFF80..=0xFFFE, _) => self.hram[(address - HRAM_START) as usize] = value,
+            _ => self.memory[address as usize] = value,
+        }
+    }
+
+    // ワークRAM（0xC000〜0xDFFF）のアドレスに対応する、バンクを含めたwramの位置
+    fn wram_index(&self, address: u16) -> usize {
+        let offset = (address - WRAM_START) as usize;
+        if offset < WRAM_BANK_SIZE {
+            offset
+        } else {
+            self.wram_bank as usize * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE
+        }
+    }
+
+    // 読み込まれているカートリッジのヘッダを解析する
+    pub fn parse_header(&self) -> CartridgeHeader {
+        let bytes: Vec<u8> = (0..0x150).map(|address| self.peek(address)).collect();
+        CartridgeHeader::parse(&bytes)
+    }
+
+    // 外部RAMの内容を.savファイルに書き出す
+    // バッテリーを持たないカートリッジでは何もしない
+    pub fn save_ram(&self, path: &str) -> std::io::Result<()> {
+        match &self.mbc {
+            Some(mbc) if self.battery => std::fs::write(path, mbc.ram()),
+            _ => Ok(()),
+        }
+    }
+
+    // .savファイルから外部RAMの内容を読み込む
+    // ファイルの大きさが外部RAMと異なる場合は収まる部分だけを読み込む
+    pub fn load_ram(&mut self, path: &str) -> std::io::Result<()> {
+        match &mut self.mbc {
+            Some(mbc) if self.battery => {
+                let data = std::fs::read(path)?;
+                let ram = mbc.ram_mut();
+                let length = data.len().min(ram.len());
+                ram[..length].copy_from_slice(&data[..length]);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // 命令の実行で消費したTサイクル数だけ周辺機器を進める
+    // PPUがVBlankに入った（フレームを描き終えた）場合はtrueを返す
+    pub fn tick(&mut self, cycles: u8) -> bool {
+        let mut timer_interrupt = self.timer.tick(cycles);
+        if self.double_speed {
+            timer_interrupt |= self.timer.tick(cycles);
+        }
+        if timer_interrupt {
+            self.request_interrupt(Interrupt::Timer);
+        }
+        let vblank = self.ppu.tick(cycles);
+        if vblank {
+            self.request_interrupt(Interrupt::VBlank);
+        }
+        if self.ppu.take_stat_interrupt() {
+            self.request_interrupt(Interrupt::LcdStat);
+        }
+        self.apu.tick(cycles);
+        vblank
+    }
+
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    pub fn press_button(&mut self, button: Button) {
+        if self.joypad.press(button) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    // ボタンの状態をまとめて設定する（並びはJoypad::set_stateと同じ）
+    pub fn set_buttons(&mut self, buttons: u8) {
+        if self.joypad.set_state(buttons) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.joypad.state()
+    }
+
+    pub fn release_button(&mut self, button: Button) {
+        self.joypad.release(button);
+    }
+
+    pub fn any_button_pressed(&self) -> bool {
+        self.joypad.any_pressed()
+    }
+
+    // アドレスへの読み込みや書き込みを監視し、take_watch_eventsで取り出せるように記録する
+    pub fn add_watch(&mut self, address: u16, on: WatchKind) {
+        self.watchpoints.add(address, on);
+    }
+
+    pub fn remove_watch(&mut self, address: u16) {
+        self.watchpoints.remove(address);
+    }
+
+    pub fn take_watch_events(&mut self) -> Vec<WatchEvent> {
+        self.watchpoints.take_events()
+    }
+
+    // 実行中の命令のアドレス（ウォッチポイントの記録に使う）
+    pub fn set_current_pc(&mut self, pc: u16) {
+        self.watchpoints.pc = pc;
+    }
+
+    // シリアルポートに送信された文字列
+    pub fn serial_output(&self) -> &str {
+        self.serial.output()
+    }
+
+    // IFの該当するビットを立てて割り込みを要求する
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.memory[INTERRUPT_FLAG_ADDRESS as usize] |= interrupt.bit();
+    }
+
+    // IEとIFの両方で立っている割り込み
+    pub fn pending_interrupts(&self) -> u8 {
+        self.memory[INTERRUPT_ENABLE_ADDRESS as usize]
+            & self.memory[INTERRUPT_FLAG_ADDRESS as usize]
+            & 0x1F
+    }
+
+    // I/Oレジスタの読み込み
+    // 特別な扱いが必要なレジスタ以外は配列の値をそのまま返す
+    fn read_io(&self, address: u16) -> u8 {
+        match address {
+            JOYPAD_ADDRESS => self.joypad.read(),
+            SB_ADDRESS => self.serial.sb,
+            SC_ADDRESS => self.serial.sc(),
+            // サウンドのチャンネル1（NR10〜NR14）とNR50、NR51
+            0xFF10..=0xFF14 | 0xFF24 | 0xFF25 => self.apu.read(address),
+            DIV_ADDRESS => self.timer.div(),
+            TIMA_ADDRESS => self.timer.tima,
+            TMA_ADDRESS => self.timer.tma,
+            TAC_ADDRESS => self.timer.tac(),
+            LCDC_ADDRESS => self.ppu.lcdc(),
+            STAT_ADDRESS => self.ppu.stat(),
+            SCY_ADDRESS => self.ppu.scy,
+            SCX_ADDRESS => self.ppu.scx,
+            LY_ADDRESS => self.ppu.ly(),
+            LYC_ADDRESS => self.ppu.lyc,
+            BGP_ADDRESS => self.ppu.bgp,
+            OBP0_ADDRESS => self.ppu.obp0,
+            OBP1_ADDRESS => self.ppu.obp1,
+            WY_ADDRESS => self.ppu.wy,
+            WX_ADDRESS => self.ppu.wx,
+            // 使われていない上位ビットは1
+            SVBK_ADDRESS => 0xF8 | self.wram_bank,
+            // ビット7が現在の速度、ビット0が切り替えの準備。それ以外は1
+            KEY1_ADDRESS => (self.double_speed as u8) << 7 | 0x7E | self.speed_switch_armed as u8,
+            _ => self.memory[address as usize],
+        }
+    }
+
+    // XX00〜XX9Fの160バイトをOAMにコピーする
+    // 実機では160Mサイクルかかるが、今は一瞬で終わらせる
+    fn dma_transfer(&mut self, page: u8) {
+        let source = (page as u16) << 8;
+        for offset in 0..DMA_LENGTH {
+            // DMAの読み込みはCPUの命令によるものではないので、ウォッチポイントには記録しない
+            let value = self.peek(source + offset);
+            self.ppu.write_oam(OAM_START + offset, value);
+        }
+    }
+
+    // I/Oレジスタへの書き込み
+    fn write_io(&mut self, address: u16, value: u8) {
+        match address {
+            JOYPAD_ADDRESS => self.joypad.write(value),
+            SB_ADDRESS => self.serial.sb = value,
+            0xFF10..=0xFF14 | 0xFF24 | 0xFF25 => self.apu.write(address, value),
+            SC_ADDRESS => {
+                if self.serial.set_sc(value) {
+                    self.request_interrupt(Interrupt::Serial);
+                }
+            }
+            // DIVはどの値を書き込んでも0にリセットされる
+            DIV_ADDRESS => self.timer.reset_div(),
+            TIMA_ADDRESS => self.timer.tima = value,
+            TMA_ADDRESS => self.timer.tma = value,
+            TAC_ADDRESS => self.timer.set_tac(value),
+            LCDC_ADDRESS => self.ppu.set_lcdc(value),
+            STAT_ADDRESS => self.ppu.set_stat(value),
+            SCY_ADDRESS => self.ppu.scy = value,
+            SCX_ADDRESS => self.ppu.scx = value,
+            // LYは読み込み専用
+            LY_ADDRESS => {}
+            LYC_ADDRESS => self.ppu.lyc = value,
+            BOOT_ROM_DISABLE_ADDRESS => {
+                self.memory[address as usize] = value;
+                self.boot_rom = None;
+            }
+            DMA_ADDRESS => {
+                self.memory[address as usize] = value;
+                self.dma_transfer(value);
+            }
+            BGP_ADDRESS => self.ppu.bgp = value,
+            OBP0_ADDRESS => self.ppu.obp0 = value,
+            OBP1_ADDRESS => self.ppu.obp1 = value,
+            WY_ADDRESS => self.ppu.wy = value,
+            WX_ADDRESS => self.ppu.wx = value,
+            // バンク0を選ぶとバンク1になる
+            SVBK_ADDRESS => self.wram_bank = (value & 0x07).max(1),
+            // 書き込めるのはビット0だけ
+            KEY1_ADDRESS => self.speed_switch_armed = value & 0x01 != 0,
+            _ => self.memory[address as usize] = value,
+        }
+    }
+}
+
+impl Memory for MemoryBus {
+    fn read_byte(&self, address: u16) -> u8 {
+        MemoryBus::read_byte(self, address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        MemoryBus::write_byte(self, address, value)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        MemoryBus::pending_interrupts(self)
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch_armed {
+            return false;
+        }
+        self.speed_switch_armed = false;
+        self.double_speed = !self.double_speed;
+        true
+    }
+}
+
+impl Snapshot for MemoryBus {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_bytes(&self.memory);
+        writer.write_bool(self.mbc.is_some());
+        if let Some(mbc) = &self.mbc {
+            mbc.save_state(writer);
+        }
+        writer.write_bool(self.boot_rom.is_some());
+        if let Some(boot_rom) = &self.boot_rom {
+            writer.write_sized_bytes(boot_rom);
+        }
+        writer.write_bytes(&self.wram);
+        writer.write_u8(self.wram_bank);
+        writer.write_bytes(&self.hram);
+        self.timer.save_state(writer);
+        self.ppu.save_state(writer);
+        self.joypad.save_state(writer);
+        self.serial.save_state(writer);
+        self.apu.save_state(writer);
+        writer.write_bool(self.double_speed);
+        writer.write_bool(self.speed_switch_armed);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        reader.read_bytes(&mut self.memory)?;
+        match (reader.read_bool()?, &mut self.mbc) {
+            (true, Some(mbc)) => mbc.load_state(reader)?,
+            (false, None) => {}
+            _ => return Err(SnapshotError::CartridgeMismatch),
+        }
+        self.boot_rom = if reader.read_bool()? {
+            Some(reader.read_sized_bytes()?)
+        } else {
+            None
+        };
+        reader.read_bytes(&mut self.wram)?;
+        self.wram_bank = reader.read_u8()?.clamp(1, 7);
+        reader.read_bytes(&mut self.hram)?;
+        self.timer.load_state(reader)?;
+        self.ppu.load_state(reader)?;
+        self.joypad.load_state(reader)?;
+        self.serial.load_state(reader)?;
+        self.apu.load_state(reader)?;
+        self.double_speed = reader.read_bool()?;
+        self.speed_switch_armed = reader.read_bool()?;
+        Ok(())
     }
 }
 
 impl Default for MemoryBus {
     fn default() -> Self {
         Self {
-            memory: [0; 0xFFFF],
+            memory: [0; 0x10000],
+            mbc: None,
+            battery: false,
+            boot_rom: None,
+            wram: vec![0; WRAM_BANK_SIZE * WRAM_BANK_COUNT],
+            wram_bank: 1,
+            hram: [0; HRAM_SIZE],
+            timer: Timer::default(),
+            ppu: Ppu::default(),
+            joypad: Joypad::default(),
+            serial: Serial::default(),
+            apu: Apu::default(),
+            watchpoints: Watchpoints::default(),
+            double_speed: false,
+            speed_switch_armed: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write_byte() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(0xC000, 0x42);
+        assert_eq!(bus.read_byte(0xC000), 0x42);
+    }
+
+    // エコーRAMはワークRAMと同じ実体を共有する
+    #[test]
+    fn test_echo_ram_mirrors_work_ram() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(0xC000, 0x12);
+        assert_eq!(bus.read_byte(0xE000), 0x12);
+        bus.write_byte(0xFDFF, 0x34);
+        assert_eq!(bus.read_byte(0xDDFF), 0x34);
+    }
+
+    // 使用禁止領域（0xFEA0〜0xFEFF）は書き込みが無視され、0xFFが読める
+    #[test]
+    fn test_prohibited_region() {
+        let mut bus = MemoryBus::default();
+        assert_eq!(bus.read_byte(0xFEA0), 0xFF);
+        bus.write_byte(0xFEA0, 0x12);
+        bus.write_byte(0xFEFF, 0x34);
+        assert_eq!(bus.read_byte(0xFEA0), 0xFF);
+        assert_eq!(bus.read_byte(0xFEFF), 0xFF);
+        assert_eq!(bus.memory[0xFEA0], 0x00);
+    }
+
+    // ブートROMは0xFF50に書き込むまでカートリッジの先頭256バイトを隠す
+    #[test]
+    fn test_boot_rom_overlay() {
+        let mut bus = MemoryBus::default();
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0000] = 0xC3;
+        rom[0x00FF] = 0x11;
+        rom[0x0100] = 0x22;
+        bus.load_rom(&rom);
+        bus.load_boot_rom(&[0x31, 0xFE, 0xFF]);
+
+        assert_eq!(bus.read_byte(0x0000), 0x31);
+        assert_eq!(bus.read_byte(0x0002), 0xFF);
+        assert_eq!(bus.read_byte(0x0100), 0x22);
+
+        bus.write_byte(0xFF50, 0x01);
+        assert_eq!(bus.read_byte(0x0000), 0xC3);
+        assert_eq!(bus.read_byte(0x00FF), 0x11);
+    }
+
+    #[test]
+    fn test_pending_interrupts() {
+        let mut bus = MemoryBus::default();
+        bus.request_interrupt(Interrupt::VBlank);
+        bus.request_interrupt(Interrupt::Timer);
+        assert_eq!(bus.pending_interrupts(), 0x00);
+        bus.write_byte(INTERRUPT_ENABLE_ADDRESS, 0xFF);
+        assert_eq!(bus.pending_interrupts(), 0x05);
+        bus.write_byte(INTERRUPT_FLAG_ADDRESS, 0xE0);
+        assert_eq!(bus.pending_interrupts(), 0x00);
+    }
+
+    // ウォッチポイントのテスト: 監視しているアドレスへの書き込みが記録される
+    #[test]
+    fn test_watch_records_write() {
+        let mut bus = MemoryBus::default();
+        bus.add_watch(0xC000, WatchKind::Write);
+        bus.set_current_pc(0x0150);
+        bus.write_byte(0xC001, 0x11);
+        bus.write_byte(0xC000, 0x42);
+        bus.read_byte(0xC000);
+        assert_eq!(
+            bus.take_watch_events(),
+            vec![WatchEvent {
+                pc: 0x0150,
+                address: 0xC000,
+                value: 0x42,
+                kind: WatchKind::Write,
+            }]
+        );
+    }
+
+    // 0xFF46への書き込みでXX00〜XX9FがOAMにコピーされる
+    #[test]
+    fn test_oam_dma_transfer() {
+        let mut bus = MemoryBus::default();
+        for offset in 0..0xA0u16 {
+            bus.write_byte(0xC100 + offset, offset as u8 ^ 0x5A);
+        }
+        bus.write_byte(0xC1A0, 0xEE);
+        bus.write_byte(0xFF46, 0xC1);
+        for offset in 0..0xA0u16 {
+            assert_eq!(bus.read_byte(0xFE00 + offset), offset as u8 ^ 0x5A);
+        }
+        // 0xFEA0以降にはコピーされない
+        assert_eq!(bus.read_byte(0xFEA0), 0xFF);
+        assert_eq!(bus.read_byte(0xFF46), 0xC1);
+    }
+
+    // DIVへの書き込みは値に関係なく0にリセットされる
+    #[test]
+    fn test_write_div_resets() {
+        let mut bus = MemoryBus::default();
+        bus.tick(0xFF);
+        bus.tick(0xFF);
+        assert_eq!(bus.read_byte(0xFF04), 0x01);
+        bus.write_byte(0xFF04, 0x12);
+        assert_eq!(bus.read_byte(0xFF04), 0x00);
+        bus.write_byte(0xFF04, 0xFF);
+        assert_eq!(bus.read_byte(0xFF04), 0x00);
+    }
+
+    // 通常のI/OレジスタとRAMは書き込んだ値がそのまま読める
+    #[test]
+    fn test_io_and_ram_round_trip() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(0xFF47, 0xE4);
+        assert_eq!(bus.read_byte(0xFF47), 0xE4);
+        bus.write_byte(0xFF80, 0x99);
+        assert_eq!(bus.read_byte(0xFF80), 0x99);
+        bus.write_byte(0x8000, 0x3C);
+        assert_eq!(bus.read_byte(0x8000), 0x3C);
+    }
+
+    #[test]
+    fn test_load_rom() {
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&[0x00, 0xC3, 0x50, 0x01]);
+        assert_eq!(bus.read_byte(0x0000), 0x00);
+        assert_eq!(bus.read_byte(0x0001), 0xC3);
+        assert_eq!(bus.read_byte(0x0002), 0x50);
+        assert_eq!(bus.read_byte(0x0003), 0x01);
+    }
+
+    // 0x8000バイトを超える部分は読み込まない
+    #[test]
+    fn test_load_rom_clamps_to_rom_region() {
+        let mut bus = MemoryBus::default();
+        let mut rom = vec![0x11; 0x8000];
+        rom.extend_from_slice(&[0x22; 0x10]);
+        bus.load_rom(&rom);
+        assert_eq!(bus.read_byte(0x7FFF), 0x11);
+        assert_eq!(bus.read_byte(0x8000), 0x00);
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let mut rom = vec![0; 0x150];
+        rom[0x0134..0x0138].copy_from_slice(b"GAME");
+        rom[0x0147] = 0x01;
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&rom);
+
+        let header = bus.parse_header();
+        assert_eq!(header.title, "GAME");
+        assert_eq!(header.cartridge_type, crate::cartridge::CartridgeType::Mbc1);
+    }
+
+    // MBC1のカートリッジはバンク2に切り替えると0x4000から読める値が変わる
+    #[test]
+    fn test_mbc1_bank_switch() {
+        let mut rom = vec![0; 0x4000 * 4];
+        rom[0x0147] = 0x01; // MBC1
+        rom[0x0148] = 0x01; // 64KiB
+        for bank in 0..4 {
+            rom[bank * 0x4000] = 0xB0 + bank as u8;
+        }
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&rom);
+        assert_eq!(bus.read_byte(0x4000), 0xB1);
+
+        bus.write_byte(0x2000, 0x02);
+        assert_eq!(bus.read_byte(0x4000), 0xB2);
+        // ROMへの書き込みはMBCのレジスタへの操作なので、ROMは変わらない
+        assert_eq!(bus.read_byte(0x2000), 0x00);
+        assert_eq!(bus.read_byte(0x0000), 0xB0);
+    }
+
+    // TIMAがオーバーフローするとTMAを読み込み、IFのタイマー割り込みが立つ
+    #[test]
+    fn test_timer_overflow_requests_interrupt() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(TAC_ADDRESS, 0x05); // 有効、16Tサイクルごと
+        bus.write_byte(TMA_ADDRESS, 0x80);
+        bus.write_byte(TIMA_ADDRESS, 0xFF);
+        assert_eq!(bus.read_byte(TAC_ADDRESS), 0xFD);
+
+        bus.tick(8);
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x04, 0);
+        bus.tick(8);
+        assert_eq!(bus.read_byte(TIMA_ADDRESS), 0x80);
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x04, 0x04);
+    }
+
+    // VRAMとLCDのレジスタはPPUに届く
+    #[test]
+    fn test_vram_and_lcd_registers_reach_ppu() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(LCDC_ADDRESS, 0x10);
+        bus.write_byte(BGP_ADDRESS, 0b11_10_01_00);
+        bus.write_byte(0x8010, 0b1000_0000);
+        bus.write_byte(0x9800, 0x01);
+        bus.ppu.render_scanline(0);
+        assert_eq!(bus.ppu.framebuffer()[0], 1);
+        assert_eq!(bus.read_byte(0x8010), 0b1000_0000);
+        assert_eq!(bus.read_byte(LCDC_ADDRESS), 0x10);
+    }
+
+    // VBlankに入るとIFのVBlank割り込みが立つ
+    #[test]
+    fn test_vblank_requests_interrupt() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(LCDC_ADDRESS, 0x80);
+        for _ in 0..143 * 456 / 4 {
+            bus.tick(4);
+        }
+        assert_eq!(bus.read_byte(LY_ADDRESS), 143);
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x01, 0);
+        for _ in 0..456 / 4 {
+            bus.tick(4);
+        }
+        assert_eq!(bus.read_byte(LY_ADDRESS), 144);
+        assert_eq!(bus.read_byte(STAT_ADDRESS) & 0x03, 1);
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x01, 0x01);
+    }
+
+    // STATでLY=LYCを選択すると、LYがLYCになったときにLCD STAT割り込みが立つ
+    #[test]
+    fn test_stat_lyc_requests_interrupt() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(LCDC_ADDRESS, 0x80);
+        bus.write_byte(LYC_ADDRESS, 3);
+        bus.write_byte(STAT_ADDRESS, 0x40);
+        for _ in 0..3 * 456 / 4 - 1 {
+            bus.tick(4);
+        }
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x02, 0);
+        bus.tick(4);
+        assert_eq!(bus.read_byte(LY_ADDRESS), 3);
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x02, 0x02);
+    }
+
+    // ボタンを選んでAを押すと0xFF00のビット0が0になり、ジョイパッド割り込みが立つ
+    #[test]
+    fn test_joypad_register() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(JOYPAD_ADDRESS, 0x10);
+        assert_eq!(bus.read_byte(JOYPAD_ADDRESS), 0xDF);
+        bus.press_button(Button::A);
+        assert_eq!(bus.read_byte(JOYPAD_ADDRESS), 0xDE);
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x10, 0x10);
+        bus.release_button(Button::A);
+        assert_eq!(bus.read_byte(JOYPAD_ADDRESS), 0xDF);
+    }
+
+    // SBに書いてSCに0x81を書くと、文字がシリアル出力に溜まる
+    #[test]
+    fn test_serial_output() {
+        let mut bus = MemoryBus::default();
+        for &byte in b"Passed" {
+            bus.write_byte(SB_ADDRESS, byte);
+            bus.write_byte(SC_ADDRESS, 0x81);
+        }
+        assert_eq!(bus.serial_output(), "Passed");
+        assert_eq!(bus.read_byte(SC_ADDRESS) & 0x80, 0);
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_ADDRESS) & 0x08, 0x08);
+    }
+
+    fn build_mbc1_rom(cartridge_type: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = cartridge_type;
+        rom[0x0149] = 0x03; // 32KiB
+        rom
+    }
+
+    // バッテリー付きのカートリッジは外部RAMを保存して別のバスに読み込める
+    #[test]
+    fn test_save_and_load_ram() {
+        let path = std::env::temp_dir().join(format!("gameboy_save_{}.sav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let rom = build_mbc1_rom(0x03); // MBC1+RAM+BATTERY
+
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&rom);
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0xA000, 0x12);
+        bus.write_byte(0xBFFF, 0x34);
+        bus.save_ram(path).unwrap();
+
+        let mut loaded = MemoryBus::default();
+        loaded.load_rom(&rom);
+        loaded.load_ram(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        loaded.write_byte(0x0000, 0x0A);
+        assert_eq!(loaded.read_byte(0xA000), 0x12);
+        assert_eq!(loaded.read_byte(0xBFFF), 0x34);
+    }
+
+    // バッテリーが無いカートリッジではファイルを作らない
+    #[test]
+    fn test_save_ram_without_battery() {
+        let path = std::env::temp_dir().join(format!("gameboy_nosave_{}.sav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&build_mbc1_rom(0x02)); // MBC1+RAM
+        bus.save_ram(path).unwrap();
+        assert!(!std::path::Path::new(path).exists());
+    }
+
+    // 0xFFFF（IEレジスタ）も範囲外にならずに読み書きできる
+    #[test]
+    fn test_read_write_last_address() {
+        let mut bus = MemoryBus::default();
+        assert_eq!(bus.read_byte(0xFFFF), 0x00);
+        bus.write_byte(0xFFFF, 0x1F);
+        assert_eq!(bus.read_byte(0xFFFF), 0x1F);
+    }
+
+    // load_atはMBCがなければROMの範囲にも置き、アドレス空間の末尾で折り返す
+    // VRAMやOAMに置いたバイトもread_byteで読める
+    #[test]
+    fn test_load_at() {
+        let mut bus = MemoryBus::default();
+        bus.load_at(0x0100, &[0x00, 0xC3]);
+        assert_eq!(bus.read_byte(0x0101), 0xC3);
+        bus.load_at(0xFFFF, &[0x1F, 0x12]);
+        assert_eq!(bus.read_byte(0xFFFF), 0x1F);
+        assert_eq!(bus.read_byte(0x0000), 0x12);
+        bus.load_at(0x8000, &[0x3C, 0x7E]);
+        assert_eq!(bus.read_byte(0x8000), 0x3C);
+        assert_eq!(bus.read_byte(0x8001), 0x7E);
+        bus.load_at(0xFE00, &[0x10, 0x08]);
+        assert_eq!(bus.read_byte(0xFE00), 0x10);
+        assert_eq!(bus.read_byte(0xFE01), 0x08);
+    }
+
+    // load_atはウォッチポイントに記録しない
+    #[test]
+    fn test_load_at_not_watched() {
+        let mut bus = MemoryBus::default();
+        bus.watchpoints.add(0xC000, WatchKind::Write);
+        bus.load_at(0xC000, &[0x12]);
+        assert_eq!(bus.read_byte(0xC000), 0x12);
+        assert!(bus.watchpoints.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_read_write_word() {
+        let mut bus = MemoryBus::default();
+        bus.write_word(0xC000, 0x1234);
+        assert_eq!(bus.read_byte(0xC000), 0x34);
+        assert_eq!(bus.read_byte(0xC001), 0x12);
+        assert_eq!(bus.read_word(0xC000), 0x1234);
+
+        // 上位バイトのアドレスは0x0000に折り返す
+        bus.memory[0xFFFF] = 0xCD;
+        bus.memory[0x0000] = 0xAB;
+        assert_eq!(bus.read_word(0xFFFF), 0xABCD);
+    }
+
+    // SVBKで0xD000〜0xDFFFのバンクが切り替わり、0xC000〜0xCFFF（バンク0）は変わらない
+    #[test]
+    fn test_wram_bank_switch() {
+        let mut bus = MemoryBus::default();
+        assert_eq!(bus.read_byte(SVBK_ADDRESS), 0xF9);
+        bus.write_byte(0xC000, 0x11);
+        bus.write_byte(0xD000, 0x22);
+
+        bus.write_byte(SVBK_ADDRESS, 0x03);
+        assert_eq!(bus.read_byte(SVBK_ADDRESS), 0xFB);
+        assert_eq!(bus.read_byte(0xD000), 0x00);
+        bus.write_byte(0xD000, 0x33);
+        assert_eq!(bus.read_byte(0xC000), 0x11);
+        // エコーRAMも選んだバンクを映す
+        assert_eq!(bus.read_byte(0xF000), 0x33);
+
+        // バンク0を選ぶとバンク1になる
+        bus.write_byte(SVBK_ADDRESS, 0x00);
+        assert_eq!(bus.read_byte(SVBK_ADDRESS), 0xF9);
+        assert_eq!(bus.read_byte(0xD000), 0x22);
+    }
+}
@@ -0,0 +1,995 @@
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// 画面の大きさ
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+// VRAM（0x8000〜0x9FFF）の先頭アドレス
+pub const VRAM_START: u16 = 0x8000;
+const VRAM_SIZE: usize = 0x2000;
+
+// タイルデータ（0x8000〜0x97FF）に入る384個のタイルを、1行16個ずつ並べたデバッグ用の画像の大きさ
+const TILE_COUNT: usize = 384;
+const TILES_PER_ROW: usize = 16;
+pub const TILE_DATA_WIDTH: usize = TILES_PER_ROW * 8;
+pub const TILE_DATA_HEIGHT: usize = TILE_COUNT / TILES_PER_ROW * 8;
+
+// 背景のタイルマップ（32x32タイル）全体の大きさ
+pub const BG_MAP_SIZE: usize = 256;
+
+// OAM（0xFE00〜0xFE9F）の先頭アドレス
+// 1つのスプライトは4バイト（Y座標、X座標、タイル番号、属性）で、40個まで置ける
+pub const OAM_START: u16 = 0xFE00;
+const OAM_SIZE: usize = 0xA0;
+const SPRITE_COUNT: usize = 40;
+// 1ラインに表示できるスプライトの数
+const SPRITES_PER_LINE: usize = 10;
+
+// スプライトの属性の各ビット
+// ビット4: パレット（0: OBP0、1: OBP1）
+const SPRITE_PALETTE: u8 = 1 << 4;
+// ビット5: 左右反転
+const SPRITE_X_FLIP: u8 = 1 << 5;
+// ビット6: 上下反転
+const SPRITE_Y_FLIP: u8 = 1 << 6;
+// ビット7: 背景の色番号1〜3の後ろに表示する
+const SPRITE_BEHIND_BG: u8 = 1 << 7;
+
+// LCDCの各ビット
+// ビット1: スプライトの表示
+const LCDC_SPRITE_ENABLE: u8 = 1 << 1;
+// ビット2: スプライトの大きさ（0: 8x8、1: 8x16）
+const LCDC_SPRITE_SIZE: u8 = 1 << 2;
+// ビット3: 背景のタイルマップ（0: 0x9800、1: 0x9C00）
+const LCDC_BG_TILE_MAP: u8 = 1 << 3;
+// ビット4: タイルデータ（0: 0x8800（符号付きの番号）、1: 0x8000（符号なしの番号））
+const LCDC_TILE_DATA: u8 = 1 << 4;
+// ビット5: ウィンドウの表示
+const LCDC_WINDOW_ENABLE: u8 = 1 << 5;
+// ビット6: ウィンドウのタイルマップ（0: 0x9800、1: 0x9C00）
+const LCDC_WINDOW_TILE_MAP: u8 = 1 << 6;
+// ビット7: LCDの表示
+const LCDC_LCD_ENABLE: u8 = 1 << 7;
+
+// STATの割り込み選択ビット
+// ビット3: HBlank、ビット4: VBlank、ビット5: OAMサーチ、ビット6: LY=LYC
+const STAT_HBLANK_SOURCE: u8 = 1 << 3;
+const STAT_VBLANK_SOURCE: u8 = 1 << 4;
+const STAT_OAM_SOURCE: u8 = 1 << 5;
+const STAT_LYC_SOURCE: u8 = 1 << 6;
+
+// 1ラインにかかるTサイクル数
+const CYCLES_PER_LINE: u16 = 456;
+// OAMサーチ（モード2）とピクセル転送（モード3）にかかるTサイクル数
+const OAM_SEARCH_CYCLES: u16 = 80;
+const PIXEL_TRANSFER_CYCLES: u16 = 172;
+// VBlankを含めた1フレームのライン数（0〜153）
+const LINES_PER_FRAME: u8 = 154;
+
+// 濃さ0〜3を表示するRGBの色（初代ゲームボーイの緑がかった画面）
+pub const DMG_GREEN_PALETTE: [(u8, u8, u8); 4] = [
+    (0x9B, 0xBC, 0x0F),
+    (0x8B, 0xAC, 0x0F),
+    (0x30, 0x62, 0x30),
+    (0x0F, 0x38, 0x0F),
+];
+
+// STATの下位2ビットに入るPPUのモード
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PpuMode {
+    HBlank = 0,
+    VBlank = 1,
+    OamSearch = 2,
+    PixelTransfer = 3,
+}
+
+// PPU（画像処理ユニット）
+// VRAMとLCD関連のレジスタを持ち、1ラインずつフレームバッファに描画する
+pub struct Ppu {
+    vram: [u8; VRAM_SIZE],
+    oam: [u8; OAM_SIZE],
+    // LCDC（0xFF40）: LCD制御
+    lcdc: u8,
+    // SCY（0xFF42）、SCX（0xFF43）: 背景のスクロール位置
+    pub scy: u8,
+    pub scx: u8,
+    // BGP（0xFF47）: 背景とウィンドウのパレット
+    pub bgp: u8,
+    // OBP0（0xFF48）、OBP1（0xFF49）: スプライトのパレット
+    pub obp0: u8,
+    pub obp1: u8,
+    // WY（0xFF4A）、WX（0xFF4B）: ウィンドウの位置（WXは7を引いた値が画面のX座標）
+    pub wy: u8,
+    pub wx: u8,
+    // ウィンドウの何ライン目まで描画したか
+    // ウィンドウが表示されたラインでだけ進むので、LYとは一致しない
+    window_line: u8,
+    // LY（0xFF44）: 現在のライン
+    ly: u8,
+    // LYC（0xFF45）: LYと比較する値
+    pub lyc: u8,
+    // STAT（0xFF41）のうち書き込み可能な割り込み選択ビット（ビット3〜6）
+    stat_select: u8,
+    // 選択された条件のどれかが成り立っているか（STAT割り込みの信号線）
+    // 信号線が0から1に変わったときにだけ割り込みが要求される
+    stat_line: bool,
+    // まだバスに渡していないSTAT割り込みの要求
+    stat_interrupt: bool,
+    mode: PpuMode,
+    // 現在のラインで経過したTサイクル数
+    line_cycles: u16,
+    // 各ピクセルの濃さ（パレットを通したあとの0〜3）
+    framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+}
+
+impl Ppu {
+    pub fn read_vram(&self, address: u16) -> u8 {
+        self.vram[(address - VRAM_START) as usize]
+    }
+
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        self.vram[(address - VRAM_START) as usize] = value;
+    }
+
+    pub fn read_oam(&self, address: u16) -> u8 {
+        self.oam[(address - OAM_START) as usize]
+    }
+
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        self.oam[(address - OAM_START) as usize] = value;
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    // フレームバッファの各ピクセルの濃さをパレットのRGBに変換し、RGBの順に並べて返す
+    // パレットを指定しなければDMG_GREEN_PALETTEを使う
+    pub fn render_rgb(&self, palette: Option<&[(u8, u8, u8); 4]>) -> Vec<u8> {
+        let palette = palette.unwrap_or(&DMG_GREEN_PALETTE);
+        self.framebuffer
+            .iter()
+            .flat_map(|&shade| {
+                let (r, g, b) = palette[(shade & 0x03) as usize];
+                [r, g, b]
+            })
+            .collect()
+    }
+
+    // VRAMのタイルデータをすべて色番号（パレットを通す前の0〜3）の画像にする
+    // 画像の大きさはTILE_DATA_WIDTH x TILE_DATA_HEIGHTで、タイル番号の順に左上から並べる
+    pub fn render_tile_data(&self) -> Vec<u8> {
+        let mut image = vec![0; TILE_DATA_WIDTH * TILE_DATA_HEIGHT];
+        for tile in 0..TILE_COUNT {
+            let tile_address = VRAM_START + tile as u16 * 16;
+            let left = tile % TILES_PER_ROW * 8;
+            let top = tile / TILES_PER_ROW * 8;
+            for y in 0..8 {
+                for x in 0..8 {
+                    image[(top + y) * TILE_DATA_WIDTH + left + x] =
+                        self.tile_pixel(tile_address, x as u8, y as u8);
+                }
+            }
+        }
+        image
+    }
+
+    // 背景のタイルマップ全体（BG_MAP_SIZE x BG_MAP_SIZE）を、スクロールを無視して濃さの画像にする
+    // map_selectがtrueなら0x9C00、falseなら0x9800のタイルマップを使う
+    // タイルデータのアドレスの指定方法とパレットは、今のLCDCとBGPに従う
+    pub fn render_bg_map(&self, map_select: bool) -> Vec<u8> {
+        let map_base = tile_map_base(map_select);
+        let mut image = vec![0; BG_MAP_SIZE * BG_MAP_SIZE];
+        for (index, pixel) in image.iter_mut().enumerate() {
+            let x = (index % BG_MAP_SIZE) as u8;
+            let y = (index / BG_MAP_SIZE) as u8;
+            *pixel = apply_palette(self.bgp, self.map_pixel(map_base, x, y));
+        }
+        image
+    }
+
+    pub fn lcdc(&self) -> u8 {
+        self.lcdc
+    }
+
+    // LCDを無効にするとLYとモードが0になり、画面は白くなる
+    // 再び有効にしたときはライン0のOAMサーチから始める
+    pub fn set_lcdc(&mut self, value: u8) {
+        let was_enabled = self.lcd_enabled();
+        self.lcdc = value;
+        if was_enabled && !self.lcd_enabled() {
+            self.ly = 0;
+            self.line_cycles = 0;
+            self.window_line = 0;
+            self.mode = PpuMode::HBlank;
+            self.stat_line = false;
+            self.framebuffer.fill(0);
+        } else if !was_enabled && self.lcd_enabled() {
+            self.ly = 0;
+            self.line_cycles = 0;
+            self.window_line = 0;
+            self.mode = PpuMode::OamSearch;
+        }
+    }
+
+    fn lcd_enabled(&self) -> bool {
+        self.lcdc & LCDC_LCD_ENABLE != 0
+    }
+
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    pub fn mode(&self) -> PpuMode {
+        self.mode
+    }
+
+    // ビット7は常に1、ビット2はLY=LYCの一致フラグ、ビット0〜1は現在のモード
+    pub fn stat(&self) -> u8 {
+        let coincidence = if self.ly == self.lyc { 0x04 } else { 0x00 };
+        0x80 | self.stat_select | coincidence | self.mode as u8
+    }
+
+    // 一致フラグとモードは読み込み専用
+    pub fn set_stat(&mut self, value: u8) {
+        self.stat_select = value & 0x78;
+    }
+
+    // 命令の実行で消費したTサイクル数だけ進める
+    // VBlankに入ってVBlank割り込みを要求する場合はtrueを返す
+    pub fn tick(&mut self, cycles: u8) -> bool {
+        // LCDが無効な間はLYもモードも進まない
+        if !self.lcd_enabled() {
+            return false;
+        }
+        let mut vblank = false;
+        for _ in 0..cycles {
+            self.line_cycles += 1;
+            if self.line_cycles == CYCLES_PER_LINE {
+                self.line_cycles = 0;
+                self.ly = (self.ly + 1) % LINES_PER_FRAME;
+                if self.ly == SCREEN_HEIGHT as u8 {
+                    vblank = true;
+                }
+            }
+            self.update_mode();
+            self.update_stat_line();
+        }
+        vblank
+    }
+
+    // STAT割り込みの信号線を更新し、立ち上がったら割り込みを要求する
+    // 複数の条件が続けて成り立っても、信号線が1のままなら割り込みは1回だけになる
+    fn update_stat_line(&mut self) {
+        let line = (self.stat_select & STAT_HBLANK_SOURCE != 0 && self.mode == PpuMode::HBlank)
+            || (self.stat_select & STAT_VBLANK_SOURCE != 0 && self.mode == PpuMode::VBlank)
+            || (self.stat_select & STAT_OAM_SOURCE != 0 && self.mode == PpuMode::OamSearch)
+            || (self.stat_select & STAT_LYC_SOURCE != 0 && self.ly == self.lyc);
+        if line && !self.stat_line {
+            self.stat_interrupt = true;
+        }
+        self.stat_line = line;
+    }
+
+    // STAT割り込みが要求されていればtrueを返し、要求を取り下げる
+    pub fn take_stat_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.stat_interrupt)
+    }
+
+    fn update_mode(&mut self) {
+        let mode = if self.ly >= SCREEN_HEIGHT as u8 {
+            PpuMode::VBlank
+        } else if self.line_cycles < OAM_SEARCH_CYCLES {
+            PpuMode::OamSearch
+        } else if self.line_cycles < OAM_SEARCH_CYCLES + PIXEL_TRANSFER_CYCLES {
+            PpuMode::PixelTransfer
+        } else {
+            PpuMode::HBlank
+        };
+        // ピクセル転送が終わったところでそのラインを描画する
+        if self.mode == PpuMode::PixelTransfer && mode == PpuMode::HBlank {
+            self.render_scanline(self.ly);
+        }
+        self.mode = mode;
+    }
+
+    // 指定したラインの背景、ウィンドウ、スプライトを描画する
+    pub fn render_scanline(&mut self, ly: u8) {
+        if ly == 0 {
+            self.window_line = 0;
+        }
+        let mut background = self.render_background_line(ly);
+        self.render_window_line(ly, &mut background);
+        let row = ly as usize * SCREEN_WIDTH;
+        for (pixel, &color) in self.framebuffer[row..row + SCREEN_WIDTH]
+            .iter_mut()
+            .zip(background.iter())
+        {
+            *pixel = apply_palette(self.bgp, color);
+        }
+        if self.lcdc & LCDC_SPRITE_ENABLE != 0 {
+            self.render_sprites(ly, &background);
+        }
+    }
+
+    // 背景の1ライン分の色番号
+    fn render_background_line(&self, ly: u8) -> [u8; SCREEN_WIDTH] {
+        let map_base = tile_map_base(self.lcdc & LCDC_BG_TILE_MAP != 0);
+        // スクロール後の座標は256x256の背景の中で折り返す
+        let y = ly.wrapping_add(self.scy);
+        let mut line = [0; SCREEN_WIDTH];
+        for (screen_x, pixel) in line.iter_mut().enumerate() {
+            let x = (screen_x as u8).wrapping_add(self.scx);
+            *pixel = self.map_pixel(map_base, x, y);
+        }
+        line
+    }
+
+    // ウィンドウが表示されていれば、背景の上に重ねる
+    fn render_window_line(&mut self, ly: u8, line: &mut [u8; SCREEN_WIDTH]) {
+        if self.lcdc & LCDC_WINDOW_ENABLE == 0
+            || ly < self.wy
+            || self.wx as usize >= SCREEN_WIDTH + 7
+        {
+            return;
+        }
+        let map_base = tile_map_base(self.lcdc & LCDC_WINDOW_TILE_MAP != 0);
+        let left = self.wx as i16 - 7;
+        for (screen_x, pixel) in line.iter_mut().enumerate() {
+            let x = screen_x as i16 - left;
+            if x >= 0 {
+                *pixel = self.map_pixel(map_base, x as u8, self.window_line);
+            }
+        }
+        self.window_line += 1;
+    }
+
+    // タイルマップ上の座標(x, y)の色番号
+    fn map_pixel(&self, map_base: u16, x: u8, y: u8) -> u8 {
+        // タイルマップは32x32タイル
+        let map_address = map_base + (y as u16 / 8) * 32 + (x as u16 / 8);
+        let tile_number = self.read_vram(map_address);
+        self.tile_pixel(self.bg_tile_address(tile_number), x % 8, y % 8)
+    }
+
+    // 背景のタイル番号からタイルデータのアドレスを求める
+    fn bg_tile_address(&self, tile_number: u8) -> u16 {
+        if self.lcdc & LCDC_TILE_DATA != 0 {
+            VRAM_START + tile_number as u16 * 16
+        } else {
+            // 0x9000を基準に-128〜127の番号で指定する
+            0x9000u16.wrapping_add_signed(tile_number as i8 as i16 * 16)
+        }
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.lcdc & LCDC_SPRITE_SIZE != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    // 背景の上にスプライトを重ねる
+    fn render_sprites(&mut self, ly: u8, background: &[u8; SCREEN_WIDTH]) {
+        let height = self.sprite_height();
+        // OAMの順に、このラインにかかるスプライトを10個まで選ぶ
+        let mut sprites: Vec<usize> = (0..SPRITE_COUNT)
+            .filter(|&index| {
+                let top = self.oam[index * 4] as i16 - 16;
+                (top..top + height as i16).contains(&(ly as i16))
+            })
+            .take(SPRITES_PER_LINE)
+            .collect();
+        // X座標が小さいもの、同じならOAMで先にあるものが手前になるので、奥のものから描く
+        sprites.sort_by_key(|&index| self.oam[index * 4 + 1]);
+        let row = ly as usize * SCREEN_WIDTH;
+        for &index in sprites.iter().rev() {
+            let entry = &self.oam[index * 4..index * 4 + 4];
+            let top = entry[0] as i16 - 16;
+            let left = entry[1] as i16 - 8;
+            let attributes = entry[3];
+            let mut tile_y = (ly as i16 - top) as u8;
+            if attributes & SPRITE_Y_FLIP != 0 {
+                tile_y = height - 1 - tile_y;
+            }
+            // 8x16では番号の最下位ビットを無視し、連続する2つのタイルを上下に並べる
+            let tile_number = if height == 16 {
+                entry[2] & 0xFE
+            } else {
+                entry[2]
+            };
+            // スプライトのタイルデータは常に0x8000から
+            let tile_address = VRAM_START + tile_number as u16 * 16 + (tile_y as u16 / 8) * 16;
+            let palette = if attributes & SPRITE_PALETTE != 0 {
+                self.obp1
+            } else {
+                self.obp0
+            };
+            for tile_x in 0..8u8 {
+                let screen_x = left + tile_x as i16;
+                if !(0..SCREEN_WIDTH as i16).contains(&screen_x) {
+                    continue;
+                }
+                let screen_x = screen_x as usize;
+                let x = if attributes & SPRITE_X_FLIP != 0 {
+                    7 - tile_x
+                } else {
+                    tile_x
+                };
+                let color = self.tile_pixel(tile_address, x, tile_y % 8);
+                // 色番号0は透明
+                if color == 0 {
+                    continue;
+                }
+                // 背景優先のスプライトは背景の色番号0の上にだけ表示する
+                if attributes & SPRITE_BEHIND_BG != 0 && background[screen_x] != 0 {
+                    continue;
+                }
+                self.framebuffer[row + screen_x] = apply_palette(palette, color);
+            }
+        }
+    }
+
+    // タイル内の1ピクセルの色番号
+    // タイルは1ラインあたり2バイトで、1バイト目が色番号の下位ビット、2バイト目が上位ビット
+    fn tile_pixel(&self, tile_address: u16, x: u8, y: u8) -> u8 {
+        let line_address = tile_address + y as u16 * 2;
+        let low = self.read_vram(line_address);
+        let high = self.read_vram(line_address + 1);
+        // 左端のピクセルが最上位ビット
+        let bit = 7 - x;
+        (((high >> bit) & 1) << 1) | ((low >> bit) & 1)
+    }
+}
+
+// パレットで色番号を濃さ（0: 白〜3: 黒）に変換する
+// パレットは色番号0〜3の濃さを下位ビットから2ビットずつ並べたもの
+pub fn apply_palette(palette: u8, color_index: u8) -> u8 {
+    (palette >> (color_index * 2)) & 0x03
+}
+
+// LCDCのビットで選ばれるタイルマップの先頭アドレス
+fn tile_map_base(high: bool) -> u16 {
+    if high { 0x9C00 } else { 0x9800 }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self {
+            vram: [0; VRAM_SIZE],
+            oam: [0; OAM_SIZE],
+            lcdc: 0,
+            scy: 0,
+            scx: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            window_line: 0,
+            ly: 0,
+            lyc: 0,
+            stat_select: 0,
+            stat_line: false,
+            stat_interrupt: false,
+            mode: PpuMode::HBlank,
+            line_cycles: 0,
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+}
+
+impl Snapshot for Ppu {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_bytes(&self.vram);
+        writer.write_bytes(&self.oam);
+        for register in [
+            self.lcdc,
+            self.scy,
+            self.scx,
+            self.bgp,
+            self.obp0,
+            self.obp1,
+            self.wy,
+            self.wx,
+            self.window_line,
+            self.ly,
+            self.lyc,
+            self.stat_select,
+            self.mode as u8,
+        ] {
+            writer.write_u8(register);
+        }
+        writer.write_bool(self.stat_line);
+        writer.write_bool(self.stat_interrupt);
+        writer.write_u16(self.line_cycles);
+        writer.write_bytes(&self.framebuffer);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        reader.read_bytes(&mut self.vram)?;
+        reader.read_bytes(&mut self.oam)?;
+        self.lcdc = reader.read_u8()?;
+        self.scy = reader.read_u8()?;
+        self.scx = reader.read_u8()?;
+        self.bgp = reader.read_u8()?;
+        self.obp0 = reader.read_u8()?;
+        self.obp1 = reader.read_u8()?;
+        self.wy = reader.read_u8()?;
+        self.wx = reader.read_u8()?;
+        self.window_line = reader.read_u8()?;
+        self.ly = reader.read_u8()?;
+        self.lyc = reader.read_u8()?;
+        self.stat_select = reader.read_u8()?;
+        self.mode = match reader.read_u8()? & 0x03 {
+            0 => PpuMode::HBlank,
+            1 => PpuMode::VBlank,
+            2 => PpuMode::OamSearch,
+            _ => PpuMode::PixelTransfer,
+        };
+        self.stat_line = reader.read_bool()?;
+        self.stat_interrupt = reader.read_bool()?;
+        self.line_cycles = reader.read_u16()?;
+        reader.read_bytes(&mut self.framebuffer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1ライン目が色番号 0,1,2,3,0,1,2,3 になるタイルを書き込む
+    fn write_striped_tile(ppu: &mut Ppu, tile_address: u16) {
+        for line in 0..8 {
+            ppu.write_vram(tile_address + line * 2, 0b0101_0101);
+            ppu.write_vram(tile_address + line * 2 + 1, 0b0011_0011);
+        }
+    }
+
+    #[test]
+    fn test_render_rgb() {
+        let mut ppu = Ppu::default();
+        let gray = [
+            (0xFF, 0xFF, 0xFF),
+            (0xAA, 0xAA, 0xAA),
+            (0x55, 0x55, 0x55),
+            (0, 0, 0),
+        ];
+        let rgb = ppu.render_rgb(Some(&gray));
+        assert_eq!(rgb.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        assert!(rgb.iter().all(|&channel| channel == 0xFF));
+
+        ppu.framebuffer[1] = 3;
+        let rgb = ppu.render_rgb(None);
+        assert_eq!(rgb[0..3], [0x9B, 0xBC, 0x0F]);
+        assert_eq!(rgb[3..6], [0x0F, 0x38, 0x0F]);
+    }
+
+    #[test]
+    fn test_render_tile_data() {
+        let mut ppu = Ppu::default();
+        // タイル17番（2行目の2番目）
+        write_striped_tile(&mut ppu, 0x8110);
+        let image = ppu.render_tile_data();
+        assert_eq!(image.len(), 128 * 192);
+        for y in 8..16 {
+            let row = &image[y * TILE_DATA_WIDTH..(y + 1) * TILE_DATA_WIDTH];
+            assert_eq!(row[8..16], [0, 1, 2, 3, 0, 1, 2, 3]);
+            assert!(row[..8].iter().all(|&pixel| pixel == 0));
+            assert!(row[16..].iter().all(|&pixel| pixel == 0));
+        }
+        assert!(image[..8 * TILE_DATA_WIDTH].iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn test_render_bg_map() {
+        let mut ppu = Ppu::default();
+        ppu.bgp = 0b11_10_01_00;
+        // 符号付きの指定方法（LCDCのビット4が0）でタイル0xFFは0x8FF0
+        write_striped_tile(&mut ppu, 0x8FF0);
+        for address in 0x9C00..0xA000 {
+            ppu.write_vram(address, 0xFF);
+        }
+        let image = ppu.render_bg_map(true);
+        assert_eq!(image.len(), 256 * 256);
+        let last_row = 255 * BG_MAP_SIZE;
+        assert_eq!(image[0..4], [0, 1, 2, 3]);
+        assert_eq!(image[252..256], [0, 1, 2, 3]);
+        assert_eq!(image[last_row..last_row + 4], [0, 1, 2, 3]);
+        assert_eq!(image[last_row + 252..], [0, 1, 2, 3]);
+
+        // 0x9800のタイルマップはタイル0（空）のまま
+        assert!(ppu.render_bg_map(false).iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn test_render_background_unsigned_tile_data() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_TILE_DATA;
+        ppu.bgp = 0b11_10_01_00;
+        // タイル1を0x8010に置き、マップの左上に配置する
+        write_striped_tile(&mut ppu, 0x8010);
+        ppu.write_vram(0x9800, 0x01);
+
+        ppu.render_scanline(0);
+        assert_eq!(&ppu.framebuffer()[0..8], &[0, 1, 2, 3, 0, 1, 2, 3]);
+        // 隣のタイル（タイル0）は空
+        assert_eq!(ppu.framebuffer()[8], 0);
+    }
+
+    // LCDCのビット4が0のときは0x9000を基準にした符号付きの番号になる
+    #[test]
+    fn test_render_background_signed_tile_data() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_BG_TILE_MAP;
+        ppu.bgp = 0b11_10_01_00;
+        // タイル-1（0xFF）は0x8FF0
+        write_striped_tile(&mut ppu, 0x8FF0);
+        ppu.write_vram(0x9C00, 0xFF);
+
+        ppu.render_scanline(3);
+        let row = 3 * SCREEN_WIDTH;
+        assert_eq!(&ppu.framebuffer()[row..row + 4], &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_apply_palette() {
+        let identity = 0b11_10_01_00;
+        for color in 0..4 {
+            assert_eq!(apply_palette(identity, color), color);
+        }
+        let inverted = 0b00_01_10_11;
+        assert_eq!(apply_palette(inverted, 0), 3);
+        assert_eq!(apply_palette(inverted, 1), 2);
+        assert_eq!(apply_palette(inverted, 2), 1);
+        assert_eq!(apply_palette(inverted, 3), 0);
+    }
+
+    // 反転したBGPでは背景の濃さも反転する
+    #[test]
+    fn test_render_background_with_inverted_bgp() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_TILE_DATA;
+        ppu.bgp = 0b00_01_10_11;
+        write_striped_tile(&mut ppu, 0x8010);
+        ppu.write_vram(0x9800, 0x01);
+
+        ppu.render_scanline(0);
+        assert_eq!(&ppu.framebuffer()[0..8], &[3, 2, 1, 0, 3, 2, 1, 0]);
+        // 空のタイル（色番号0）も3になる
+        assert_eq!(ppu.framebuffer()[8], 3);
+    }
+
+    fn write_sprite(ppu: &mut Ppu, index: u16, y: u8, x: u8, tile: u8, attributes: u8) {
+        let address = OAM_START + index * 4;
+        ppu.write_oam(address, y);
+        ppu.write_oam(address + 1, x);
+        ppu.write_oam(address + 2, tile);
+        ppu.write_oam(address + 3, attributes);
+    }
+
+    // スプライトの色番号0以外の部分は背景の上に表示される
+    #[test]
+    fn test_render_sprite_over_background() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_TILE_DATA | LCDC_SPRITE_ENABLE;
+        ppu.bgp = 0b11_10_01_00;
+        ppu.obp0 = 0b11_10_01_00;
+        // 背景はタイル1（全面が色番号1）
+        for line in 0..8 {
+            ppu.write_vram(0x8010 + line * 2, 0xFF);
+        }
+        ppu.write_vram(0x9800, 0x01);
+        // スプライトはタイル2（0,1,2,3,0,1,2,3）を画面の(0, 0)に置く
+        write_striped_tile(&mut ppu, 0x8020);
+        write_sprite(&mut ppu, 0, 16, 8, 0x02, 0x00);
+
+        ppu.render_scanline(0);
+        assert_eq!(&ppu.framebuffer()[0..8], &[1, 1, 2, 3, 1, 1, 2, 3]);
+    }
+
+    // 左右反転とOBP1の選択
+    #[test]
+    fn test_render_sprite_flip_and_palette() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_SPRITE_ENABLE;
+        ppu.bgp = 0b11_10_01_00;
+        ppu.obp0 = 0b11_10_01_00;
+        // OBP1は色番号1〜3をすべて3にする
+        ppu.obp1 = 0b11_11_11_00;
+        write_striped_tile(&mut ppu, 0x8010);
+        write_sprite(&mut ppu, 0, 16, 8, 0x01, SPRITE_X_FLIP);
+        write_sprite(&mut ppu, 1, 16, 16, 0x01, SPRITE_PALETTE);
+
+        ppu.render_scanline(0);
+        assert_eq!(&ppu.framebuffer()[0..8], &[3, 2, 1, 0, 3, 2, 1, 0]);
+        assert_eq!(&ppu.framebuffer()[8..16], &[0, 3, 3, 3, 0, 3, 3, 3]);
+    }
+
+    // 背景優先のスプライトは背景の色番号0の部分にだけ表示される
+    #[test]
+    fn test_render_sprite_behind_background() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_TILE_DATA | LCDC_SPRITE_ENABLE;
+        ppu.bgp = 0b11_10_01_00;
+        ppu.obp0 = 0b11_10_01_00;
+        // 背景の左半分だけ色番号3
+        for line in 0..8 {
+            ppu.write_vram(0x8010 + line * 2, 0xF0);
+            ppu.write_vram(0x8010 + line * 2 + 1, 0xF0);
+        }
+        ppu.write_vram(0x9800, 0x01);
+        for line in 0..8 {
+            ppu.write_vram(0x8020 + line * 2, 0xFF);
+        }
+        write_sprite(&mut ppu, 0, 16, 8, 0x02, SPRITE_BEHIND_BG);
+
+        ppu.render_scanline(0);
+        assert_eq!(&ppu.framebuffer()[0..8], &[3, 3, 3, 3, 1, 1, 1, 1]);
+    }
+
+    // 8x16のスプライトは番号の最下位ビットを無視し、上半分が偶数、下半分が次のタイル
+    #[test]
+    fn test_render_tall_sprite() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_SPRITE_ENABLE | LCDC_SPRITE_SIZE;
+        ppu.obp0 = 0b11_10_01_00;
+        // タイル2は全面が色番号1、タイル3は全面が色番号2
+        for line in 0..8 {
+            ppu.write_vram(0x8020 + line * 2, 0xFF);
+            ppu.write_vram(0x8030 + line * 2 + 1, 0xFF);
+        }
+        write_sprite(&mut ppu, 0, 16, 8, 0x03, 0x00);
+        write_sprite(&mut ppu, 1, 16, 16, 0x03, SPRITE_Y_FLIP);
+
+        let mut pixels = Vec::new();
+        for ly in [0, 7, 8, 15, 16] {
+            ppu.render_scanline(ly);
+            let row = ly as usize * SCREEN_WIDTH;
+            pixels.push((ppu.framebuffer()[row], ppu.framebuffer()[row + 8]));
+        }
+        // 上下反転すると、上半分に下のタイルが来る
+        assert_eq!(pixels, vec![(1, 2), (1, 2), (2, 1), (2, 1), (0, 0)]);
+    }
+
+    // 1ラインに表示できるスプライトは10個まで
+    #[test]
+    fn test_render_sprite_limit_per_line() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_SPRITE_ENABLE;
+        ppu.bgp = 0b11_10_01_00;
+        ppu.obp0 = 0b11_10_01_00;
+        for line in 0..8 {
+            ppu.write_vram(0x8010 + line * 2, 0xFF);
+        }
+        for index in 0..11 {
+            write_sprite(&mut ppu, index, 16, 8 + index as u8 * 8, 0x01, 0x00);
+        }
+
+        ppu.render_scanline(0);
+        assert_eq!(ppu.framebuffer()[9 * 8], 1);
+        assert_eq!(ppu.framebuffer()[10 * 8], 0);
+    }
+
+    // WY=0、WX=7のウィンドウはライン全体を覆い、ウィンドウ用のタイルマップを使う
+    #[test]
+    fn test_render_window_covers_line() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_TILE_DATA | LCDC_WINDOW_ENABLE | LCDC_WINDOW_TILE_MAP;
+        ppu.bgp = 0b11_10_01_00;
+        ppu.wy = 0;
+        ppu.wx = 7;
+        // 背景（0x9800）はタイル0で空、ウィンドウ（0x9C00）はすべてタイル1
+        for line in 0..8 {
+            ppu.write_vram(0x8010 + line * 2, 0xFF);
+            ppu.write_vram(0x8010 + line * 2 + 1, 0xFF);
+        }
+        for offset in 0..32 {
+            ppu.write_vram(0x9C00 + offset, 0x01);
+        }
+        ppu.scx = 3;
+
+        ppu.render_scanline(0);
+        assert!(
+            ppu.framebuffer()[..SCREEN_WIDTH]
+                .iter()
+                .all(|&pixel| pixel == 3)
+        );
+    }
+
+    // ウィンドウの開始位置より左は背景のまま
+    #[test]
+    fn test_render_window_position() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_TILE_DATA | LCDC_WINDOW_ENABLE | LCDC_WINDOW_TILE_MAP;
+        ppu.bgp = 0b11_10_01_00;
+        ppu.wy = 2;
+        ppu.wx = 7 + 80;
+        for line in 0..8 {
+            ppu.write_vram(0x8010 + line * 2, 0xFF);
+        }
+        for offset in 0..32 {
+            ppu.write_vram(0x9C00 + offset, 0x01);
+        }
+
+        ppu.render_scanline(1);
+        assert_eq!(ppu.framebuffer()[SCREEN_WIDTH + 100], 0);
+        ppu.render_scanline(2);
+        let row = 2 * SCREEN_WIDTH;
+        assert_eq!(ppu.framebuffer()[row + 79], 0);
+        assert_eq!(ppu.framebuffer()[row + 80], 1);
+    }
+
+    // ウィンドウのライン数はウィンドウが表示されたラインでだけ進む
+    #[test]
+    fn test_window_line_counter() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_TILE_DATA | LCDC_WINDOW_ENABLE;
+        ppu.bgp = 0b11_10_01_00;
+        ppu.wx = 7;
+        // タイル1は1ライン目が色番号1、2ライン目が色番号2、3ライン目が色番号3
+        ppu.write_vram(0x8010, 0xFF);
+        ppu.write_vram(0x8013, 0xFF);
+        ppu.write_vram(0x8014, 0xFF);
+        ppu.write_vram(0x8015, 0xFF);
+        ppu.write_vram(0x9800, 0x01);
+
+        ppu.render_scanline(0);
+        assert_eq!(ppu.framebuffer()[0], 1);
+        // ライン1ではウィンドウを隠す
+        ppu.wx = 200;
+        ppu.render_scanline(1);
+        // ライン2ではウィンドウの3ライン目ではなく、2ライン目が描かれる
+        ppu.wx = 7;
+        ppu.render_scanline(2);
+        assert_eq!(ppu.framebuffer()[2 * SCREEN_WIDTH], 2);
+    }
+
+    // 456Tサイクルで次のラインに進み、モードは2→3→0と変わる
+    #[test]
+    fn test_tick_advances_ly_and_mode() {
+        let mut ppu = Ppu::default();
+        ppu.set_lcdc(LCDC_LCD_ENABLE);
+        assert_eq!(ppu.mode(), PpuMode::OamSearch);
+        ppu.tick(80);
+        assert_eq!(ppu.mode(), PpuMode::PixelTransfer);
+        ppu.tick(172);
+        assert_eq!(ppu.mode(), PpuMode::HBlank);
+        assert_eq!(ppu.ly(), 0);
+        ppu.tick(200);
+        ppu.tick(4);
+        assert_eq!(ppu.ly(), 1);
+        assert_eq!(ppu.mode(), PpuMode::OamSearch);
+    }
+
+    // ライン144でVBlankに入り、ライン153のあとは0に戻る
+    #[test]
+    fn test_tick_vblank_and_wrap() {
+        let mut ppu = Ppu::default();
+        ppu.set_lcdc(LCDC_LCD_ENABLE);
+        let mut vblank_count = 0;
+        for _ in 0..144 * 456 / 4 {
+            if ppu.tick(4) {
+                vblank_count += 1;
+            }
+        }
+        assert_eq!(ppu.ly(), 144);
+        assert_eq!(ppu.mode(), PpuMode::VBlank);
+        assert_eq!(vblank_count, 1);
+
+        for _ in 0..10 * 456 / 4 {
+            assert!(!ppu.tick(4));
+        }
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.mode(), PpuMode::OamSearch);
+    }
+
+    // LY=LYCのときSTATのビット2が立つ
+    #[test]
+    fn test_stat_coincidence() {
+        let mut ppu = Ppu::default();
+        ppu.set_lcdc(LCDC_LCD_ENABLE);
+        ppu.lyc = 1;
+        ppu.set_stat(0xFF);
+        assert_eq!(ppu.stat(), 0xFA);
+        for _ in 0..456 / 4 {
+            ppu.tick(4);
+        }
+        assert_eq!(ppu.stat(), 0xFE);
+    }
+
+    // LY=LYCだけを選択すると、LYがLYCになったときに1回だけSTAT割り込みが要求される
+    #[test]
+    fn test_stat_interrupt_on_lyc() {
+        let mut ppu = Ppu::default();
+        ppu.set_lcdc(LCDC_LCD_ENABLE);
+        ppu.lyc = 2;
+        ppu.set_stat(STAT_LYC_SOURCE);
+        let mut requested_lines = Vec::new();
+        for _ in 0..(456 * 4) / 4 {
+            ppu.tick(4);
+            if ppu.take_stat_interrupt() {
+                requested_lines.push(ppu.ly());
+            }
+        }
+        assert_eq!(requested_lines, vec![2]);
+    }
+
+    // 信号線が1のまま別の条件が成り立っても割り込みは増えない（STATブロッキング）
+    #[test]
+    fn test_stat_interrupt_blocking() {
+        let mut ppu = Ppu::default();
+        ppu.set_lcdc(LCDC_LCD_ENABLE);
+        ppu.lyc = 0;
+        ppu.set_stat(STAT_LYC_SOURCE | STAT_HBLANK_SOURCE);
+        ppu.tick(4);
+        assert!(ppu.take_stat_interrupt());
+        // ライン0のHBlankに入ってもLY=LYCで信号線は1のまま
+        ppu.tick(252);
+        assert!(!ppu.take_stat_interrupt());
+        // ライン1のOAMサーチで信号線が0に戻り、次のHBlankで再び立ち上がる
+        ppu.tick(204);
+        assert!(!ppu.take_stat_interrupt());
+        ppu.tick(252);
+        assert!(ppu.take_stat_interrupt());
+    }
+
+    // LCDを無効にするとLYは0のまま進まない
+    #[test]
+    fn test_lcd_disabled_freezes_ly() {
+        let mut ppu = Ppu::default();
+        ppu.set_lcdc(LCDC_LCD_ENABLE);
+        for _ in 0..3 * 456 / 4 {
+            ppu.tick(4);
+        }
+        assert_eq!(ppu.ly(), 3);
+
+        ppu.set_lcdc(0x00);
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.mode(), PpuMode::HBlank);
+        for _ in 0..3 * 456 / 4 {
+            assert!(!ppu.tick(4));
+        }
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.mode(), PpuMode::HBlank);
+    }
+
+    // LCDを無効にすると画面は白くなり、有効に戻すと描画が再開する
+    #[test]
+    fn test_lcd_reenable_resumes_rendering() {
+        let mut ppu = Ppu::default();
+        ppu.set_lcdc(LCDC_LCD_ENABLE | LCDC_TILE_DATA);
+        ppu.bgp = 0b11_10_01_00;
+        write_striped_tile(&mut ppu, 0x8010);
+        ppu.write_vram(0x9800, 0x01);
+        ppu.tick(252);
+        assert_eq!(ppu.framebuffer()[1], 1);
+
+        ppu.set_lcdc(LCDC_TILE_DATA);
+        assert!(ppu.framebuffer().iter().all(|&pixel| pixel == 0));
+
+        ppu.set_lcdc(LCDC_LCD_ENABLE | LCDC_TILE_DATA);
+        assert_eq!(ppu.mode(), PpuMode::OamSearch);
+        ppu.tick(252);
+        assert_eq!(ppu.framebuffer()[1], 1);
+    }
+
+    // スクロールした分だけずれて描画される
+    #[test]
+    fn test_render_background_scroll() {
+        let mut ppu = Ppu::default();
+        ppu.lcdc = LCDC_TILE_DATA;
+        ppu.bgp = 0b11_10_01_00;
+        write_striped_tile(&mut ppu, 0x8010);
+        // マップの(1, 1)のタイル
+        ppu.write_vram(0x9800 + 32 + 1, 0x01);
+        ppu.scx = 10;
+        ppu.scy = 8;
+
+        ppu.render_scanline(0);
+        // 背景のx=10,11,12はタイル内のx=2,3,4
+        assert_eq!(&ppu.framebuffer()[0..3], &[2, 3, 0]);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
 #[derive(Default)]
 pub struct Registers {
     pub a: u8,
@@ -26,6 +28,26 @@ impl Registers {
         self.c = (value & 0xFF) as u8;
     }
 
+    pub fn get_de(&self) -> u16 {
+        (self.d as u16) << 8 | self.e as u16
+    }
+
+    pub fn set_de(&mut self, value: u16) {
+        self.d = ((value & 0xFF00) >> 8) as u8;
+        self.e = (value & 0xFF) as u8;
+    }
+
+    pub fn get_af(&self) -> u16 {
+        // Fレジスタの下位4ビットは常に0なので、u8への変換で自然に0になる
+        (self.a as u16) << 8 | u8::from(self.f) as u16
+    }
+
+    pub fn set_af(&mut self, value: u16) {
+        // 下位バイトはFlagsRegister::fromを通すので、下位4ビットは捨てられる
+        self.a = ((value & 0xFF00) >> 8) as u8;
+        self.f = FlagsRegister::from((value & 0xFF) as u8);
+    }
+
     pub fn get_hl(&self) -> u16 {
         (self.h as u16) << 8 | self.l as u16
     }
@@ -36,7 +58,7 @@ impl Registers {
     }
 }
 
-#[derive(Default, PartialEq, Debug)]
+#[derive(Default, PartialEq, Debug, Clone, Copy)]
 pub struct FlagsRegister {
     pub zero: bool,
     pub subtract: bool,
@@ -74,6 +96,38 @@ impl std::convert::From<u8> for FlagsRegister {
     }
 }
 
+// 立っているフラグを「ZNHC」の文字で、立っていないフラグを「-」で表す（例: Z--C）
+impl std::fmt::Display for FlagsRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let flag = |set: bool, name: char| if set { name } else { '-' };
+        write!(
+            f,
+            "{}{}{}{}",
+            flag(self.zero, 'Z'),
+            flag(self.subtract, 'N'),
+            flag(self.half_carry, 'H'),
+            flag(self.carry, 'C')
+        )
+    }
+}
+
+impl Snapshot for Registers {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_u16(self.get_af());
+        writer.write_u16(self.get_bc());
+        writer.write_u16(self.get_de());
+        writer.write_u16(self.get_hl());
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.set_af(reader.read_u16()?);
+        self.set_bc(reader.read_u16()?);
+        self.set_de(reader.read_u16()?);
+        self.set_hl(reader.read_u16()?);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,6 +148,56 @@ mod tests {
         assert_eq!(registers.c, 0x3C);
     }
 
+    #[test]
+    fn test_get_de() {
+        let mut registers = Registers::default();
+        registers.d = 0x1A;
+        registers.e = 0x3C;
+        assert_eq!(registers.get_de(), 0x1A3C);
+    }
+
+    #[test]
+    fn test_set_de() {
+        let mut registers = Registers::default();
+        registers.set_de(0x1A3C);
+        assert_eq!(registers.d, 0x1A);
+        assert_eq!(registers.e, 0x3C);
+    }
+
+    #[test]
+    fn test_get_af() {
+        let mut registers = Registers::default();
+        registers.a = 0x12;
+        registers.f = FlagsRegister {
+            zero: true,
+            subtract: true,
+            half_carry: true,
+            carry: true,
+        };
+        assert_eq!(registers.get_af(), 0x12F0);
+    }
+
+    #[test]
+    fn test_set_af_round_trip() {
+        let mut registers = Registers::default();
+        registers.set_af(0x12F0);
+        assert_eq!(registers.a, 0x12);
+        assert!(registers.f.zero);
+        assert!(registers.f.subtract);
+        assert!(registers.f.half_carry);
+        assert!(registers.f.carry);
+        assert_eq!(registers.get_af(), 0x12F0);
+    }
+
+    // Fレジスタの下位4ビットは無視されるので、往復すると0になる
+    #[test]
+    fn test_set_af_masks_low_nibble() {
+        let mut registers = Registers::default();
+        registers.set_af(0x34AF);
+        assert_eq!(registers.a, 0x34);
+        assert_eq!(registers.get_af(), 0x34A0);
+    }
+
     #[test]
     fn test_get_hl() {
         let mut registers = Registers::default();
@@ -134,4 +238,14 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_flags_display() {
+        let mut flags = FlagsRegister::default();
+        assert_eq!(flags.to_string(), "----");
+        flags.zero = true;
+        flags.carry = true;
+        assert_eq!(flags.to_string(), "Z--C");
+        assert_eq!(FlagsRegister::from(0xF0).to_string(), "ZNHC");
+    }
 }
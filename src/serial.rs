@@ -0,0 +1,83 @@
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// SCのビット7: 転送開始
+const TRANSFER_START: u8 = 1 << 7;
+// SCのビット0: 内部クロックを使う
+const INTERNAL_CLOCK: u8 = 1 << 0;
+
+// シリアル通信（SB: 0xFF01、SC: 0xFF02）
+// 接続相手はいないので、送信されたバイトを文字列として溜めておく
+// BlarggのテストROMはここに結果を出力する
+#[derive(Default)]
+pub struct Serial {
+    pub sb: u8,
+    sc: u8,
+    output: String,
+}
+
+impl Serial {
+    pub fn sc(&self) -> u8 {
+        // 使われていないビットは1が読める
+        self.sc | 0x7E
+    }
+
+    // SCへの書き込み
+    // 内部クロックで転送を開始したらすぐに送信が終わったものとして扱い、
+    // シリアル割り込みを要求するためにtrueを返す
+    pub fn set_sc(&mut self, value: u8) -> bool {
+        self.sc = value & (TRANSFER_START | INTERNAL_CLOCK);
+        if value & (TRANSFER_START | INTERNAL_CLOCK) != TRANSFER_START | INTERNAL_CLOCK {
+            return false;
+        }
+        self.output.push(self.sb as char);
+        // 受信するバイトは相手がいないので0xFF
+        self.sb = 0xFF;
+        self.sc &= !TRANSFER_START;
+        true
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+}
+
+impl Snapshot for Serial {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_u8(self.sb);
+        writer.write_u8(self.sc);
+        writer.write_sized_bytes(self.output.as_bytes());
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.sb = reader.read_u8()?;
+        self.sc = reader.read_u8()?;
+        // 書き出したのはStringのバイト列なので、壊れていなければそのまま戻る
+        self.output = String::from_utf8_lossy(&reader.read_sized_bytes()?).into_owned();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_captures_byte() {
+        let mut serial = Serial::default();
+        serial.sb = b'O';
+        assert!(serial.set_sc(0x81));
+        assert_eq!(serial.output(), "O");
+        // 転送が終わると開始ビットは0に戻る
+        assert_eq!(serial.sc(), 0x7F);
+    }
+
+    // 外部クロックでは相手がいないので転送は終わらない
+    #[test]
+    fn test_external_clock_does_not_transfer() {
+        let mut serial = Serial::default();
+        serial.sb = b'X';
+        assert!(!serial.set_sc(0x80));
+        assert_eq!(serial.output(), "");
+        assert_eq!(serial.sc(), 0xFE);
+    }
+}
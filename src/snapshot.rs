@@ -0,0 +1,207 @@
+// セーブステート（CPUとすべての周辺機器の状態）のバイナリ形式
+// 先頭にマジックナンバーとバージョンを置き、その後に各部品の状態を決まった順に並べる
+// 数値はリトルエンディアン、可変長のデータは長さ（u32）を前に付ける
+// カートリッジのROMそのものは含まないので、同じカートリッジを読み込んだ状態に戻す前提
+
+const MAGIC: &[u8; 4] = b"GBSS";
+// 形式を変えたら上げる（古いスナップショットは読み込めなくなる）
+const VERSION: u8 = 5;
+
+// スナップショットの読み込みに失敗した理由
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    // 先頭がマジックナンバーではない
+    InvalidMagic,
+    // 対応していないバージョン
+    UnsupportedVersion(u8),
+    // データが途中で終わっている
+    UnexpectedEnd,
+    // 読み込まれているカートリッジとMBCの種類や外部RAMの大きさが違う
+    CartridgeMismatch,
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SnapshotError::InvalidMagic => write!(f, "Not a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported snapshot version: {}", version)
+            }
+            SnapshotError::UnexpectedEnd => write!(f, "Snapshot is truncated"),
+            SnapshotError::CartridgeMismatch => {
+                write!(f, "Snapshot was taken with a different cartridge")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+// 状態をスナップショットに書き出し、読み戻せる部品
+pub trait Snapshot {
+    fn save_state(&self, writer: &mut SnapshotWriter);
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError>;
+}
+
+pub struct SnapshotWriter {
+    data: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    // マジックナンバーとバージョンを書き込んだ状態で始める
+    pub fn new() -> SnapshotWriter {
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        SnapshotWriter { data }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // 大きさが決まっている配列（VRAMなど）
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    // 大きさが変わるデータ（外部RAMなど）は長さを前に付ける
+    pub fn write_sized_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct SnapshotReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    // マジックナンバーとバージョンを確認し、その直後から読み始める
+    pub fn new(data: &'a [u8]) -> Result<SnapshotReader<'a>, SnapshotError> {
+        if !data.starts_with(MAGIC) {
+            return Err(SnapshotError::InvalidMagic);
+        }
+        let mut reader = SnapshotReader {
+            data,
+            position: MAGIC.len(),
+        };
+        match reader.read_u8()? {
+            VERSION => Ok(reader),
+            version => Err(SnapshotError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(SnapshotError::UnexpectedEnd)?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SnapshotError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<(), SnapshotError> {
+        bytes.copy_from_slice(self.take(bytes.len())?);
+        Ok(())
+    }
+
+    pub fn read_sized_bytes(&mut self) -> Result<Vec<u8>, SnapshotError> {
+        let length = self.read_u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_values() {
+        let mut writer = SnapshotWriter::new();
+        writer.write_u8(0x12);
+        writer.write_bool(true);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789A_BCDE);
+        writer.write_u64(0x0123_4567_89AB_CDEF);
+        writer.write_sized_bytes(&[1, 2, 3]);
+        let data = writer.finish();
+
+        let mut reader = SnapshotReader::new(&data).unwrap();
+        assert_eq!(reader.read_u8(), Ok(0x12));
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u16(), Ok(0x3456));
+        assert_eq!(reader.read_u32(), Ok(0x789A_BCDE));
+        assert_eq!(reader.read_u64(), Ok(0x0123_4567_89AB_CDEF));
+        assert_eq!(reader.read_sized_bytes(), Ok(vec![1, 2, 3]));
+        assert_eq!(reader.read_u8(), Err(SnapshotError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_rejects_invalid_header() {
+        assert_eq!(
+            SnapshotReader::new(b"NOPE\x01").err(),
+            Some(SnapshotError::InvalidMagic)
+        );
+        assert_eq!(
+            SnapshotReader::new(b"GBSS\x63").err(),
+            Some(SnapshotError::UnsupportedVersion(0x63))
+        );
+        assert_eq!(
+            SnapshotReader::new(b"GBSS").err(),
+            Some(SnapshotError::UnexpectedEnd)
+        );
+    }
+
+    // 長さが実際のデータより大きくても範囲外を読まない
+    #[test]
+    fn test_sized_bytes_out_of_range() {
+        let mut writer = SnapshotWriter::new();
+        writer.write_u32(u32::MAX);
+        let data = writer.finish();
+        let mut reader = SnapshotReader::new(&data).unwrap();
+        assert_eq!(reader.read_sized_bytes(), Err(SnapshotError::UnexpectedEnd));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// タイマー
+// 内部の16ビットカウンタはTサイクルごとに1ずつ増え、その上位8ビットがDIV（0xFF04）として見える
+// そのためDIVは256Tサイクルごと（16384Hz）に1ずつ増える
+#[derive(Default)]
+pub struct Timer {
+    counter: u16,
+    // TIMA（0xFF05）: TACで選んだ周期で増えるカウンタ
+    pub tima: u8,
+    // TMA（0xFF06）: TIMAがオーバーフローしたときに読み込まれる値
+    pub tma: u8,
+    // TAC（0xFF07）: ビット2が有効フラグ、ビット0〜1が周期の選択
+    tac: u8,
+}
+
+impl Timer {
+    // 命令の実行で消費したTサイクル数だけ進める
+    // TIMAがオーバーフローしてタイマー割り込みを要求する場合はtrueを返す
+    pub fn tick(&mut self, cycles: u8) -> bool {
+        let mut interrupt = false;
+        for _ in 0..cycles {
+            let previous = self.counter;
+            self.counter = self.counter.wrapping_add(1);
+            // 選んだビットが1から0に変わったときにTIMAが増える
+            if self.enabled()
+                && previous & self.selected_bit() != 0
+                && self.counter & self.selected_bit() == 0
+            {
+                interrupt |= self.increment_tima();
+            }
+        }
+        interrupt
+    }
+
+    fn increment_tima(&mut self) -> bool {
+        let (value, overflow) = self.tima.overflowing_add(1);
+        // オーバーフローしたらTMAの値を読み込む
+        self.tima = if overflow { self.tma } else { value };
+        overflow
+    }
+
+    fn enabled(&self) -> bool {
+        self.tac & 0x04 != 0
+    }
+
+    // TIMAを増やすタイミングを決める内部カウンタのビット
+    // 00: 4096Hz（1024Tサイクル）、01: 262144Hz（16Tサイクル）
+    // 10: 65536Hz（64Tサイクル）、11: 16384Hz（256Tサイクル）
+    fn selected_bit(&self) -> u16 {
+        match self.tac & 0x03 {
+            0b00 => 1 << 9,
+            0b01 => 1 << 3,
+            0b10 => 1 << 5,
+            _ => 1 << 7,
+        }
+    }
+
+    // 使われていない上位ビットは1が読める
+    pub fn tac(&self) -> u8 {
+        self.tac | 0xF8
+    }
+
+    pub fn set_tac(&mut self, value: u8) {
+        self.tac = value & 0x07;
+    }
+
+    pub fn div(&self) -> u8 {
+        (self.counter >> 8) as u8
+    }
+
+    // DIVへの書き込みは内部カウンタ全体を0にする
+    pub fn reset_div(&mut self) {
+        self.counter = 0;
+    }
+}
+
+impl Snapshot for Timer {
+    fn save_state(&self, writer: &mut SnapshotWriter) {
+        writer.write_u16(self.counter);
+        writer.write_u8(self.tima);
+        writer.write_u8(self.tma);
+        writer.write_u8(self.tac);
+    }
+
+    fn load_state(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.counter = reader.read_u16()?;
+        self.tima = reader.read_u8()?;
+        self.tma = reader.read_u8()?;
+        self.tac = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_div_increments_every_256_cycles() {
+        let mut timer = Timer::default();
+        for _ in 0..63 {
+            timer.tick(4);
+        }
+        assert_eq!(timer.div(), 0);
+        timer.tick(4);
+        assert_eq!(timer.div(), 1);
+        for _ in 0..64 {
+            timer.tick(4);
+        }
+        assert_eq!(timer.div(), 2);
+    }
+
+    #[test]
+    fn test_reset_div_clears_counter() {
+        let mut timer = Timer::default();
+        for _ in 0..100 {
+            timer.tick(4);
+        }
+        timer.reset_div();
+        assert_eq!(timer.div(), 0);
+        // 下位ビットも0になるので、次に増えるのは256Tサイクル後
+        timer.tick(252);
+        assert_eq!(timer.div(), 0);
+        timer.tick(4);
+        assert_eq!(timer.div(), 1);
+    }
+
+    // TACが無効な間はTIMAは増えない
+    #[test]
+    fn test_tima_disabled() {
+        let mut timer = Timer::default();
+        timer.set_tac(0x01);
+        for _ in 0..100 {
+            timer.tick(16);
+        }
+        assert_eq!(timer.tima, 0);
+    }
+
+    // 262144Hzでは16Tサイクルごとに1増える
+    #[test]
+    fn test_tima_increments_at_selected_rate() {
+        let mut timer = Timer::default();
+        timer.set_tac(0x05);
+        timer.tick(15);
+        assert_eq!(timer.tima, 0);
+        timer.tick(1);
+        assert_eq!(timer.tima, 1);
+        timer.tick(32);
+        assert_eq!(timer.tima, 3);
+
+        // 4096Hzでは1024Tサイクルごと
+        let mut timer = Timer::default();
+        timer.set_tac(0x04);
+        for _ in 0..4 {
+            timer.tick(255);
+        }
+        assert_eq!(timer.tima, 0);
+        timer.tick(4);
+        assert_eq!(timer.tima, 1);
+    }
+
+    // オーバーフローするとTMAを読み込み、割り込みを要求する
+    #[test]
+    fn test_tima_overflow_reloads_tma() {
+        let mut timer = Timer::default();
+        timer.set_tac(0x05);
+        timer.tma = 0xAB;
+        timer.tima = 0xFE;
+        assert!(!timer.tick(16));
+        assert_eq!(timer.tima, 0xFF);
+        assert!(timer.tick(16));
+        assert_eq!(timer.tima, 0xAB);
+    }
+}
@@ -0,0 +1,114 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// ウォッチポイントで監視するアクセスの種類
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Both,
+}
+
+impl WatchKind {
+    // accessはReadかWriteのどちらか
+    fn matches(&self, access: WatchKind) -> bool {
+        *self == WatchKind::Both || *self == access
+    }
+}
+
+// 監視しているアドレスへのアクセスの記録
+#[derive(Debug, PartialEq)]
+pub struct WatchEvent {
+    // アクセスした命令のアドレス
+    pub pc: u16,
+    pub address: u16,
+    // 読み込んだ値、または書き込んだ値
+    pub value: u8,
+    // ReadかWriteのどちらか
+    pub kind: WatchKind,
+}
+
+// デバッガ用のメモリのウォッチポイント
+// 読み込みは&selfで行われるので、記録はRefCellに溜める
+#[derive(Default)]
+pub struct Watchpoints {
+    watches: HashMap<u16, WatchKind>,
+    events: RefCell<Vec<WatchEvent>>,
+    // 実行中の命令のアドレス（CPUが命令ごとに設定する）
+    pub pc: u16,
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, address: u16, kind: WatchKind) {
+        self.watches.insert(address, kind);
+    }
+
+    pub fn remove(&mut self, address: u16) {
+        self.watches.remove(&address);
+    }
+
+    // 監視しているアドレスへのアクセスなら記録する
+    pub fn record(&self, address: u16, value: u8, access: WatchKind) {
+        if self.watches.is_empty() {
+            return;
+        }
+        if self
+            .watches
+            .get(&address)
+            .is_some_and(|kind| kind.matches(access))
+        {
+            self.events.borrow_mut().push(WatchEvent {
+                pc: self.pc,
+                address,
+                value,
+                kind: access,
+            });
+        }
+    }
+
+    // 溜まった記録を取り出して空にする
+    pub fn take_events(&mut self) -> Vec<WatchEvent> {
+        std::mem::take(self.events.get_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_matching_access() {
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.add(0xC000, WatchKind::Write);
+        watchpoints.pc = 0x0150;
+        watchpoints.record(0xC000, 0x12, WatchKind::Read);
+        watchpoints.record(0xC001, 0x34, WatchKind::Write);
+        watchpoints.record(0xC000, 0x56, WatchKind::Write);
+        assert_eq!(
+            watchpoints.take_events(),
+            vec![WatchEvent {
+                pc: 0x0150,
+                address: 0xC000,
+                value: 0x56,
+                kind: WatchKind::Write,
+            }]
+        );
+        assert!(watchpoints.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_both_records_reads_and_writes() {
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.add(0xFF80, WatchKind::Both);
+        watchpoints.record(0xFF80, 0x01, WatchKind::Read);
+        watchpoints.record(0xFF80, 0x02, WatchKind::Write);
+        watchpoints.remove(0xFF80);
+        watchpoints.record(0xFF80, 0x03, WatchKind::Write);
+        let kinds: Vec<WatchKind> = watchpoints
+            .take_events()
+            .iter()
+            .map(|event| event.kind)
+            .collect();
+        assert_eq!(kinds, vec![WatchKind::Read, WatchKind::Write]);
+    }
+}